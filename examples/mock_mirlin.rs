@@ -0,0 +1,209 @@
+//! A stand-in for the mirlin feature extraction server, for testing the audio features
+//! protocol offline. Computes a few cheap features from the received frames and fills in
+//! plausible values for the rest.
+//!
+//! ```text
+//! cargo run --example mock_mirlin -- 127.0.0.1:9002
+//! ```
+
+use serde_json::{json, Map, Value};
+use std::collections::VecDeque;
+use std::thread;
+use websocket::sync::Server;
+use websocket::OwnedMessage;
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:9002";
+const NUM_MFCCS: usize = 13;
+
+/// Session state for one client.
+struct Session {
+    features: Vec<String>,
+    sample_rate: f32,
+    memory: usize,
+    history: VecDeque<Frame>,
+}
+
+/// Per frame measurements averaged over the session memory.
+#[derive(Clone, Copy)]
+struct Frame {
+    rms: f32,
+    zero_crossing_rate: f32,
+}
+
+impl Session {
+    fn new(request: &Value) -> Self {
+        let payload = &request["payload"];
+        let features = payload["features"]
+            .as_array()
+            .map(|f| {
+                f.iter()
+                    .filter_map(|v| v.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            features,
+            sample_rate: payload["sample_rate"].as_f64().unwrap_or(44100.0) as f32,
+            memory: payload["memory"].as_u64().unwrap_or(4).max(1) as usize,
+            history: VecDeque::new(),
+        }
+    }
+
+    fn wants(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+
+    fn process(&mut self, samples: &[f32]) -> Value {
+        let n = samples.len().max(1) as f32;
+        let rms = (samples.iter().map(|s| s * s).sum::<f32>() / n).sqrt();
+        let crossings = samples
+            .windows(2)
+            .filter(|w| (w[0] >= 0.0) != (w[1] >= 0.0))
+            .count();
+        let zero_crossing_rate = crossings as f32 / n;
+
+        let previous_rms = self.history.back().map(|f| f.rms).unwrap_or(0.0);
+        self.history.push_back(Frame {
+            rms,
+            zero_crossing_rate,
+        });
+        while self.history.len() > self.memory {
+            self.history.pop_front();
+        }
+
+        let count = self.history.len() as f32;
+        let mean_rms = self.history.iter().map(|f| f.rms).sum::<f32>() / count;
+        let mean_zcr = self
+            .history
+            .iter()
+            .map(|f| f.zero_crossing_rate)
+            .sum::<f32>()
+            / count;
+
+        let mut features = Map::new();
+        let mut add = |name: &str, key: &str, value: Value| {
+            if self.wants(name) {
+                features.insert(String::from(key), value);
+            }
+        };
+
+        add("rms", "rms.mean", json!([mean_rms]));
+        add("energy", "energy.mean", json!([mean_rms * mean_rms * n]));
+        add("loudness", "loudness.mean", json!([mean_rms.powf(0.67)]));
+        add("noisiness", "noisiness.mean", json!([mean_zcr]));
+        add(
+            "pitch",
+            "f0.mean",
+            json!([mean_zcr * self.sample_rate * 0.5]),
+        );
+        add("centroid", "centroid.mean", json!([mean_zcr]));
+        add(
+            "onset",
+            "onset",
+            json!([if rms > previous_rms * 1.5 + 0.01 {
+                1.0
+            } else {
+                0.0
+            }]),
+        );
+        add("dissonance", "dissonance.mean", json!([0.5]));
+        add(
+            "spectral_complexity",
+            "spectral_complexity.mean",
+            json!([mean_zcr * 10.0]),
+        );
+        add(
+            "spectral_contrast",
+            "spectral_contrast.mean",
+            json!([-mean_zcr]),
+        );
+        add("tristimulus", "tristimulus.mean", json!([0.5, 0.3, 0.2]));
+        add(
+            "mfcc",
+            "mfcc.mean",
+            json!((0..NUM_MFCCS)
+                .map(|i| (mean_rms + 1e-6).ln() / (i + 1) as f32)
+                .collect::<Vec<f32>>()),
+        );
+        add("key", "key", json!(["A", "minor", 0.5]));
+
+        json!({
+            "type": "features",
+            "payload": { "features": features },
+        })
+    }
+}
+
+fn main() {
+    let address = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| String::from(DEFAULT_ADDRESS));
+    let server = Server::bind(&address).expect("unable to bind address");
+    println!("mock mirlin listening on ws://{}", address);
+
+    for request in server.filter_map(Result::ok) {
+        thread::spawn(move || {
+            let client = match request.use_protocol("rust-websocket").accept() {
+                Ok(c) => c,
+                Err((_, e)) => {
+                    println!("rejected connection: {:?}", e);
+                    return;
+                }
+            };
+
+            let (mut receiver, mut sender) = client.split().unwrap();
+            let mut session: Option<Session> = None;
+
+            for message in receiver.incoming_messages() {
+                let text = match message {
+                    Ok(OwnedMessage::Text(t)) => t,
+                    Ok(OwnedMessage::Close(_)) | Err(_) => break,
+                    Ok(_) => continue,
+                };
+
+                let value: Value = match serde_json::from_str(&text) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        println!("invalid message: {:?}", e);
+                        continue;
+                    }
+                };
+
+                let response = match value["type"].as_str() {
+                    Some("session_request") => {
+                        let s = Session::new(&value);
+                        println!("session started with features {:?}", s.features);
+                        session = Some(s);
+                        json!({ "type": "session_confirmation" })
+                    }
+                    Some("audio_frame") => match session.as_mut() {
+                        Some(s) => {
+                            let samples = value["payload"]
+                                .as_array()
+                                .map(|a| {
+                                    a.iter()
+                                        .filter_map(|v| v.as_f64())
+                                        .map(|v| v as f32)
+                                        .collect::<Vec<f32>>()
+                                })
+                                .unwrap_or_default();
+                            s.process(&samples)
+                        }
+                        None => continue,
+                    },
+                    _ => continue,
+                };
+
+                if sender
+                    .send_message(&OwnedMessage::Text(response.to_string()))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+
+            println!("session ended");
+        });
+    }
+}
@@ -0,0 +1,105 @@
+//! Compares the CPU cost of preparing a 1080p video frame for upload: the old path that
+//! mirrors and converts every pixel to 16-bit floats, and the current one that converts to
+//! 8-bit BGRA and leaves the swizzle and mirroring to the GPU.
+//!
+//! ```text
+//! cargo run --release --example upload_benchmark -- 100
+//! ```
+
+use nannou::image;
+use opencv::prelude::*;
+use std::time::Instant;
+
+const WIDTH: i32 = 1920;
+const HEIGHT: i32 = 1080;
+const DEFAULT_ITERATIONS: usize = 60;
+
+fn float_as_bytes(data: &f32) -> [u8; 2] {
+    half::f16::from_f32(*data).to_ne_bytes()
+}
+
+fn floats_as_byte_vec(data: &[f32]) -> Vec<u8> {
+    let mut bytes = vec![];
+    data.iter()
+        .for_each(|f| bytes.extend(float_as_bytes(f).iter()));
+    bytes
+}
+
+/// A frame with some variation so nothing can be skipped.
+fn synthetic_frame() -> opencv::Result<opencv::core::Mat> {
+    let mut frame = opencv::core::Mat::new_rows_cols_with_default(
+        HEIGHT,
+        WIDTH,
+        opencv::core::CV_8UC3,
+        opencv::core::Scalar::all(0.0),
+    )?;
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let pixel = frame.at_2d_mut::<opencv::core::Vec3b>(y, x)?;
+            *pixel = opencv::core::Vec3b::from([x as u8, y as u8, (x ^ y) as u8]);
+        }
+    }
+    Ok(frame)
+}
+
+fn old_path(frame: &opencv::core::Mat) -> Vec<u8> {
+    let frame_data: Vec<Vec<opencv::core::Vec3b>> = frame.to_vec_2d().unwrap();
+    let width = WIDTH as u32;
+    let height = HEIGHT as u32;
+
+    let image = image::ImageBuffer::from_fn(width, height, |x, y| {
+        let pixel = frame_data[y as usize][(width - x - 1) as usize];
+        // convert from BGR to RGB
+        image::Rgba([
+            pixel[2] as f32 / 255.0,
+            pixel[1] as f32 / 255.0,
+            pixel[0] as f32 / 255.0,
+            1.0,
+        ])
+    });
+
+    let flat_samples = image.as_flat_samples();
+    floats_as_byte_vec(flat_samples.as_slice())
+}
+
+fn new_path(frame: &opencv::core::Mat, bgra: &mut opencv::core::Mat, bytes: &mut Vec<u8>) {
+    opencv::imgproc::cvt_color(frame, bgra, opencv::imgproc::COLOR_BGR2BGRA, 0).unwrap();
+    bytes.clear();
+    bytes.extend_from_slice(bgra.data_bytes().unwrap());
+}
+
+fn main() -> opencv::Result<()> {
+    let iterations = std::env::args()
+        .nth(1)
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(DEFAULT_ITERATIONS);
+
+    let frame = synthetic_frame()?;
+
+    let start = Instant::now();
+    let mut size = 0;
+    for _ in 0..iterations {
+        size = old_path(&frame).len();
+    }
+    let old_ms = start.elapsed().as_secs_f64() * 1000.0 / iterations as f64;
+    println!(
+        "f16 RGBA on the CPU: {:.2} ms/frame, {} bytes",
+        old_ms, size
+    );
+
+    let mut bgra = opencv::core::Mat::default();
+    let mut bytes = vec![];
+    let start = Instant::now();
+    for _ in 0..iterations {
+        new_path(&frame, &mut bgra, &mut bytes);
+    }
+    let new_ms = start.elapsed().as_secs_f64() * 1000.0 / iterations as f64;
+    println!(
+        "8-bit BGRA:          {:.2} ms/frame, {} bytes",
+        new_ms,
+        bytes.len()
+    );
+
+    println!("speedup: {:.1}x", old_ms / new_ms);
+    Ok(())
+}
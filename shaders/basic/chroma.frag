@@ -0,0 +1,49 @@
+#version 450
+
+layout(location = 0) in vec2 uv;
+layout(location = 0) out vec4 frag_color;
+
+layout(set = 0, binding = 0) uniform GeneralUniforms {
+    vec2 mouse;
+    vec2 resolution;
+    float time;
+    int mouse_down;
+};
+
+layout(set = 1, binding = 0) uniform sampler spectrum_sampler;
+layout(set = 1, binding = 1) uniform texture2D spectrum;
+layout(set = 1, binding = 2) uniform texture2D spectrum_history;
+layout(set = 1, binding = 3) uniform texture2D chroma;
+layout(set = 1, binding = 4) uniform AudioFftUniforms {
+    int history_row;
+    int history_size;
+    int pitch_class;
+    int key;
+    int key_scale;
+    float key_strength;
+};
+
+//@import util/hsv2rgb
+//@import util/palette
+
+vec3 hsv2rgb(vec3 c);
+float pitch_class_hue(in int pitchClass);
+vec3 key_palette(in float t, in int key, in int keyScale);
+
+void main() {
+    vec2 st = uv * 0.5 + 0.5;
+
+    // background follows the key, more saturated the more confident the estimate
+    vec3 background = key_palette(st.y * 0.5 + time * 0.02, key, key_scale);
+    vec3 color = mix(vec3(0.1), background, clamp(key_strength, 0.0, 1.0));
+
+    // one bar per pitch class
+    int bar = int(st.x * 12.0);
+    float level = texture(sampler2D(chroma, spectrum_sampler), vec2((float(bar) + 0.5) / 12.0, 0.5)).x;
+    if (st.y < level * 0.8) {
+        float brightness = bar == pitch_class ? 1.0 : 0.6;
+        color = hsv2rgb(vec3(pitch_class_hue(bar), 0.8, brightness));
+    }
+
+    frag_color = vec4(color, 1.0);
+}
@@ -10,33 +10,48 @@ layout(set = 0, binding = 0) uniform GeneralUniforms {
     int mouse_down;
 };
 
-layout(set = 1, binding = 0) uniform sampler image_sampler;
-layout(set = 1, binding = 1) uniform texture2D image1;
-layout(set = 1, binding = 2) uniform texture2D image2;
-layout(set = 1, binding = 2) uniform ImageUniforms {
-    vec2 image1_size;
-    vec2 image2_size;
+layout(set = 1, binding = 0) uniform sampler video_sampler;
+layout(set = 1, binding = 1) uniform texture2D video;
+layout(set = 1, binding = 2) uniform VideoUniforms {
+    vec2 video_size;
+    float video_time;
+    float video_progress;
 };
 
+layout(set = 2, binding = 0) uniform sampler flow_sampler;
+layout(set = 2, binding = 1) uniform texture2D video_flow;
+layout(set = 2, binding = 2) uniform VideoFlowUniforms {
+    vec2 flow_motion;
+    float flow_magnitude;
+};
+
+// ripples the video like a liquid, the more it moves the stronger the waves,
+// and drags each pixel along its own motion
+
 #define PI 3.14159265359
 
-void main() {
-    vec2 st = uv * 0.5 + 0.5;;
-    st.y *= resolution.y / resolution.x;
-    st *= 2.0;
+vec2 flow(in vec2 coord) {
+    return texture(sampler2D(video_flow, flow_sampler), clamp(coord, 0.0, 1.0)).xy;
+}
 
-    vec3 color = vec3(0.0);
+void main() {
+    vec2 st = uv * 0.5 + 0.5;
 
+    float amount = clamp(flow_magnitude * 40.0, 0.0, 1.0);
     float t1 = time * 1.13;
     float t2 = time * 0.9;
 
+    vec2 p = st;
     for (float i = 1.0; i < 4.0; i += 1.0) {
-        vec2 p = st;
-        p.x += 0.75 / i * sin(i * PI * st.y + t1 * 0.1);
-        p.y += 0.35 / i * cos(i * PI * st.x + t2);
-        st = p;
+        vec2 q = p;
+        q.x += amount * 0.05 / i * sin(i * PI * p.y * 4.0 + t1);
+        q.y += amount * 0.05 / i * cos(i * PI * p.x * 4.0 + t2);
+        p = q;
     }
 
-    color = texture(sampler2D(image1, image_sampler), fract(st)).xyz;
+    // moving parts are smeared back along their motion
+    p -= flow(st) * 6.0;
+
+    vec3 color = texture(sampler2D(video, video_sampler), fract(p)).xyz;
     frag_color = vec4(color, 1);
 }
@@ -0,0 +1,39 @@
+/*{
+    "DESCRIPTION": "Pulses with the mirlin audio features",
+    "CREDIT": "",
+    "ISFVSN": "2",
+    "CATEGORIES": [
+        "Generator"
+    ],
+    "AUDIO_FEATURES": true,
+    "INPUTS": [
+        {
+            "NAME": "radius",
+            "TYPE": "float",
+            "MIN": 0.0,
+            "MAX": 1.0,
+            "DEFAULT": 0.3
+        }
+    ]
+}*/
+
+void main() {
+    vec2 loc = isf_FragNormCoord;
+    vec2 st = loc * 2.0 - 1.0;
+    st.x *= RENDERSIZE.x / RENDERSIZE.y;
+
+    float r = radius * (1.0 + AUDIO_FEATURES.loudness + AUDIO_FEATURES.onset * 0.5);
+    float circle = 1.0 - smoothstep(r, r + 0.01, length(st));
+
+    vec3 color = vec3(
+        AUDIO_FEATURES.tristimulus1,
+        AUDIO_FEATURES.tristimulus2,
+        AUDIO_FEATURES.tristimulus3
+    );
+
+    // MFCCs along the bottom
+    float mfcc = IMG_NORM_PIXEL(AUDIO_MFCC, vec2(loc.x, 0.5)).r;
+    float bars = step(loc.y, 0.1) * clamp(abs(mfcc) * 0.05, 0.0, 1.0);
+
+    gl_FragColor = vec4(color * circle + vec3(bars), 1.0);
+}
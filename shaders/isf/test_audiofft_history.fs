@@ -0,0 +1,35 @@
+/*{
+    "DESCRIPTION": "Scrolling spectrogram from the audioFFT history texture",
+    "CREDIT": "",
+    "ISFVSN": "2",
+    "CATEGORIES": [
+        "Generator"
+    ],
+    "INPUTS": [
+        {
+            "NAME": "fftImage",
+            "TYPE": "audioFFT",
+            "HISTORY": 128
+        },
+        {
+            "NAME": "gain",
+            "TYPE": "float",
+            "MIN": 1.0,
+            "MAX": 10.0,
+            "DEFAULT": 2.0
+        }
+    ]
+}*/
+
+void main() {
+    vec2 loc = isf_FragNormCoord;
+    ivec2 size = IMG_SIZE(fftImage);
+
+    // newest spectrum at the bottom, oldest at the top
+    float age = floor(loc.y * float(size.y));
+    float row = mod(float(fftImage_row) - age + float(size.y), float(size.y));
+    float y = (row + 0.5) / float(size.y);
+
+    float val = gain * IMG_NORM_PIXEL(fftImage, vec2(loc.x, 1.0 - y)).r;
+    gl_FragColor = vec4(vec3(val), 1.0);
+}
@@ -3,3 +3,16 @@
 vec3 palette(in float t, in vec3 a, in vec3 b, in vec3 c, in vec3 d) {
     return a + b * cos(6.28318 * (c * t + d));
 }
+
+// Hue of a pitch class (0 = C) on the circle of fifths, so related keys get neighbouring colors.
+float pitch_class_hue(in int pitchClass) {
+    return mod(float(pitchClass) * 7.0, 12.0) / 12.0;
+}
+
+// A palette following the key from the audio_fft uniforms, darker and cooler in minor keys.
+vec3 key_palette(in float t, in int key, in int keyScale) {
+    float hue = key < 0 ? 0.0 : pitch_class_hue(key);
+    vec3 brightness = keyScale == 1 ? vec3(0.35) : vec3(0.5);
+    vec3 phase = vec3(hue, hue + 0.1, hue + 0.2) + (keyScale == 1 ? vec3(0.1, 0.15, 0.0) : vec3(0.0));
+    return palette(t, brightness, vec3(0.5), vec3(1.0), phase);
+}
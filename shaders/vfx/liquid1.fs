@@ -1,16 +1,13 @@
 /*{
-    "DESCRIPTION": "Liquid domain distortion effect.",
+    "DESCRIPTION": "Liquid domain distortion effect, stirred by the motion in the image.",
     "CREDIT": "by julesyoungberg",
     "ISFVSN": "2.0",
     "CATEGORIES": [ "Distortion" ],
+    "FLOW": { "INPUT": "inputImage" },
     "INPUTS": [
         {
             "NAME": "inputImage",
             "TYPE": "image"
-        },
-        {
-            "NAME": "fft_texture",
-            "TYPE": "audioFFT"
         }
     ]
 }*/
@@ -19,21 +16,22 @@ vec3 image_color(in vec2 coord) {
     return IMG_NORM_PIXEL(inputImage, fract(coord)).rgb;
 }
 
-float get_spectrum(float i) {
-    return log(IMG_NORM_PIXEL(fft_texture, vec2(fract(i), 0)).x + 1.0);
+vec2 flow(in vec2 coord) {
+    return IMG_NORM_PIXEL(FLOW_IMAGE, clamp(coord, 0.0, 1.0)).xy;
 }
 
 void main() {
     vec2 st = isf_FragNormCoord;
 
-    float s1 = get_spectrum(0.3) * 2.0 + 0.1;
-    float s2 = get_spectrum(0.6) * 2.0 + 0.1;
+    // waves grow with the overall motion and bend along the local motion
+    float s1 = clamp(FLOW.magnitude * 20.0, 0.0, 1.0) * 0.2 + 0.01;
+    float s2 = length(flow(st)) * 40.0 + 0.1;
     float t = TIME;
 
     float shift = sin(st.y * 10.0 + t + cos(st.y * st.x + t)) * s1;
     shift *= sin(st.x * 15.0 + t * 1.387) * s2;
 
-    st += shift;
+    st += shift - flow(st) * 4.0;
 
     vec3 color = image_color(st);
 
@@ -0,0 +1,28 @@
+/*{
+    "DESCRIPTION": "Stop motion effect that advances on every other onset of the music.",
+    "CREDIT": "by julesyoungberg",
+    "ISFVSN": "2.0",
+    "CATEGORIES": [ "Blur" ],
+    "INPUTS": [
+        {
+            "NAME": "inputImage",
+            "TYPE": "image"
+        },
+        {
+            "NAME": "tap",
+            "TYPE": "event",
+            "TRIGGER": { "type": "every_nth_onset", "every": 2 }
+        }
+    ],
+    "PASSES": [
+        {
+            "TARGET": "prev_frame",
+            "PERSISTENT": true,
+            "FLOAT": true
+        }
+    ]
+}*/
+
+void main() {
+    gl_FragColor = tap ? IMG_THIS_PIXEL(inputImage) : IMG_THIS_PIXEL(prev_frame);
+}
@@ -17,6 +17,8 @@ layout(set = 2, binding = 0) uniform sampler video_sampler;
 layout(set = 2, binding = 1) uniform texture2D video;
 layout(set = 2, binding = 2) uniform VideoUniforms {
     vec2 video_size;
+    float video_time;
+    float video_progress;
 };
 
 layout(set = 3, binding = 0) uniform sampler multipass_sampler;
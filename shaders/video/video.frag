@@ -14,6 +14,8 @@ layout(set = 1, binding = 0) uniform sampler video_sampler;
 layout(set = 1, binding = 1) uniform texture2D video;
 layout(set = 1, binding = 2) uniform VideoUniforms {
     vec2 video_size;
+    float video_time;
+    float video_progress;
 };
 
 void main() {
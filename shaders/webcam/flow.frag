@@ -0,0 +1,65 @@
+#version 450
+
+layout(location = 0) in vec2 uv;
+layout(location = 0) out vec4 frag_color;
+
+layout(set = 0, binding = 0) uniform GeneralUniforms {
+    vec2 mouse;
+    vec2 resolution;
+    float time;
+    int mouse_down;
+};
+
+layout(set = 1, binding = 0) uniform sampler webcam_sampler;
+layout(set = 1, binding = 1) uniform texture2D webcam;
+layout(set = 1, binding = 2) uniform WebcamUniforms {
+    vec2 video_size;
+};
+
+layout(set = 2, binding = 0) uniform sampler flow_sampler;
+layout(set = 2, binding = 1) uniform texture2D webcam_flow;
+layout(set = 2, binding = 2) uniform WebcamFlowUniforms {
+    vec2 flow_motion;
+    float flow_magnitude;
+};
+
+// smears the webcam along the optical flow and colors moving parts by their direction
+
+#define PI 3.14159265359
+
+vec3 webcam_color(in vec2 coord) {
+    vec2 c = vec2(coord.x, 1.0 - coord.y);
+    return texture(sampler2D(webcam, webcam_sampler), fract(c)).rgb;
+}
+
+vec2 flow(in vec2 coord) {
+    vec2 c = vec2(coord.x, 1.0 - coord.y);
+    return texture(sampler2D(webcam_flow, flow_sampler), clamp(c, 0.0, 1.0)).xy;
+}
+
+vec3 hue(in float h) {
+    return clamp(abs(mod(h * 6.0 + vec3(0.0, 4.0, 2.0), 6.0) - 3.0) - 1.0, 0.0, 1.0);
+}
+
+void main() {
+    vec2 st = uv * 0.5 + 0.5;
+
+    // follow the flow backwards to where the color came from
+    vec2 p = st;
+    vec3 color = vec3(0.0);
+    for (int i = 0; i < 8; i++) {
+        p -= flow(p) * 2.0;
+        color += webcam_color(p);
+    }
+    color /= 8.0;
+
+    // moving parts are tinted by their direction
+    vec2 f = flow(st);
+    float speed = smoothstep(0.002, 0.02, length(f));
+    color = mix(color, hue(atan(f.y, f.x) / (2.0 * PI) + 0.5), speed * 0.6);
+
+    // the whole image flashes with overall motion
+    color += flow_magnitude * 10.0;
+
+    frag_color = vec4(color, 1.0);
+}
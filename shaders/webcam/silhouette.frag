@@ -0,0 +1,62 @@
+#version 450
+
+layout(location = 0) in vec2 uv;
+layout(location = 0) out vec4 frag_color;
+
+layout(set = 0, binding = 0) uniform GeneralUniforms {
+    vec2 mouse;
+    vec2 resolution;
+    float time;
+    int mouse_down;
+};
+
+layout(set = 1, binding = 0) uniform sampler webcam_sampler;
+layout(set = 1, binding = 1) uniform texture2D webcam;
+layout(set = 1, binding = 2) uniform WebcamUniforms {
+    vec2 video_size;
+};
+
+layout(set = 2, binding = 0) uniform sampler mask_sampler;
+layout(set = 2, binding = 1) uniform texture2D webcam_mask;
+layout(set = 2, binding = 2) uniform WebcamMaskUniforms {
+    float motion_amount;
+};
+
+// people in front of the webcam become glowing silhouettes, the more they move the brighter
+
+vec3 webcam_color(in vec2 coord) {
+    vec2 c = vec2(coord.x, 1.0 - coord.y);
+    return texture(sampler2D(webcam, webcam_sampler), fract(c)).rgb;
+}
+
+float mask(in vec2 coord) {
+    vec2 c = vec2(coord.x, 1.0 - coord.y);
+    return texture(sampler2D(webcam_mask, mask_sampler), clamp(c, 0.0, 1.0)).r;
+}
+
+vec3 palette(in float t) {
+    return 0.5 + 0.5 * cos(6.28318 * (t + vec3(0.0, 0.33, 0.67)));
+}
+
+void main() {
+    vec2 st = uv * 0.5 + 0.5;
+
+    float m = mask(st);
+
+    // blurred mask for the glow around the silhouette
+    vec2 px = 1.0 / resolution;
+    float glow = 0.0;
+    for (int x = -3; x <= 3; x++) {
+        for (int y = -3; y <= 3; y++) {
+            glow += mask(st + vec2(x, y) * px * 6.0);
+        }
+    }
+    glow /= 49.0;
+
+    vec3 background = webcam_color(st) * 0.15;
+    vec3 silhouette = palette(st.y * 0.5 + time * 0.1);
+    vec3 color = mix(background, silhouette, m);
+    color += palette(time * 0.1 + 0.5) * glow * (1.0 - m) * (0.5 + motion_amount * 4.0);
+
+    frag_color = vec4(color, 1.0);
+}
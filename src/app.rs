@@ -23,11 +23,14 @@ widget_ids! {
         fog_dist,
         audio_features_folder,
         audio_fft_folder,
+        audio_input_folder,
         general_folder,
         geometry_folder,
         image_folder,
         noise_folder,
+        transform_folder,
         video_folder,
+        webcam_folder,
         color1_r,
         color1_g,
         color1_b,
@@ -66,8 +69,33 @@ widget_ids! {
         errors_wrapper,
         errors_title,
         errors_message,
-        audio_feature_smoothing,
-        audio_fft_smoothing,
+        audio_feature_attack,
+        audio_feature_release,
+        audio_feature_peak_hold,
+        audio_feature_auto_gain,
+        audio_feature_auto_gain_label,
+        audio_fft_attack,
+        audio_fft_release,
+        audio_fft_peak_hold,
+        audio_fft_auto_gain,
+        audio_fft_auto_gain_label,
+        audio_fft_size,
+        audio_fft_size_label,
+        audio_fft_window,
+        audio_fft_window_label,
+        audio_fft_overlap,
+        audio_fft_key,
+        audio_input_gain,
+        audio_input_agc,
+        audio_input_agc_label,
+        audio_input_target,
+        audio_input_latency,
+        audio_input_meter_label,
+        audio_input_peak,
+        audio_input_peak_background,
+        audio_input_clip,
+        audio_input_rms,
+        audio_input_rms_background,
         noise_lacunarity,
         noise_gain,
         noise_invert,
@@ -87,6 +115,49 @@ widget_ids! {
         video_label,
         video_load_button,
         video_speed,
+        video_sound,
+        video_sound_label,
+        video_analyze,
+        video_analyze_label,
+        video_position,
+        video_loop_in,
+        video_loop_out,
+        video_mode,
+        video_mode_label,
+        video_step_back,
+        video_step_forward,
+        video_sync,
+        video_sync_label,
+        background_relearn,
+        webcam_camera,
+        webcam_camera_label,
+        webcam_device,
+        webcam_device_label,
+        webcam_mode,
+        webcam_mode_label,
+        webcam_info,
+        transform_source,
+        transform_source_label,
+        transform_mirror,
+        transform_mirror_label,
+        transform_flip,
+        transform_flip_label,
+        transform_rotation,
+        transform_rotation_label,
+        transform_crop_label,
+        transform_crop_x,
+        transform_crop_y,
+        transform_crop_width,
+        transform_crop_height,
+        transform_brightness,
+        transform_contrast,
+        transform_saturation,
+        transform_gamma,
+        transform_premultiplied,
+        transform_premultiplied_label,
+        transform_premultiply,
+        transform_premultiply_label,
+        transform_reset,
         fps_container,
         fps,
         isf_inputs_title,
@@ -107,11 +178,18 @@ pub struct Model {
     pub ui: Ui,
     pub ui_show_audio_features: bool,
     pub ui_show_audio_fft: bool,
+    pub ui_show_audio_input: bool,
     pub ui_show_color: bool,
     pub ui_show_geometry: bool,
     pub ui_show_image: bool,
     pub ui_show_noise: bool,
+    pub ui_show_transform: bool,
     pub ui_show_video: bool,
+    pub ui_show_webcam: bool,
+    /// Camera shown in the webcam controls.
+    pub ui_webcam: usize,
+    /// Source shown in the transform controls.
+    pub ui_transform: usize,
     pub resized: bool,
     pub size: Vector2,
     pub vertex_buffer: wgpu::Buffer,
@@ -122,7 +200,6 @@ impl Model {
     pub fn encode_update(
         &mut self,
         app: &App,
-        update: Update,
         window: &Ref<'_, Window>,
         device: &wgpu::Device,
         num_samples: u32,
@@ -139,7 +216,7 @@ impl Model {
         }
 
         self.program_store
-            .encode_update(app, update, device, &mut encoder, self.size, num_samples);
+            .encode_update(app, device, &mut encoder, self.size, num_samples);
 
         if self.resized {
             let msaa_samples = window.msaa_samples();
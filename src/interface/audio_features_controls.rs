@@ -1,7 +1,7 @@
 use nannou::ui::prelude::*;
 
 use crate::app;
-use crate::interface::components;
+use crate::interface::envelope_controls;
 use crate::programs::uniforms::audio_features;
 
 /// Section height, computes and returns the current height.
@@ -10,7 +10,7 @@ pub fn height(model: &mut app::Model) -> f32 {
     let mut h = 0.0;
 
     if model.ui_show_audio_features {
-        h = 30.0;
+        h = envelope_controls::HEIGHT;
     }
 
     h
@@ -22,12 +22,12 @@ pub fn update(
     ui: &mut UiCell,
     uniforms: &mut audio_features::AudioFeaturesUniforms,
 ) {
-    if let Some(value) = components::slider(uniforms.smoothing, 0.0, 0.999999)
-        .parent(widget_ids.controls_wrapper)
-        .down(10.0)
-        .label("Feature Smoothing")
-        .set(widget_ids.audio_feature_smoothing, ui)
-    {
-        uniforms.smoothing = value;
-    }
+    let ids = envelope_controls::EnvelopeWidgetIds {
+        attack: widget_ids.audio_feature_attack,
+        release: widget_ids.audio_feature_release,
+        peak_hold: widget_ids.audio_feature_peak_hold,
+        auto_gain: widget_ids.audio_feature_auto_gain,
+        auto_gain_label: widget_ids.audio_feature_auto_gain_label,
+    };
+    envelope_controls::update(widget_ids, ids, ui, &mut uniforms.envelope.settings);
 }
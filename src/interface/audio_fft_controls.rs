@@ -1,16 +1,19 @@
 use nannou::ui::prelude::*;
 
 use crate::app;
-use crate::interface::components;
+use crate::interface::{components, envelope_controls};
 use crate::programs::uniforms::audio_fft;
 
+const FFT_SIZE_LABELS: &[&str] = &["256", "512", "1024", "2048", "4096", "8192"];
+const WINDOW_LABELS: &[&str] = &["hann", "hamming", "blackman", "nuttall"];
+
 /// Section height, computes and returns the current height.
 /// Used to compute the container height.
 pub fn height(model: &mut app::Model) -> f32 {
     let mut h = 0.0;
 
     if model.ui_show_audio_fft {
-        h = 30.0;
+        h = 165.0 + envelope_controls::HEIGHT;
     }
 
     h
@@ -22,12 +25,68 @@ pub fn update(
     ui: &mut UiCell,
     uniforms: &mut audio_fft::AudioFftUniforms,
 ) {
-    if let Some(value) = components::slider(uniforms.smoothing, 0.0, 0.999999)
+    let mut settings = uniforms.fft_settings;
+
+    /////////////////////////
+    // fft size select
+    components::label("FFT Size")
+        .parent(widget_ids.controls_wrapper)
+        .set(widget_ids.audio_fft_size_label, ui);
+    let size_index = audio_fft::FFT_SIZES
+        .iter()
+        .position(|s| *s == settings.size)
+        .unwrap_or(0);
+    if let Some(selected) = components::drop_down(FFT_SIZE_LABELS, size_index)
+        .parent(widget_ids.controls_wrapper)
+        .down(5.0)
+        .set(widget_ids.audio_fft_size, ui)
+    {
+        settings.size = audio_fft::FFT_SIZES[selected];
+    }
+
+    /////////////////////////
+    // window function select
+    components::label("Window")
+        .parent(widget_ids.controls_wrapper)
+        .set(widget_ids.audio_fft_window_label, ui);
+    let window_index = audio_fft::WINDOW_FUNCTIONS
+        .iter()
+        .position(|w| *w == settings.window)
+        .unwrap_or(0);
+    if let Some(selected) = components::drop_down(WINDOW_LABELS, window_index)
+        .parent(widget_ids.controls_wrapper)
+        .down(5.0)
+        .set(widget_ids.audio_fft_window, ui)
+    {
+        settings.window = audio_fft::WINDOW_FUNCTIONS[selected];
+    }
+
+    /////////////////////////
+    // overlap
+    if let Some(value) = components::slider(settings.overlap, 0.0, audio_fft::MAX_OVERLAP)
         .parent(widget_ids.controls_wrapper)
         .down(10.0)
-        .label("Smoothing")
-        .set(widget_ids.audio_fft_smoothing, ui)
+        .label("Overlap")
+        .set(widget_ids.audio_fft_overlap, ui)
     {
-        uniforms.smoothing = value;
+        settings.overlap = value;
     }
+
+    uniforms.set_fft_settings(settings);
+
+    /////////////////////////
+    // estimated key
+    let key = uniforms.key_name().unwrap_or_else(|| String::from("-"));
+    components::label(&format!("Key: {}", key))
+        .parent(widget_ids.controls_wrapper)
+        .set(widget_ids.audio_fft_key, ui);
+
+    let ids = envelope_controls::EnvelopeWidgetIds {
+        attack: widget_ids.audio_fft_attack,
+        release: widget_ids.audio_fft_release,
+        peak_hold: widget_ids.audio_fft_peak_hold,
+        auto_gain: widget_ids.audio_fft_auto_gain,
+        auto_gain_label: widget_ids.audio_fft_auto_gain_label,
+    };
+    envelope_controls::update(widget_ids, ids, ui, &mut uniforms.envelope.settings);
 }
@@ -0,0 +1,150 @@
+use nannou::ui::prelude::*;
+
+use crate::app;
+use crate::interface::components;
+use crate::programs::uniforms::audio_source::MAX_LATENCY;
+use crate::programs::uniforms::input_gain::{gain_to_db, GainSettings, Levels};
+
+/// Lowest level shown on the meter, in dBFS.
+const METER_FLOOR: f32 = -60.0;
+const METER_WIDTH: f64 = 170.0;
+
+/// Section height, computes and returns the current height.
+/// Used to compute the container height.
+pub fn height(model: &mut app::Model) -> f32 {
+    let mut h = 0.0;
+
+    if model.ui_show_audio_input {
+        h = 197.0;
+    }
+
+    h
+}
+
+/// Position of a linear level on the meter, 0 at the floor and 1 at full scale.
+fn meter_position(level: f32) -> f64 {
+    ((gain_to_db(level) - METER_FLOOR) / -METER_FLOOR).clamp(0.0, 1.0) as f64
+}
+
+/// A meter bar, drawn as a fill over a dark background.
+fn meter_bar(
+    widget_ids: &app::WidgetIds,
+    ui: &mut UiCell,
+    ids: (widget::Id, widget::Id),
+    level: f32,
+    color: (f32, f32, f32),
+) {
+    let (background, fill) = ids;
+    widget::Rectangle::fill([METER_WIDTH, 10.0])
+        .rgb(0.2, 0.2, 0.2)
+        .parent(widget_ids.controls_wrapper)
+        .down(5.0)
+        .align_left_of(widget_ids.controls_wrapper)
+        .set(background, ui);
+    widget::Rectangle::fill([(METER_WIDTH * meter_position(level)).max(1.0), 10.0])
+        .rgb(color.0, color.1, color.2)
+        .parent(widget_ids.controls_wrapper)
+        .top_left_of(background)
+        .set(fill, ui);
+}
+
+/// Section update, defines layout and update logic of the section
+pub fn update(
+    widget_ids: &app::WidgetIds,
+    ui: &mut UiCell,
+    settings: &mut GainSettings,
+    latency: &mut f32,
+    levels: Levels,
+    clipping: bool,
+) {
+    /////////////////////////
+    // manual gain
+    if let Some(value) = components::slider(settings.gain, -24.0, 24.0)
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .label("Gain (dB)")
+        .set(widget_ids.audio_input_gain, ui)
+    {
+        settings.gain = value;
+    }
+
+    /////////////////////////
+    // agc
+    components::label("AGC")
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .set(widget_ids.audio_input_agc_label, ui);
+    for _click in components::button_small(settings.agc)
+        .parent(widget_ids.controls_wrapper)
+        .right(137.0)
+        .set(widget_ids.audio_input_agc, ui)
+    {
+        settings.agc = !settings.agc;
+    }
+
+    if let Some(value) = components::slider(settings.target, -40.0, -6.0)
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .align_left_of(widget_ids.controls_wrapper)
+        .label("AGC Target (dBFS)")
+        .set(widget_ids.audio_input_target, ui)
+    {
+        settings.target = value;
+    }
+
+    /////////////////////////
+    // latency compensation
+    if let Some(value) = components::slider(*latency, -MAX_LATENCY, MAX_LATENCY)
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .label("Latency (ms)")
+        .set(widget_ids.audio_input_latency, ui)
+    {
+        *latency = value.round();
+    }
+
+    /////////////////////////
+    // level meter
+    components::label(&format!(
+        "Peak {:.1} dB, RMS {:.1} dB",
+        gain_to_db(levels.peak).max(METER_FLOOR),
+        gain_to_db(levels.rms).max(METER_FLOOR),
+    ))
+    .parent(widget_ids.controls_wrapper)
+    .set(widget_ids.audio_input_meter_label, ui);
+
+    let peak_color = if clipping {
+        (0.9, 0.2, 0.2)
+    } else {
+        (0.3, 0.8, 0.3)
+    };
+    meter_bar(
+        widget_ids,
+        ui,
+        (
+            widget_ids.audio_input_peak_background,
+            widget_ids.audio_input_peak,
+        ),
+        levels.peak,
+        peak_color,
+    );
+
+    // clip indicator, lit for a moment after the last clipped frame
+    let clip = if clipping { 0.9 } else { 0.2 };
+    widget::Rectangle::fill([25.0, 10.0])
+        .rgb(clip, 0.2, 0.2)
+        .parent(widget_ids.controls_wrapper)
+        .right_from(widget_ids.audio_input_peak_background, 5.0)
+        .set(widget_ids.audio_input_clip, ui);
+
+    meter_bar(
+        widget_ids,
+        ui,
+        (
+            widget_ids.audio_input_rms_background,
+            widget_ids.audio_input_rms,
+        ),
+        levels.rms,
+        (0.3, 0.6, 0.9),
+    );
+}
@@ -0,0 +1,73 @@
+use nannou::ui::prelude::*;
+
+use crate::app;
+use crate::interface::components;
+use crate::programs::uniforms::envelope::EnvelopeSettings;
+
+/// Height of the envelope controls within an audio section.
+pub const HEIGHT: f32 = 145.0;
+
+/// Widget ids of one set of envelope controls.
+pub struct EnvelopeWidgetIds {
+    pub attack: widget::Id,
+    pub release: widget::Id,
+    pub peak_hold: widget::Id,
+    pub auto_gain: widget::Id,
+    pub auto_gain_label: widget::Id,
+}
+
+/// Attack, release, peak-hold and auto-gain controls, shared by the audio sections.
+/// Should be placed last in a section since it ends with a right aligned toggle.
+pub fn update(
+    widget_ids: &app::WidgetIds,
+    ids: EnvelopeWidgetIds,
+    ui: &mut UiCell,
+    settings: &mut EnvelopeSettings,
+) {
+    /////////////////////////
+    // attack
+    if let Some(value) = components::slider(settings.attack, 0.0, 500.0)
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .label("Attack (ms)")
+        .set(ids.attack, ui)
+    {
+        settings.attack = value;
+    }
+
+    /////////////////////////
+    // release
+    if let Some(value) = components::slider(settings.release, 0.0, 2000.0)
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .label("Release (ms)")
+        .set(ids.release, ui)
+    {
+        settings.release = value;
+    }
+
+    /////////////////////////
+    // peak hold
+    if let Some(value) = components::slider(settings.peak_hold, 0.0, 1000.0)
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .label("Peak Hold (ms)")
+        .set(ids.peak_hold, ui)
+    {
+        settings.peak_hold = value;
+    }
+
+    /////////////////////////
+    // auto gain
+    components::label("Auto Gain")
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .set(ids.auto_gain_label, ui);
+    for _click in components::button_small(settings.auto_gain)
+        .parent(widget_ids.controls_wrapper)
+        .right(137.0)
+        .set(ids.auto_gain, ui)
+    {
+        settings.auto_gain = !settings.auto_gain;
+    }
+}
@@ -5,6 +5,7 @@ use crate::app;
 use crate::interface::components;
 use crate::programs::isf::data;
 use crate::programs::isf::IsfPipeline;
+use crate::programs::uniforms::webcam::MAX_WEBCAMS;
 
 pub fn height(model: &mut app::Model) -> f32 {
     let isf = match &model.program_store.isf_pipeline {
@@ -86,7 +87,7 @@ pub fn update(
                         *val = !*val;
                     }
                 }
-                (data::IsfInputData::Event { happening }, isf::InputType::Event) => {
+                (data::IsfInputData::Event { happening, .. }, isf::InputType::Event) => {
                     let widget_id = match isf_widget_ids.get(&input.name) {
                         Some(id) => id,
                         None => continue,
@@ -167,34 +168,44 @@ pub fn update(
                         .align_left_of(widget_ids.controls_wrapper)
                         .set(*isf_widget_ids.get(&label_name).unwrap(), ui);
 
-                    let labels = &["image", "video", "webcam"];
+                    let mut source_labels = vec![String::from("image"), String::from("video")];
+                    source_labels.extend((0..MAX_WEBCAMS).map(|i| format!("webcam{}", i)));
+                    let labels = source_labels
+                        .iter()
+                        .map(|s| s.as_str())
+                        .collect::<Vec<&str>>();
                     let selected = match &image_input.source {
                         data::ImageSource::Image(_) => 0,
                         data::ImageSource::Video(_) => 1,
-                        data::ImageSource::Webcam(_) => 2,
+                        data::ImageSource::Webcam(_) => 2 + image_input.webcam,
                         _ => 0,
                     };
 
-                    if let Some(index) = components::drop_down(labels, selected)
+                    if let Some(index) = components::drop_down(&labels[..], selected)
                         .parent(widget_ids.controls_wrapper)
                         .down(5.0)
                         .set(*widget_id, ui)
                     {
-                        match labels[index] {
-                            "image" => {
+                        match index {
+                            0 => {
                                 image_input.select_image(
                                     device,
                                     encoder,
                                     &isf_pipeline.image_loader,
                                 );
                             }
-                            "video" => {
-                                image_input.select_video(device);
+                            1 => {
+                                image_input.select_video(device, isf_pipeline.video_audio);
                             }
-                            "webcam" => {
-                                image_input.start_webcam(device, size);
+                            _ => {
+                                let camera = index - 2;
+                                image_input.start_webcam(
+                                    device,
+                                    size,
+                                    camera,
+                                    &isf_pipeline.webcams[camera],
+                                );
                             }
-                            _ => {}
                         };
                         isf_pipeline.updated = true;
                     }
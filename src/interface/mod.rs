@@ -6,19 +6,35 @@ use crate::app;
 
 mod audio_features_controls;
 mod audio_fft_controls;
+mod audio_input_controls;
 mod camera_info;
 mod color_controls;
 mod components;
+mod envelope_controls;
 mod errors;
 mod geometry_controls;
 mod image_controls;
 mod isf_controls;
 mod noise_controls;
+mod transform_controls;
 mod video_controls;
+mod webcam_controls;
 
 fn controls_height(model: &mut app::Model) -> f32 {
     let mut height = 140.0;
 
+    if model.program_store.audio_source().is_running() {
+        height += 60.0 + audio_input_controls::height(model);
+    }
+
+    if model.program_store.uses_webcam() {
+        height += 60.0 + webcam_controls::height(model);
+    }
+
+    if !model.program_store.transform_sources().is_empty() {
+        height += 60.0 + transform_controls::height(model);
+    }
+
     let subscriptions = match &model.program_store.current_subscriptions {
         Some(s) => s,
         None => return height + isf_controls::height(model),
@@ -32,6 +48,7 @@ fn controls_height(model: &mut app::Model) -> f32 {
         subscriptions.image,
         subscriptions.noise,
         subscriptions.video,
+        subscriptions.video_mask || subscriptions.webcam_mask,
     ]
     .iter()
     .for_each(|s| {
@@ -155,6 +172,110 @@ pub fn update(
         }
     }
 
+    //////////////////////////////////////////////////
+    // Audio Input Controls
+    //////////////////////////////////////////////////
+    let audio_source = model.program_store.audio_source();
+    if audio_source.is_running() {
+        let levels = audio_source.levels;
+        let clipping = audio_source.clipping();
+
+        for _click in components::button_big()
+            .parent(model.widget_ids.controls_wrapper)
+            .down(20.0)
+            .align_left_of(model.widget_ids.controls_wrapper)
+            .label("Audio Input")
+            .set(model.widget_ids.audio_input_folder, ui)
+        {
+            println!("toggle audio input controls");
+            model.ui_show_audio_input = !model.ui_show_audio_input;
+        }
+
+        if model.ui_show_audio_input {
+            audio_input_controls::update(
+                &model.widget_ids,
+                ui,
+                &mut model.program_store.audio_gain,
+                &mut model.program_store.audio_latency,
+                levels,
+                clipping,
+            );
+        }
+    }
+
+    //////////////////////////////////////////////////
+    // Webcam Controls
+    //////////////////////////////////////////////////
+    if model.program_store.uses_webcam() {
+        for _click in components::button_big()
+            .parent(model.widget_ids.controls_wrapper)
+            .down(20.0)
+            .align_left_of(model.widget_ids.controls_wrapper)
+            .label("Webcam")
+            .set(model.widget_ids.webcam_folder, ui)
+        {
+            println!("toggle webcam controls");
+            model.ui_show_webcam = !model.ui_show_webcam;
+            if model.ui_show_webcam {
+                model.program_store.refresh_webcam_devices();
+            }
+        }
+
+        if model.ui_show_webcam {
+            let cameras = model.program_store.webcams_in_use();
+            if !cameras.contains(&model.ui_webcam) {
+                model.ui_webcam = cameras[0];
+            }
+            let camera = model.ui_webcam;
+            let negotiated = model
+                .program_store
+                .webcam_capture(camera)
+                .map(|c| (c.video_size, c.frame_rate));
+            webcam_controls::update(
+                &model.widget_ids,
+                ui,
+                &mut model.ui_webcam,
+                &cameras,
+                &mut model.program_store.webcams[camera],
+                &model.program_store.webcam_devices,
+                negotiated,
+            );
+        }
+    }
+
+    //////////////////////////////////////////////////
+    // Transform Controls
+    //////////////////////////////////////////////////
+    let sources = model.program_store.transform_sources();
+    if !sources.is_empty() {
+        for _click in components::button_big()
+            .parent(model.widget_ids.controls_wrapper)
+            .down(20.0)
+            .align_left_of(model.widget_ids.controls_wrapper)
+            .label("Transform")
+            .set(model.widget_ids.transform_folder, ui)
+        {
+            println!("toggle transform controls");
+            model.ui_show_transform = !model.ui_show_transform;
+        }
+
+        if model.ui_show_transform {
+            if model.ui_transform >= sources.len() {
+                model.ui_transform = 0;
+            }
+            let source = sources[model.ui_transform].clone();
+            if let Some(transform) = model.program_store.transform_mut(&source) {
+                transform_controls::update(
+                    &model.widget_ids,
+                    ui,
+                    &mut model.ui_transform,
+                    &sources,
+                    transform,
+                );
+            }
+        }
+    }
+
     if let Some(subscriptions) = &model.program_store.current_subscriptions {
         //////////////////////////////////////////////////
         // Color Controls
@@ -325,6 +446,22 @@ pub fn update(
             }
         }
 
+        //////////////////////////////////////////////////
+        // Background Controls
+        //////////////////////////////////////////////////
+        if subscriptions.video_mask || subscriptions.webcam_mask {
+            for _click in components::button()
+                .parent(model.widget_ids.controls_wrapper)
+                .down(20.0)
+                .align_left_of(model.widget_ids.controls_wrapper)
+                .label("Re-learn Background")
+                .set(model.widget_ids.background_relearn, ui)
+            {
+                println!("re-learning background");
+                model.program_store.buffer_store.relearn_background();
+            }
+        }
+
         //////////////////////////////////////////////////
         // Other UI
         //////////////////////////////////////////////////
@@ -0,0 +1,209 @@
+use nannou::ui::prelude::*;
+
+use crate::app;
+use crate::interface::components;
+use crate::programs::uniforms::source_transform::{SourceTransform, ROTATIONS};
+
+/// Section height, computes and returns the current height.
+/// Used to compute the container height.
+pub fn height(model: &mut app::Model) -> f32 {
+    let mut h = 0.0;
+
+    if model.ui_show_transform {
+        h = 490.0;
+    }
+
+    h
+}
+
+/// Section update, defines layout and update logic of the section
+pub fn update(
+    widget_ids: &app::WidgetIds,
+    ui: &mut UiCell,
+    source: &mut usize,
+    sources: &[String],
+    transform: &mut SourceTransform,
+) {
+    /////////////////////////
+    // source select
+    components::label("Source")
+        .parent(widget_ids.controls_wrapper)
+        .set(widget_ids.transform_source_label, ui);
+
+    let labels = sources.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
+    if let Some(selected) = components::drop_down(&labels[..], *source)
+        .parent(widget_ids.controls_wrapper)
+        .down(5.0)
+        .set(widget_ids.transform_source, ui)
+    {
+        *source = selected;
+    }
+
+    /////////////////////////
+    // orientation
+    components::label("Mirror")
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .align_left_of(widget_ids.controls_wrapper)
+        .set(widget_ids.transform_mirror_label, ui);
+    for _click in components::button_small(transform.mirror)
+        .parent(widget_ids.controls_wrapper)
+        .right(134.0)
+        .set(widget_ids.transform_mirror, ui)
+    {
+        transform.mirror = !transform.mirror;
+    }
+
+    components::label("Flip")
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .align_left_of(widget_ids.controls_wrapper)
+        .set(widget_ids.transform_flip_label, ui);
+    for _click in components::button_small(transform.flip)
+        .parent(widget_ids.controls_wrapper)
+        .right(148.0)
+        .set(widget_ids.transform_flip, ui)
+    {
+        transform.flip = !transform.flip;
+    }
+
+    components::label("Rotation")
+        .parent(widget_ids.controls_wrapper)
+        .align_left_of(widget_ids.controls_wrapper)
+        .set(widget_ids.transform_rotation_label, ui);
+    if let Some(selected) =
+        components::drop_down(&ROTATIONS[..], transform.quarter_turns() as usize)
+            .parent(widget_ids.controls_wrapper)
+            .down(5.0)
+            .set(widget_ids.transform_rotation, ui)
+    {
+        transform.rotation = selected as u32 * 90;
+    }
+
+    /////////////////////////
+    // crop
+    components::label("Crop")
+        .parent(widget_ids.controls_wrapper)
+        .set(widget_ids.transform_crop_label, ui);
+
+    let [x, y, width, height] = transform.crop_rect();
+
+    if let Some(value) = components::x_2d_slider(x, 0.0, 0.99)
+        .parent(widget_ids.controls_wrapper)
+        .set(widget_ids.transform_crop_x, ui)
+    {
+        transform.crop[0] = value;
+    }
+
+    if let Some(value) = components::y_2d_slider(y, 0.0, 0.99)
+        .parent(widget_ids.controls_wrapper)
+        .set(widget_ids.transform_crop_y, ui)
+    {
+        transform.crop[1] = value;
+    }
+
+    if let Some(value) = components::slider_2d_small(width, 0.01, 1.0)
+        .rgb(0.3, 0.3, 0.3)
+        .down_from(widget_ids.transform_crop_x, 5.0)
+        .label("W")
+        .parent(widget_ids.controls_wrapper)
+        .set(widget_ids.transform_crop_width, ui)
+    {
+        transform.crop[2] = value;
+    }
+
+    if let Some(value) = components::slider_2d_small(height, 0.01, 1.0)
+        .rgb(0.3, 0.3, 0.3)
+        .right(10.0)
+        .label("H")
+        .parent(widget_ids.controls_wrapper)
+        .set(widget_ids.transform_crop_height, ui)
+    {
+        transform.crop[3] = value;
+    }
+
+    /////////////////////////
+    // color
+    if let Some(value) = components::slider(transform.brightness, -1.0, 1.0)
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .align_left_of(widget_ids.controls_wrapper)
+        .label("Brightness")
+        .set(widget_ids.transform_brightness, ui)
+    {
+        transform.brightness = value;
+    }
+
+    if let Some(value) = components::slider(transform.contrast, 0.0, 2.0)
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .label("Contrast")
+        .set(widget_ids.transform_contrast, ui)
+    {
+        transform.contrast = value;
+    }
+
+    if let Some(value) = components::slider(transform.saturation, 0.0, 2.0)
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .label("Saturation")
+        .set(widget_ids.transform_saturation, ui)
+    {
+        transform.saturation = value;
+    }
+
+    if let Some(value) = components::slider(transform.gamma, 0.2, 3.0)
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .label("Gamma")
+        .set(widget_ids.transform_gamma, ui)
+    {
+        transform.gamma = value;
+    }
+
+    /////////////////////////
+    // alpha
+    components::label("Premultiplied")
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .align_left_of(widget_ids.controls_wrapper)
+        .set(widget_ids.transform_premultiplied_label, ui);
+    for _click in components::button_small(transform.premultiplied)
+        .parent(widget_ids.controls_wrapper)
+        .right(85.0)
+        .set(widget_ids.transform_premultiplied, ui)
+    {
+        transform.premultiplied = !transform.premultiplied;
+    }
+
+    components::label("Premultiply")
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .align_left_of(widget_ids.controls_wrapper)
+        .set(widget_ids.transform_premultiply_label, ui);
+    for _click in components::button_small(transform.premultiply)
+        .parent(widget_ids.controls_wrapper)
+        .right(99.0)
+        .set(widget_ids.transform_premultiply, ui)
+    {
+        transform.premultiply = !transform.premultiply;
+    }
+
+    // keeps the orientation and alpha handling
+    for _click in components::button()
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .align_left_of(widget_ids.controls_wrapper)
+        .label("Reset Crop & Color")
+        .set(widget_ids.transform_reset, ui)
+    {
+        *transform = SourceTransform {
+            mirror: transform.mirror,
+            flip: transform.flip,
+            rotation: transform.rotation,
+            premultiplied: transform.premultiplied,
+            premultiply: transform.premultiply,
+            ..SourceTransform::default()
+        };
+    }
+}
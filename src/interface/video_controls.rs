@@ -4,6 +4,7 @@ use nannou::ui::prelude::*;
 use crate::app;
 use crate::interface::components;
 use crate::programs::uniforms::video;
+use crate::programs::uniforms::video_capture::{PlayMode, VideoCapture};
 
 /// Section height, computes and returns the current height.
 /// Used to compute the container height.
@@ -11,7 +12,15 @@ pub fn height(model: &mut app::Model) -> f32 {
     let mut h = 0.0;
 
     if model.ui_show_video {
-        h = 100.0;
+        h = 190.0;
+
+        // transport controls are only shown for video files
+        let video_uniforms = &model.program_store.buffer_store.video_uniforms;
+        if let Some(capture) = &video_uniforms.video_capture {
+            if capture.duration > 0.0 {
+                h += 205.0;
+            }
+        }
     }
 
     h
@@ -52,5 +61,147 @@ pub fn update(
         {
             capture.set_speed(value);
         }
+
+        if capture.duration > 0.0 {
+            transport(widget_ids, ui, capture);
+        }
+    }
+
+    /////////////////////////
+    // soundtrack
+    let mut audio_settings = uniforms.audio_settings;
+
+    components::label("Sound")
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .align_left_of(widget_ids.controls_wrapper)
+        .set(widget_ids.video_sound_label, ui);
+    for _click in components::button_small(audio_settings.play)
+        .parent(widget_ids.controls_wrapper)
+        .right(137.0)
+        .set(widget_ids.video_sound, ui)
+    {
+        audio_settings.play = !audio_settings.play;
+    }
+
+    components::label("Analyze Sound")
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .align_left_of(widget_ids.controls_wrapper)
+        .set(widget_ids.video_analyze_label, ui);
+    for _click in components::button_small(audio_settings.analyze)
+        .parent(widget_ids.controls_wrapper)
+        .right(97.0)
+        .set(widget_ids.video_analyze, ui)
+    {
+        audio_settings.analyze = !audio_settings.analyze;
+    }
+
+    if audio_settings != uniforms.audio_settings {
+        uniforms.set_audio_settings(audio_settings);
+    }
+
+    components::label("Sync to Time")
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .align_left_of(widget_ids.controls_wrapper)
+        .set(widget_ids.video_sync_label, ui);
+    for _click in components::button_small(uniforms.sync)
+        .parent(widget_ids.controls_wrapper)
+        .right(104.0)
+        .set(widget_ids.video_sync, ui)
+    {
+        uniforms.set_sync(!uniforms.sync);
+    }
+}
+
+/// Seek, loop region, play mode and frame step controls of a video file.
+fn transport(widget_ids: &app::WidgetIds, ui: &mut UiCell, capture: &mut VideoCapture) {
+    let duration = capture.duration as f32;
+
+    if let Some(value) = components::slider(capture.position() as f32, 0.0, duration)
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .label("Position")
+        .set(widget_ids.video_position, ui)
+    {
+        // scrub whole frames
+        let frame = (value as f64 * capture.frame_rate).round();
+        capture.seek_frame(frame as i64);
+    }
+
+    /////////////////////////
+    // loop region
+    let (loop_in, loop_out) = capture.loop_region.unwrap_or((0.0, capture.duration));
+    let mut region = (loop_in as f32, loop_out as f32);
+
+    if let Some(value) = components::slider(region.0, 0.0, duration)
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .label("Loop In")
+        .set(widget_ids.video_loop_in, ui)
+    {
+        region.0 = value.min(region.1);
+    }
+
+    if let Some(value) = components::slider(region.1, 0.0, duration)
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .label("Loop Out")
+        .set(widget_ids.video_loop_out, ui)
+    {
+        region.1 = value.max(region.0);
+    }
+
+    if region != (loop_in as f32, loop_out as f32) {
+        if region.0 <= 0.0 && region.1 >= duration {
+            capture.set_loop_region(None);
+        } else {
+            capture.set_loop_region(Some((region.0 as f64, region.1 as f64)));
+        }
+    }
+
+    /////////////////////////
+    // play mode
+    components::label("Play Mode")
+        .parent(widget_ids.controls_wrapper)
+        .set(widget_ids.video_mode_label, ui);
+
+    let labels = PlayMode::ALL
+        .iter()
+        .map(|m| m.label())
+        .collect::<Vec<&str>>();
+    let selected = PlayMode::ALL
+        .iter()
+        .position(|m| *m == capture.play_mode)
+        .unwrap_or(0);
+    if let Some(index) = components::drop_down(&labels[..], selected)
+        .parent(widget_ids.controls_wrapper)
+        .down(5.0)
+        .set(widget_ids.video_mode, ui)
+    {
+        capture.set_play_mode(PlayMode::ALL[index]);
+    }
+
+    /////////////////////////
+    // frame step
+    for _click in components::button()
+        .w(95.0)
+        .parent(widget_ids.controls_wrapper)
+        .down(10.0)
+        .label("< Frame")
+        .set(widget_ids.video_step_back, ui)
+    {
+        capture.step(-1);
+    }
+
+    for _click in components::button()
+        .w(95.0)
+        .parent(widget_ids.controls_wrapper)
+        .right(10.0)
+        .label("Frame >")
+        .set(widget_ids.video_step_forward, ui)
+    {
+        capture.step(1);
     }
 }
@@ -0,0 +1,121 @@
+use nannou::prelude::*;
+use nannou::ui::prelude::*;
+
+use crate::app;
+use crate::interface::components;
+use crate::programs::uniforms::webcam_devices::{WebcamDevice, WebcamSettings};
+
+/// Section height, computes and returns the current height.
+/// Used to compute the container height.
+pub fn height(model: &mut app::Model) -> f32 {
+    let mut h = 0.0;
+
+    if model.ui_show_webcam {
+        h = 195.0;
+    }
+
+    h
+}
+
+/// Section update, defines layout and update logic of the section
+pub fn update(
+    widget_ids: &app::WidgetIds,
+    ui: &mut UiCell,
+    camera: &mut usize,
+    cameras: &[usize],
+    settings: &mut WebcamSettings,
+    devices: &[WebcamDevice],
+    negotiated: Option<(Vector2, f64)>,
+) {
+    /////////////////////////
+    // camera select
+    components::label("Camera")
+        .parent(widget_ids.controls_wrapper)
+        .set(widget_ids.webcam_camera_label, ui);
+
+    let camera_labels = cameras
+        .iter()
+        .map(|c| format!("webcam{}", c))
+        .collect::<Vec<String>>();
+    let labels = camera_labels
+        .iter()
+        .map(|s| s.as_str())
+        .collect::<Vec<&str>>();
+    let camera_index = cameras.iter().position(|c| c == camera).unwrap_or(0);
+    if let Some(selected) = components::drop_down(&labels[..], camera_index)
+        .parent(widget_ids.controls_wrapper)
+        .down(5.0)
+        .set(widget_ids.webcam_camera, ui)
+    {
+        *camera = cameras[selected];
+    }
+
+    /////////////////////////
+    // device select
+    components::label("Device")
+        .parent(widget_ids.controls_wrapper)
+        .set(widget_ids.webcam_device_label, ui);
+
+    let device_labels = if devices.is_empty() {
+        vec![format!("{}", settings.device)]
+    } else {
+        devices.iter().map(|d| d.label()).collect::<Vec<String>>()
+    };
+    let labels = device_labels
+        .iter()
+        .map(|s| s.as_str())
+        .collect::<Vec<&str>>();
+    let device_index = devices
+        .iter()
+        .position(|d| d.index == settings.device)
+        .unwrap_or(0);
+    if let Some(selected) = components::drop_down(&labels[..], device_index)
+        .parent(widget_ids.controls_wrapper)
+        .down(5.0)
+        .set(widget_ids.webcam_device, ui)
+    {
+        if let Some(device) = devices.get(selected) {
+            settings.device = device.index;
+            settings.name = None;
+            settings.set_mode(None);
+        }
+    }
+
+    /////////////////////////
+    // capture mode select
+    components::label("Format")
+        .parent(widget_ids.controls_wrapper)
+        .set(widget_ids.webcam_mode_label, ui);
+
+    let modes = devices
+        .iter()
+        .find(|d| d.index == settings.device)
+        .map_or(&[][..], |d| &d.modes[..]);
+    let mut mode_labels = vec![String::from("auto")];
+    mode_labels.extend(modes.iter().map(|m| m.label()));
+    let labels = mode_labels
+        .iter()
+        .map(|s| s.as_str())
+        .collect::<Vec<&str>>();
+    let mode_index = modes
+        .iter()
+        .position(|m| settings.uses_mode(m))
+        .map_or(0, |i| i + 1);
+    if let Some(selected) = components::drop_down(&labels[..], mode_index)
+        .parent(widget_ids.controls_wrapper)
+        .down(5.0)
+        .set(widget_ids.webcam_mode, ui)
+    {
+        settings.set_mode(selected.checked_sub(1).and_then(|i| modes.get(i)));
+    }
+
+    /////////////////////////
+    // negotiated size
+    let info = match negotiated {
+        Some((size, fps)) => format!("Capturing {}x{} @ {:.0}fps", size.x, size.y, fps),
+        None => String::from("Not capturing"),
+    };
+    components::label(&info)
+        .parent(widget_ids.controls_wrapper)
+        .set(widget_ids.webcam_info, ui);
+}
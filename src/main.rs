@@ -66,11 +66,16 @@ fn model(app: &App) -> app::Model {
         ui,
         ui_show_audio_features: false,
         ui_show_audio_fft: false,
+        ui_show_audio_input: false,
         ui_show_color: false,
         ui_show_geometry: false,
         ui_show_image: false,
         ui_show_noise: false,
+        ui_show_transform: false,
         ui_show_video: false,
+        ui_show_webcam: false,
+        ui_webcam: 0,
+        ui_transform: 0,
         resized: false,
         size,
         vertex_buffer,
@@ -182,7 +187,7 @@ fn mouse_released(_app: &App, model: &mut app::Model, _: nannou::event::MouseBut
         .mouse_down = 0;
 }
 
-fn update(app: &App, model: &mut app::Model, update: Update) {
+fn update(app: &App, model: &mut app::Model, _update: Update) {
     if model.paused {
         return;
     }
@@ -191,7 +196,7 @@ fn update(app: &App, model: &mut app::Model, update: Update) {
     let device = window.swap_chain_device();
     let num_samples = window.msaa_samples();
 
-    model.encode_update(app, update, &window, device, num_samples);
+    model.encode_update(app, &window, device, num_samples);
 
     if model.program_store.is_multipass() {
         model.encode_render_passes(&window, device);
@@ -3,6 +3,20 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 
+use crate::programs::uniforms::animated_image::AnimationSettings;
+use crate::programs::uniforms::audio_features::FeatureServerSettings;
+use crate::programs::uniforms::audio_fft::WindowFunction;
+use crate::programs::uniforms::audio_generator::GeneratorSettings;
+use crate::programs::uniforms::audio_source::ChannelMode;
+use crate::programs::uniforms::background::BackgroundSettings;
+use crate::programs::uniforms::envelope::EnvelopeSettings;
+use crate::programs::uniforms::faces::FaceDetectionSettings;
+use crate::programs::uniforms::image_sequence::ImageSequenceSettings;
+use crate::programs::uniforms::input_gain::GainSettings;
+use crate::programs::uniforms::optical_flow::FlowSettings;
+use crate::programs::uniforms::source_transform::SourceTransform;
+use crate::programs::uniforms::video_audio::VideoAudioSettings;
+use crate::programs::uniforms::webcam_devices::WebcamSettings;
 use crate::util;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,15 +27,32 @@ pub struct PipelineConfig {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProgramSettings {
-    pub audio_feature_smoothing: Option<f32>,
-    pub audio_fft_smoothing: Option<f32>,
+    pub audio_channels: Option<ChannelMode>,
+    pub audio_envelope: Option<EnvelopeSettings>,
+    pub audio_feature_envelope: Option<EnvelopeSettings>,
+    pub audio_feature_server: Option<FeatureServerSettings>,
+    pub audio_fft_envelope: Option<EnvelopeSettings>,
+    pub audio_fft_history: Option<usize>,
+    pub audio_fft_overlap: Option<f32>,
+    pub audio_fft_size: Option<usize>,
+    pub audio_fft_window: Option<WindowFunction>,
+    pub audio_gain: Option<GainSettings>,
+    pub audio_latency: Option<f32>,
+    pub audio_generator: Option<GeneratorSettings>,
+    /// How the `video_mask` and `webcam_mask` uniforms separate the foreground.
+    pub background_subtraction: Option<BackgroundSettings>,
     pub camera_position: Option<Vector3<f32>>,
     pub camera_target: Option<Vector3<f32>>,
     pub camera_up: Option<Vector3<f32>>,
     pub color_mode: Option<u32>,
+    /// How faces are found in the first webcam for the `faces` uniforms.
+    pub face_detection: Option<FaceDetectionSettings>,
     pub shape_rotation: Option<Vector3<f32>>,
+    pub image_animation: Option<AnimationSettings>,
     pub image1: Option<String>,
+    pub image1_transform: Option<SourceTransform>,
     pub image2: Option<String>,
+    pub image2_transform: Option<SourceTransform>,
     pub noise_lacunarity: Option<f32>,
     pub noise_gain: Option<f32>,
     pub noise_invert: Option<i32>,
@@ -30,9 +61,23 @@ pub struct ProgramSettings {
     pub noise_scale_by_prev: Option<i32>,
     pub noise_sharpen: Option<i32>,
     pub noise_speed: Option<f32>,
+    /// How the `video_flow` and `webcam_flow` uniforms compute optical flow.
+    pub optical_flow: Option<FlowSettings>,
     pub passes: Option<i32>,
+    /// Frames per second of a fixed timestep render clock, time follows the wall clock
+    /// without it.
+    pub render_fps: Option<f64>,
     pub video: Option<String>,
+    pub video_audio: Option<VideoAudioSettings>,
+    /// In and out point in seconds.
+    pub video_loop: Option<(f64, f64)>,
+    /// How `video` is played when it is a folder or one of its numbered images.
+    pub video_sequence: Option<ImageSequenceSettings>,
     pub video_speed: Option<f32>,
+    pub video_sync: Option<bool>,
+    pub video_transform: Option<SourceTransform>,
+    pub webcam: Option<WebcamSettings>,
+    pub webcams: Option<Vec<WebcamSettings>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -2,7 +2,6 @@
 
 use nannou::image;
 use nannou::prelude::*;
-use opencv::prelude::*;
 use regex::Regex;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
@@ -11,19 +10,33 @@ use thiserror::Error;
 use threadpool::ThreadPool;
 use tinyfiledialogs::open_file_dialog;
 
+use crate::programs::isf::extensions::{InputExtension, InputExtensions};
+use crate::programs::uniforms::animated_image::{Animation, AnimationSettings};
 use crate::programs::uniforms::audio::AudioUniforms;
 use crate::programs::uniforms::audio_fft::AudioFftUniforms;
-use crate::programs::uniforms::audio_source::AudioSource;
+use crate::programs::uniforms::audio_source::{self, AudioSource, ChannelMode};
+use crate::programs::uniforms::audio_trigger::AudioTrigger;
+use crate::programs::uniforms::envelope::EnvelopeSettings;
+use crate::programs::uniforms::frame_source;
+use crate::programs::uniforms::frame_upload::FrameUploader;
+use crate::programs::uniforms::image_sequence::ImageSequenceSettings;
+use crate::programs::uniforms::source_transform::SourceTransform;
+use crate::programs::uniforms::video_audio::VideoAudioSettings;
 use crate::programs::uniforms::video_capture::VideoCapture;
+use crate::programs::uniforms::webcam_devices::{self, WebcamSettings};
 
 pub const DEFAULT_AUDIO_SAMPLE_COUNT: u32 = 64;
 pub const DEFAULT_AUDIO_FFT_COLUMNS: u32 = 64;
+/// ISF audio textures have one row per channel.
+pub const DEFAULT_AUDIO_CHANNELS: ChannelMode = ChannelMode::Stereo;
 
 /// Handles to both the cpu and gpu representations of the image.
 #[derive(Debug)]
 pub struct ImageData {
     pub image: image::RgbaImage,
     pub texture: wgpu::Texture,
+    /// All frames of animated images, `image` is the first one.
+    pub animation: Option<Animation>,
 }
 
 #[derive(Debug)]
@@ -51,7 +64,7 @@ pub type InputName = String;
 
 #[derive(Debug)]
 pub struct LoadingImage {
-    receiver: mpsc::Receiver<Result<image::RgbaImage, ImageLoadError>>,
+    receiver: mpsc::Receiver<Result<(image::RgbaImage, Option<Animation>), ImageLoadError>>,
     texture: wgpu::Texture,
 }
 
@@ -98,9 +111,12 @@ impl ImageState {
 
                 image_loader.threadpool.execute(move || {
                     println!("loading {:?}", img_path);
-                    let img_res = image::open(img_path)
-                        .map(|img| img.to_rgba8())
-                        .map_err(|err| err.into());
+                    let img_res = match Animation::open(&img_path) {
+                        Some(animation) => Ok((animation.first_frame().clone(), Some(animation))),
+                        None => image::open(img_path)
+                            .map(|img| (img.to_rgba8(), None))
+                            .map_err(|err| err.into()),
+                    };
                     tx.send(img_res).ok();
                 });
 
@@ -122,11 +138,15 @@ impl ImageState {
                 Ok(img_res) => {
                     let usage = wgpu::TextureUsage::COPY_DST | wgpu::TextureUsage::SAMPLED;
 
-                    let res = img_res.map(|image| {
+                    let res = img_res.map(|(image, animation)| {
                         let texture = wgpu::Texture::encode_load_from_image_buffer(
                             device, encoder, usage, &image,
                         );
-                        ImageData { image, texture }
+                        ImageData {
+                            image,
+                            texture,
+                            animation,
+                        }
                     });
 
                     println!("loaded: {:?}", img_path);
@@ -167,16 +187,39 @@ pub enum ImageSource {
 #[derive(Debug)]
 pub struct ImageInput {
     pub source: ImageSource,
+    /// Set when a webcam couldn't be opened.
+    pub error: Option<String>,
+    /// Camera the webcam source captures from.
+    pub webcam: usize,
+    /// Applied to image and video sources, webcams use the transform of the camera.
+    pub transform: SourceTransform,
+    /// How image sequences chosen as the video source are played.
+    pub sequence: ImageSequenceSettings,
+    /// How animated images are played.
+    pub animation: AnimationSettings,
+    /// The image source after `transform`, made once the image has loaded.
+    transformed: Option<FrameUploader>,
 }
 
 impl ImageInput {
-    fn new() -> Self {
+    fn new(
+        transform: SourceTransform,
+        sequence: ImageSequenceSettings,
+        animation: AnimationSettings,
+    ) -> Self {
         Self {
             source: ImageSource::None,
+            error: None,
+            webcam: 0,
+            transform,
+            sequence,
+            animation,
+            transformed: None,
         }
     }
 
     fn end_sessions(&mut self) {
+        self.error = None;
         match &mut self.source {
             ImageSource::Video(v) => v.end_session(),
             ImageSource::Webcam(v) => v.end_session(),
@@ -195,16 +238,67 @@ impl ImageInput {
         let mut image_source = ImageState::None;
         let updated = image_source.update(device, encoder, image_loader, path);
         self.source = ImageSource::Image(image_source);
+        self.transformed = None;
         updated
     }
 
+    /// Render a loaded image with `transform` when it is new or the transform changed.
+    /// Returns true when the transformed texture was replaced.
+    fn transform_image(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+    ) -> bool {
+        let data = match &self.source {
+            ImageSource::Image(ImageState::Ready(Ok(data))) => data,
+            _ => return false,
+        };
+
+        match &mut self.transformed {
+            Some(uploader) => {
+                if uploader.transform == self.transform {
+                    return false;
+                }
+                let replaced = uploader.set_transform(device, self.transform);
+                uploader.render(encoder);
+                replaced
+            }
+            None => {
+                let uploader =
+                    FrameUploader::from_texture(device, data.texture.clone(), self.transform);
+                uploader.render(encoder);
+                self.transformed = Some(uploader);
+                true
+            }
+        }
+    }
+
+    /// Show the current frame of an animated image.
+    fn play_animation(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        let animation = match &mut self.source {
+            ImageSource::Image(ImageState::Ready(Ok(ImageData {
+                animation: Some(animation),
+                ..
+            }))) => animation,
+            _ => return,
+        };
+
+        if let Some(uploader) = &self.transformed {
+            animation.play(device, encoder, uploader, self.animation);
+        }
+    }
+
     pub fn select_image(
         &mut self,
         device: &wgpu::Device,
         encoder: &mut wgpu::CommandEncoder,
         image_loader: &ImageLoader,
     ) {
-        let filepath = match open_file_dialog("Load Image", "~", Some((&["*.jpg", "*.png"], ""))) {
+        let filepath = match open_file_dialog(
+            "Load Image",
+            "~",
+            Some((&["*.jpg", "*.png", "*.gif", "*.apng"], "")),
+        ) {
             Some(path) => path,
             None => return,
         };
@@ -214,11 +308,17 @@ impl ImageInput {
         self.load_image(device, encoder, image_loader, PathBuf::from(filepath));
     }
 
-    pub fn select_video(&mut self, device: &wgpu::Device) {
+    pub fn select_video(&mut self, device: &wgpu::Device, audio_settings: VideoAudioSettings) {
         let filepath = match open_file_dialog(
             "Load Video",
             "~",
-            Some((&["*.mp4", "*.avi", "*.mov", "*.mpeg", "*.flv", "*.wmv"], "")),
+            Some((
+                &[
+                    "*.mp4", "*.avi", "*.mov", "*.mpeg", "*.flv", "*.wmv", "*.png", "*.exr",
+                    "*.tif", "*.tiff", "*.jpg",
+                ],
+                "",
+            )),
         ) {
             Some(filepath) => filepath,
             None => return,
@@ -228,33 +328,59 @@ impl ImageInput {
 
         self.end_sessions();
 
-        let capture =
-            opencv::videoio::VideoCapture::from_file(&filepath, opencv::videoio::CAP_ANY).unwrap();
+        let (capture, is_sequence) = match frame_source::open_file(&filepath, self.sequence) {
+            Ok(c) => c,
+            Err(e) => {
+                println!("Video error: {}", e);
+                return;
+            }
+        };
 
-        let video_capture = VideoCapture::new(device, capture, 1.0);
+        let mut video_capture = VideoCapture::new(device, capture, 1.0, self.transform);
+        if is_sequence {
+            video_capture.set_play_mode(self.sequence.play_mode);
+        } else {
+            video_capture.start_audio(&filepath, audio_settings);
+        }
 
         self.source = ImageSource::Video(video_capture);
     }
 
-    pub fn start_webcam(&mut self, device: &wgpu::Device, size: Point2) {
-        println!("selected webcam");
+    pub fn start_webcam(
+        &mut self,
+        device: &wgpu::Device,
+        size: Point2,
+        index: usize,
+        settings: &WebcamSettings,
+    ) {
+        println!("selected webcam {}", index);
 
         self.end_sessions();
+        self.webcam = index;
+
+        let capture = match webcam_devices::open_webcam(settings, size) {
+            Ok(c) => c,
+            Err(e) => {
+                println!("Webcam error: {}", e);
+                self.error = Some(e);
+                return;
+            }
+        };
 
-        let mut capture = opencv::videoio::VideoCapture::new(0, opencv::videoio::CAP_ANY).unwrap();
-        capture
-            .set(opencv::videoio::CAP_PROP_FRAME_WIDTH, size[0] as f64)
-            .ok();
-        capture
-            .set(opencv::videoio::CAP_PROP_FRAME_HEIGHT, size[1] as f64)
-            .ok();
-
-        let video_capture = VideoCapture::new(device, capture, 1.0);
+        let video_capture = VideoCapture::new(device, Box::new(capture), 1.0, settings.transform);
 
+        self.error = None;
         self.source = ImageSource::Webcam(video_capture);
     }
 
     pub fn get_error(&self) -> Option<IsfInputError> {
+        if let Some(error) = &self.error {
+            return Some(IsfInputError {
+                msg: error.clone(),
+                ty: String::from("Webcam"),
+            });
+        }
+
         match &self.source {
             ImageSource::Image(image_state) => match image_state.get_error() {
                 Some(error) => Some(IsfInputError {
@@ -284,9 +410,15 @@ impl ImageInput {
 
 #[derive(Debug)]
 pub enum IsfInputData {
-    Event { happening: bool },
+    Event {
+        happening: bool,
+        trigger: Option<AudioTrigger>,
+    },
     Bool(bool),
-    Long { value: i32, selected: usize },
+    Long {
+        value: i32,
+        selected: usize,
+    },
     Float(f32),
     Point2d(Point2),
     Color(LinSrgba),
@@ -295,6 +427,29 @@ pub enum IsfInputData {
     AudioFft(AudioFftUniforms),
 }
 
+fn channel_mode(extension: &InputExtension) -> ChannelMode {
+    extension.channels.unwrap_or(DEFAULT_AUDIO_CHANNELS)
+}
+
+/// Start the audio trigger of an event input if it has one.
+fn create_trigger(
+    audio_source: &mut AudioSource,
+    input: &isf::Input,
+    extension: &InputExtension,
+) -> Option<AudioTrigger> {
+    let mut trigger = AudioTrigger::new(
+        extension.trigger.clone()?,
+        format!("trigger_{}", input.name),
+    );
+    trigger.start_session(audio_source);
+    Some(trigger)
+}
+
+/// ISF audio inputs are unsmoothed unless the input requests an envelope.
+fn envelope_settings(extension: &InputExtension) -> EnvelopeSettings {
+    extension.envelope.unwrap_or_else(EnvelopeSettings::instant)
+}
+
 /// Given a path to a directory, produces the paths of all images within it.
 fn image_paths(dir: &Path) -> impl Iterator<Item = PathBuf> {
     walkdir::WalkDir::new(dir)
@@ -313,10 +468,14 @@ impl IsfInputData {
         images_path: &Path,
         audio_source: &mut AudioSource,
         input: &isf::Input,
+        extension: &InputExtension,
         _size: [u32; 2],
     ) -> Self {
         match &input.ty {
-            isf::InputType::Event => IsfInputData::Event { happening: false },
+            isf::InputType::Event => IsfInputData::Event {
+                happening: false,
+                trigger: create_trigger(audio_source, input, extension),
+            },
             isf::InputType::Bool(b) => IsfInputData::Bool(b.default.unwrap_or_default()),
             isf::InputType::Long(n) => {
                 let init = n
@@ -351,7 +510,11 @@ impl IsfInputData {
                 IsfInputData::Color(lin_srgba(red, green, blue, alpha))
             }
             isf::InputType::Image => {
-                let mut image_input = ImageInput::new();
+                let mut image_input = ImageInput::new(
+                    extension.transform.unwrap_or_default(),
+                    extension.sequence.unwrap_or_default(),
+                    extension.animation.unwrap_or_default(),
+                );
                 // mage_input.start_webcam(device, pt2(size[0] as f32, size[1] as f32));
                 if let Some(path) = image_paths(images_path).next() {
                     image_input.load_image(device, encoder, image_loader, path);
@@ -361,13 +524,17 @@ impl IsfInputData {
             isf::InputType::Audio(a) => {
                 let n_samples = a.num_samples.unwrap_or(DEFAULT_AUDIO_SAMPLE_COUNT);
                 let mut audio = AudioUniforms::new(device, Some(n_samples as usize));
+                audio.set_channel_mode(device, channel_mode(extension));
                 audio.start_session(audio_source);
                 IsfInputData::Audio(audio)
             }
             isf::InputType::AudioFft(a) => {
                 let n_columns = a.num_columns.unwrap_or(DEFAULT_AUDIO_FFT_COLUMNS);
                 let mut audio_fft = AudioFftUniforms::new(device, Some(n_columns as usize));
-                audio_fft.smoothing = 0.0;
+                audio_fft.envelope.settings = envelope_settings(extension);
+                audio_fft.set_channel_mode(device, channel_mode(extension));
+                audio_fft.set_history_size(device, extension.history.unwrap_or(0) as usize);
+                audio_fft.set_fft_settings(extension.fft_settings());
                 audio_fft.start_session(audio_source);
                 IsfInputData::AudioFft(audio_fft)
             }
@@ -383,10 +550,22 @@ impl IsfInputData {
         images_path: &Path,
         audio_source: &mut AudioSource,
         input: &isf::Input,
+        extension: &InputExtension,
         size: [u32; 2],
     ) -> bool {
         match (self, &input.ty) {
-            (IsfInputData::Event { .. }, isf::InputType::Event) => (),
+            (IsfInputData::Event { happening, trigger }, isf::InputType::Event) => {
+                if trigger.as_ref().map(|t| &t.trigger) != extension.trigger.as_ref() {
+                    if let Some(mut t) = trigger.take() {
+                        t.end_session(audio_source);
+                    }
+                    *trigger = create_trigger(audio_source, input, extension);
+                }
+
+                if trigger.as_ref().map_or(false, |t| t.poll()) {
+                    *happening = true;
+                }
+            }
             (IsfInputData::Bool(_), isf::InputType::Bool(_)) => (),
             (IsfInputData::Long { .. }, isf::InputType::Long(_)) => {}
             (IsfInputData::Float(_), isf::InputType::Float(_)) => {}
@@ -400,21 +579,52 @@ impl IsfInputData {
                         }
                     }
                     ImageSource::Image(image_state) => {
+                        let mut updated = false;
                         if let Some(path) = image_paths(images_path).next() {
-                            return image_state.update(device, encoder, image_loader, path);
+                            updated = image_state.update(device, encoder, image_loader, path);
                         }
+                        let replaced = image_input.transform_image(device, encoder);
+                        image_input.play_animation(device, encoder);
+                        return replaced || updated;
                     }
-                    ImageSource::Video(ref mut video) | ImageSource::Webcam(ref mut video) => {
-                        video.update();
+                    ImageSource::Video(ref mut video) => {
+                        let replaced = video.set_transform(device, image_input.transform);
+                        video.update(device);
+                        video.update_texture(device, encoder);
+                        return replaced;
+                    }
+                    ImageSource::Webcam(ref mut video) => {
+                        video.update(device);
                         video.update_texture(device, encoder);
                     }
                 }
             }
             (IsfInputData::Audio(audio), isf::InputType::Audio(_)) => {
+                if audio.channel_mode != channel_mode(extension) {
+                    audio.end_session(audio_source);
+                    audio.set_channel_mode(device, channel_mode(extension));
+                    audio.start_session(audio_source);
+                    return true;
+                }
+
                 audio.update();
                 audio.update_texture(device, encoder);
             }
             (IsfInputData::AudioFft(audio_fft), isf::InputType::AudioFft(_)) => {
+                let history_size = extension.history.unwrap_or(0) as usize;
+                if audio_fft.history_size != history_size
+                    || audio_fft.channel_mode != channel_mode(extension)
+                {
+                    audio_fft.end_session(audio_source);
+                    audio_fft.set_channel_mode(device, channel_mode(extension));
+                    audio_fft.set_history_size(device, history_size);
+                    audio_fft.start_session(audio_source);
+                    return true;
+                }
+
+                // the running analysis thread picks up new fft settings without a restart
+                audio_fft.set_fft_settings(extension.fft_settings());
+                audio_fft.envelope.settings = envelope_settings(extension);
                 audio_fft.update();
                 audio_fft.update_texture(device, encoder);
             }
@@ -426,6 +636,7 @@ impl IsfInputData {
                     images_path,
                     audio_source,
                     input,
+                    extension,
                     size,
                 )
             }
@@ -447,6 +658,12 @@ impl IsfInputData {
             IsfInputData::AudioFft(audio_fft) => {
                 audio_fft.end_session(audio_source);
             }
+            // dropping the trigger makes the next update start a new one
+            IsfInputData::Event { trigger, .. } => {
+                if let Some(mut t) = trigger.take() {
+                    t.end_session(audio_source);
+                }
+            }
             _ => (),
         }
     }
@@ -465,6 +682,12 @@ impl IsfInputData {
             IsfInputData::AudioFft(audio_fft) => {
                 audio_fft.end_session(audio_source);
             }
+            IsfInputData::Event {
+                trigger: Some(trigger),
+                ..
+            } => {
+                trigger.end_session(audio_source);
+            }
             _ => (),
         }
     }
@@ -483,6 +706,12 @@ impl IsfInputData {
             IsfInputData::AudioFft(audio_fft) => {
                 audio_fft.start_session(audio_source);
             }
+            IsfInputData::Event {
+                trigger: Some(trigger),
+                ..
+            } => {
+                trigger.start_session(audio_source);
+            }
             _ => (),
         }
     }
@@ -496,7 +725,7 @@ impl IsfInputData {
 
     fn reset_event(&mut self) {
         match self {
-            IsfInputData::Event { happening } => {
+            IsfInputData::Event { happening, .. } => {
                 *happening = false;
             }
             _ => (),
@@ -603,6 +832,136 @@ impl IsfData {
         });
     }
 
+    /// Route the soundtrack of the first video input that should be analyzed to the audio
+    /// source, and apply the source's latency compensation to all video soundtracks.
+    pub fn sync_video_audio(&mut self, audio_source: &mut AudioSource) {
+        let delay = audio_source::monitor_delay(audio_source.latency);
+        let mut feed = None;
+
+        for input in self.inputs.values_mut() {
+            if let IsfInputData::Image(ImageInput {
+                source: ImageSource::Video(video),
+                ..
+            }) = input
+            {
+                video.set_audio_delay(delay);
+                feed = feed.or_else(|| video.audio_feed());
+            }
+        }
+
+        audio_source.set_feed(feed);
+    }
+
+    /// Show the frames of all video inputs at `time` seconds, see `VideoCapture::decode_at`.
+    pub fn decode_videos(&mut self, time: f64) {
+        for input in self.inputs.values_mut() {
+            if let IsfInputData::Image(ImageInput {
+                source: ImageSource::Video(video),
+                ..
+            }) = input
+            {
+                video.decode_at(time);
+            }
+        }
+    }
+
+    /// Reopen the inputs capturing from a camera with its new settings.
+    pub fn restart_webcams(
+        &mut self,
+        device: &wgpu::Device,
+        size: Point2,
+        index: usize,
+        settings: &WebcamSettings,
+    ) {
+        for input in self.inputs.values_mut() {
+            if let IsfInputData::Image(image_input) = input {
+                if matches!(image_input.source, ImageSource::Webcam(_))
+                    && image_input.webcam == index
+                {
+                    image_input.start_webcam(device, size, index, settings);
+                }
+            }
+        }
+    }
+
+    /// Change the transform of the inputs capturing from a camera.
+    /// Returns true when a texture was replaced.
+    pub fn transform_webcams(
+        &mut self,
+        device: &wgpu::Device,
+        index: usize,
+        transform: SourceTransform,
+    ) -> bool {
+        let mut replaced = false;
+        for input in self.inputs.values_mut() {
+            if let IsfInputData::Image(ImageInput {
+                source: ImageSource::Webcam(webcam),
+                webcam: i,
+                ..
+            }) = input
+            {
+                if *i == index && webcam.set_transform(device, transform) {
+                    replaced = true;
+                }
+            }
+        }
+        replaced
+    }
+
+    /// The first running input capturing from a camera.
+    pub fn webcam(&self, index: usize) -> Option<&VideoCapture> {
+        self.inputs.values().find_map(|input| match input {
+            IsfInputData::Image(ImageInput {
+                source: ImageSource::Webcam(webcam),
+                webcam: i,
+                ..
+            }) if *i == index => Some(webcam),
+            _ => None,
+        })
+    }
+
+    /// The first running input capturing from a camera, mutably.
+    pub fn webcam_mut(&mut self, index: usize) -> Option<&mut VideoCapture> {
+        self.inputs.values_mut().find_map(|input| match input {
+            IsfInputData::Image(ImageInput {
+                source: ImageSource::Webcam(webcam),
+                webcam: i,
+                ..
+            }) if *i == index => Some(webcam),
+            _ => None,
+        })
+    }
+
+    /// The video or webcam of an image input.
+    pub fn capture(&self, name: &str) -> Option<&VideoCapture> {
+        match self.inputs.get(name) {
+            Some(IsfInputData::Image(ImageInput {
+                source: ImageSource::Video(capture),
+                ..
+            }))
+            | Some(IsfInputData::Image(ImageInput {
+                source: ImageSource::Webcam(capture),
+                ..
+            })) => Some(capture),
+            _ => None,
+        }
+    }
+
+    /// The video or webcam of an image input, mutably.
+    pub fn capture_mut(&mut self, name: &str) -> Option<&mut VideoCapture> {
+        match self.inputs.get_mut(name) {
+            Some(IsfInputData::Image(ImageInput {
+                source: ImageSource::Video(capture),
+                ..
+            }))
+            | Some(IsfInputData::Image(ImageInput {
+                source: ImageSource::Webcam(capture),
+                ..
+            })) => Some(capture),
+            _ => None,
+        }
+    }
+
     pub fn pause(&mut self, audio_source: &mut AudioSource) {
         self.inputs
             .iter_mut()
@@ -705,6 +1064,7 @@ pub fn sync_isf_data(
     image_loader: &ImageLoader,
     images_path: &Path,
     audio_source: &mut AudioSource,
+    extensions: &InputExtensions,
     isf_data: &mut IsfData,
     num_samples: u32,
 ) -> bool {
@@ -737,6 +1097,7 @@ pub fn sync_isf_data(
 
     // Update input data
     for input in &isf.inputs {
+        let extension = extensions.get(&input.name).cloned().unwrap_or_default();
         let input_data = isf_data
             .inputs
             .entry(input.name.clone())
@@ -748,6 +1109,7 @@ pub fn sync_isf_data(
                     images_path,
                     audio_source,
                     input,
+                    &extension,
                     output_attachment_size,
                 )
             });
@@ -758,6 +1120,7 @@ pub fn sync_isf_data(
             images_path,
             audio_source,
             input,
+            &extension,
             output_attachment_size,
         ) {
             textures_updated = true;
@@ -847,17 +1210,21 @@ pub fn isf_data_textures<'a>(isf_data: &'a IsfData, isf: &'a isf::Isf) -> Vec<&'
 
         let texture = match input {
             IsfInputData::Image(ref img_input) => match &img_input.source {
+                ImageSource::Image(_) if img_input.transformed.is_some() => {
+                    &img_input.transformed.as_ref().unwrap().texture
+                }
                 ImageSource::Image(ref image_state) => match &image_state {
                     ImageState::Ready(Ok(ref data)) => &data.texture,
                     ImageState::Loading(ref loading_image) => &loading_image.texture,
                     _ => continue,
                 },
-                ImageSource::Video(ref video) | ImageSource::Webcam(ref video) => {
-                    &video.video_texture
-                }
+                ImageSource::Video(ref video) | ImageSource::Webcam(ref video) => video.texture(),
                 _ => continue,
             },
             IsfInputData::Audio(audio) => &audio.audio_texture,
+            IsfInputData::AudioFft(audio_fft) if audio_fft.history_size > 0 => {
+                &audio_fft.history_texture
+            }
             IsfInputData::AudioFft(audio_fft) => &audio_fft.spectrum_texture,
             _ => continue,
         };
@@ -912,12 +1279,17 @@ pub fn get_isf_input_uniforms_bytes_vec(isf_opt: &Option<isf::Isf>, isf_data: &I
     for input in &isf.inputs {
         let data = data_inputs.get(&input.name).unwrap();
         match data {
-            IsfInputData::Event { happening } => bytes.extend(int_as_bytes(&(*happening as i32))),
+            IsfInputData::Event { happening, .. } => {
+                bytes.extend(int_as_bytes(&(*happening as i32)))
+            }
             IsfInputData::Bool(val) => bytes.extend(int_as_bytes(&(*val as i32))),
             IsfInputData::Float(val) => bytes.extend(float_as_bytes(val)),
             IsfInputData::Long { value, .. } => bytes.extend(int_as_bytes(value)),
             IsfInputData::Point2d(point) => bytes.extend(point_as_bytes(point)),
             IsfInputData::Color(color) => bytes.extend(color_as_bytes(color)),
+            IsfInputData::AudioFft(audio_fft) if audio_fft.history_size > 0 => {
+                bytes.extend(int_as_bytes(&audio_fft.history_row()))
+            }
             _ => (),
         }
     }
@@ -0,0 +1,185 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::programs::uniforms::animated_image::AnimationSettings;
+use crate::programs::uniforms::audio_fft::{FftSettings, WindowFunction};
+use crate::programs::uniforms::audio_source::ChannelMode;
+use crate::programs::uniforms::audio_trigger::Trigger;
+use crate::programs::uniforms::envelope::EnvelopeSettings;
+use crate::programs::uniforms::faces::FaceDetectionSettings;
+use crate::programs::uniforms::image_sequence::ImageSequenceSettings;
+use crate::programs::uniforms::optical_flow::FlowSettings;
+use crate::programs::uniforms::source_transform::SourceTransform;
+
+/// Vendor extension keys that may be added to an ISF input alongside the standard ones.
+/// The `isf` crate ignores these, so they are read from the raw JSON header separately.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct InputExtension {
+    /// How input channels map to the rows of an `audio` or `audioFFT` input's texture.
+    #[serde(rename = "CHANNELS")]
+    pub channels: Option<ChannelMode>,
+    /// Envelope follower applied to the bands of an `audioFFT` input.
+    #[serde(rename = "ENVELOPE")]
+    pub envelope: Option<EnvelopeSettings>,
+    /// Number of past spectra kept in an `audioFFT` input's history texture.
+    #[serde(rename = "HISTORY")]
+    pub history: Option<u32>,
+    /// FFT window size of an `audioFFT` input.
+    #[serde(rename = "FFT_SIZE")]
+    pub fft_size: Option<usize>,
+    /// Window function of an `audioFFT` input.
+    #[serde(rename = "WINDOW")]
+    pub window: Option<WindowFunction>,
+    /// Fraction of overlap between consecutive windows of an `audioFFT` input.
+    #[serde(rename = "OVERLAP")]
+    pub overlap: Option<f32>,
+    /// Audio trigger that fires an `event` input.
+    #[serde(rename = "TRIGGER")]
+    pub trigger: Option<Trigger>,
+    /// Initial transform of the image and video sources of an `image` input.
+    #[serde(rename = "TRANSFORM")]
+    pub transform: Option<SourceTransform>,
+    /// How image sequences chosen as the video source of an `image` input are played.
+    #[serde(rename = "SEQUENCE")]
+    pub sequence: Option<ImageSequenceSettings>,
+    /// How animated GIF and PNG images loaded into an `image` input are played.
+    #[serde(rename = "ANIMATION")]
+    pub animation: Option<AnimationSettings>,
+}
+
+impl InputExtension {
+    /// The FFT settings requested by this input, falling back on the defaults.
+    pub fn fft_settings(&self) -> FftSettings {
+        let defaults = FftSettings::default();
+        FftSettings {
+            size: self.fft_size.unwrap_or(defaults.size),
+            window: self.window.unwrap_or(defaults.window),
+            overlap: self.overlap.unwrap_or(defaults.overlap),
+        }
+        .clamped()
+    }
+}
+
+pub type InputExtensions = HashMap<String, InputExtension>;
+
+/// The `FLOW` key at the top level of an ISF header.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct FlowExtension {
+    /// Name of the image input the flow is computed from, the first one playing a video or
+    /// capturing a webcam when `None`.
+    #[serde(rename = "INPUT", default)]
+    pub input: Option<String>,
+    #[serde(flatten)]
+    pub settings: FlowSettings,
+}
+
+/// Vendor extension keys that may be added to the top level of an ISF header.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct HeaderExtension {
+    /// Bind the `AUDIO_FEATURES` uniform block and `AUDIO_MFCC` texture from mirlin.
+    #[serde(rename = "AUDIO_FEATURES", default)]
+    pub audio_features: bool,
+    /// Bind the `FACES` uniform block with the faces found in the first camera's webcam inputs.
+    #[serde(rename = "FACES")]
+    pub faces: Option<FaceDetectionSettings>,
+    /// Bind the `FLOW` uniform block and `FLOW_IMAGE` texture with the optical flow of an
+    /// image input.
+    #[serde(rename = "FLOW")]
+    pub flow: Option<FlowExtension>,
+}
+
+/// Extract the JSON header from the top comment of an ISF shader.
+fn json_header(glsl_str: &str) -> Option<&str> {
+    let start = glsl_str.find("/*")? + 2;
+    let end = start + glsl_str[start..].find("*/")?;
+    Some(&glsl_str[start..end])
+}
+
+fn parse_json_header(glsl_str: &str) -> Option<Value> {
+    json_header(glsl_str).and_then(|h| serde_json::from_str(h).ok())
+}
+
+/// Parse the vendor extension keys of each input, keyed by input name.
+/// Inputs without any recognised extension keys are still included with defaults.
+pub fn parse_input_extensions(glsl_str: &str) -> InputExtensions {
+    let mut extensions = InputExtensions::new();
+
+    let header = match parse_json_header(glsl_str) {
+        Some(v) => v,
+        None => return extensions,
+    };
+
+    let inputs = match header.get("INPUTS").and_then(|i| i.as_array()) {
+        Some(i) => i,
+        None => return extensions,
+    };
+
+    for input in inputs {
+        let name = match input.get("NAME").and_then(|n| n.as_str()) {
+            Some(n) => n.to_string(),
+            None => continue,
+        };
+
+        let extension = serde_json::from_value(input.clone()).unwrap_or_default();
+        extensions.insert(name, extension);
+    }
+
+    extensions
+}
+
+pub fn read_input_extensions_from_path(path: &Path) -> InputExtensions {
+    match std::fs::read_to_string(path) {
+        Ok(s) => parse_input_extensions(&s),
+        Err(_) => InputExtensions::new(),
+    }
+}
+
+/// Parse the vendor extension keys at the top level of the header.
+pub fn parse_header_extension(glsl_str: &str) -> HeaderExtension {
+    parse_json_header(glsl_str)
+        .and_then(|h| serde_json::from_value(h).ok())
+        .unwrap_or_default()
+}
+
+pub fn read_header_extension_from_path(path: &Path) -> HeaderExtension {
+    match std::fs::read_to_string(path) {
+        Ok(s) => parse_header_extension(&s),
+        Err(_) => HeaderExtension::default(),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_parse_input_extensions() {
+    let glsl = r#"/*{
+        "INPUTS": [
+            { "NAME": "fft", "TYPE": "audioFFT", "HISTORY": 64 },
+            { "NAME": "amount", "TYPE": "float" }
+        ]
+    }*/
+    void main() {}"#;
+    let extensions = parse_input_extensions(glsl);
+    assert_eq!(extensions.get("fft").unwrap().history, Some(64));
+    assert_eq!(extensions.get("amount").unwrap().history, None);
+    assert!(!parse_header_extension(glsl).audio_features);
+
+    assert_eq!(parse_header_extension(glsl).faces, None);
+
+    let glsl = r#"/*{ "AUDIO_FEATURES": true, "INPUTS": [] }*/"#;
+    assert!(parse_header_extension(glsl).audio_features);
+
+    let glsl = r#"/*{ "FACES": { "scale": 0.25 }, "INPUTS": [] }*/"#;
+    let faces = parse_header_extension(glsl).faces.unwrap();
+    assert_eq!(faces.scale, 0.25);
+    assert_eq!(faces.model, "haarcascade_frontalface_default.xml");
+
+    let glsl = r#"/*{ "FLOW": { "INPUT": "inputImage", "scale": 0.5 }, "INPUTS": [] }*/"#;
+    let flow = parse_header_extension(glsl).flow.unwrap();
+    assert_eq!(flow.input.as_deref(), Some("inputImage"));
+    assert_eq!(flow.settings.scale, 0.5);
+    assert_eq!(flow.settings.levels, 3);
+    let glsl = r#"/*{ "FLOW": {}, "INPUTS": [] }*/"#;
+    assert_eq!(parse_header_extension(glsl).flow.unwrap().input, None);
+}
@@ -6,9 +6,20 @@ use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use threadpool::ThreadPool;
 
+use crate::programs::uniforms::audio_features::AudioFeaturesUniforms;
+use crate::programs::uniforms::audio_generator::GeneratorSettings;
 use crate::programs::uniforms::audio_source::AudioSource;
+use crate::programs::uniforms::base::UniformBuffer;
+use crate::programs::uniforms::faces::FaceUniforms;
+use crate::programs::uniforms::input_gain::GainSettings;
+use crate::programs::uniforms::optical_flow::FlowUniforms;
+use crate::programs::uniforms::source_transform::SourceTransform;
+use crate::programs::uniforms::video_audio::VideoAudioSettings;
+use crate::programs::uniforms::video_capture::VideoCapture;
+use crate::programs::uniforms::webcam_devices::WebcamSettings;
 
 pub mod data;
+pub mod extensions;
 mod shader;
 mod util;
 
@@ -50,12 +61,23 @@ pub struct IsfTime {
 pub struct IsfPipeline {
     pub isf: Option<isf::Isf>,
     pub isf_data: data::IsfData,
+    pub extensions: extensions::InputExtensions,
+    pub header: extensions::HeaderExtension,
     pub widget_ids: Option<HashMap<String, widget::Id>>,
     pub isf_err: Option<util::IsfError>,
     pub image_loader: data::ImageLoader,
     pub updated: bool,
     pub pass_index: u32,
+    /// How the soundtracks of video inputs are used.
+    pub video_audio: VideoAudioSettings,
+    /// Whether video inputs follow the render clock instead of playing freely.
+    pub video_sync: bool,
+    /// Cameras image inputs can capture from.
+    pub webcams: Vec<WebcamSettings>,
     audio_source: AudioSource,
+    audio_features: Option<(AudioFeaturesUniforms, UniformBuffer)>,
+    faces: Option<(FaceUniforms, UniformBuffer)>,
+    flow: Option<(FlowUniforms, UniformBuffer)>,
     vs: shader::Shader,
     fs: shader::Shader,
     sampler: wgpu::Sampler,
@@ -139,6 +161,8 @@ fn create_pipeline_layout(
     device.create_pipeline_layout(&desc)
 }
 
+/// Passes replace their target without blending, so alpha written by one pass is sampled
+/// unchanged by the next.
 fn create_render_pipeline(
     device: &wgpu::Device,
     layout: &wgpu::PipelineLayout,
@@ -153,6 +177,8 @@ fn create_render_pipeline(
         .add_vertex_buffer::<Vertex>(&wgpu::vertex_attr_array![0 => Float2])
         .sample_count(sample_count)
         .primitive_topology(wgpu::PrimitiveTopology::TriangleStrip)
+        .color_blend(wgpu::BlendDescriptor::REPLACE)
+        .alpha_blend(wgpu::BlendDescriptor::REPLACE)
         .build(device)
 }
 
@@ -162,6 +188,66 @@ fn build_uniform_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayo
         .build(device)
 }
 
+// Create the audio features uniforms and subscribe them to the audio source if the header asks
+// for them.
+fn create_audio_features(
+    device: &wgpu::Device,
+    header: &extensions::HeaderExtension,
+    audio_source: &mut AudioSource,
+) -> Option<(AudioFeaturesUniforms, UniformBuffer)> {
+    if !header.audio_features {
+        return None;
+    }
+
+    let mut audio_features = AudioFeaturesUniforms::new(device);
+    audio_features.start_session(audio_source);
+    let uniform_buffer = UniformBuffer::new(device, &audio_features);
+    Some((audio_features, uniform_buffer))
+}
+
+// Create the face uniforms if the header asks for them, models are loaded from `images_path`.
+fn create_faces(
+    device: &wgpu::Device,
+    header: &extensions::HeaderExtension,
+    images_path: &Path,
+) -> Option<(FaceUniforms, UniformBuffer)> {
+    let settings = header.faces.clone()?;
+    let mut faces = FaceUniforms::new();
+    faces.set_settings(images_path.to_path_buf(), settings);
+    let uniform_buffer = UniformBuffer::new(device, &faces);
+    Some((faces, uniform_buffer))
+}
+
+// Create the flow uniforms if the header asks for them.
+fn create_flow(
+    device: &wgpu::Device,
+    header: &extensions::HeaderExtension,
+) -> Option<(FlowUniforms, UniformBuffer)> {
+    let settings = header.flow.as_ref()?.settings;
+    let mut flow = FlowUniforms::new(device);
+    flow.set_settings(settings);
+    let uniform_buffer = UniformBuffer::new(device, &flow);
+    Some((flow, uniform_buffer))
+}
+
+// The video or webcam the flow is computed from, the image input named by the `FLOW` key or
+// else the first one playing a video or capturing a webcam.
+fn flow_capture<'a>(
+    isf: &isf::Isf,
+    flow: &extensions::FlowExtension,
+    isf_data: &'a mut data::IsfData,
+) -> Option<&'a mut VideoCapture> {
+    let name = match &flow.input {
+        Some(name) => name.clone(),
+        None => isf
+            .inputs
+            .iter()
+            .map(|input| input.name.clone())
+            .find(|name| isf_data.capture(name).is_some())?,
+    };
+    isf_data.capture_mut(&name)
+}
+
 impl IsfPipeline {
     pub fn new(
         device: &wgpu::Device,
@@ -176,6 +262,8 @@ impl IsfPipeline {
     ) -> Self {
         let isf_res = util::read_isf_from_path(&fs_path);
         let (isf, error) = util::split_result(isf_res);
+        let extensions = extensions::read_input_extensions_from_path(&fs_path);
+        let header = extensions::read_header_extension_from_path(&fs_path);
 
         // Create the shaders
         let fs = shader::Shader::fragment_from_path(device, fs_path);
@@ -204,6 +292,7 @@ impl IsfPipeline {
                 &image_loader,
                 &images_path,
                 &mut audio_source,
+                &extensions,
                 &mut isf_data,
                 num_samples,
             );
@@ -267,6 +356,21 @@ impl IsfPipeline {
             bind_group_layouts.push(isf_inputs_bind_group_layout.as_ref().unwrap());
         }
 
+        let audio_features = create_audio_features(device, &header, &mut audio_source);
+        if let Some((_, ref uniform_buffer)) = audio_features {
+            bind_group_layouts.push(&uniform_buffer.bind_group_layout);
+        }
+
+        let faces = create_faces(device, &header, images_path);
+        if let Some((_, ref uniform_buffer)) = faces {
+            bind_group_layouts.push(&uniform_buffer.bind_group_layout);
+        }
+
+        let flow = create_flow(device, &header);
+        if let Some((_, ref uniform_buffer)) = flow {
+            bind_group_layouts.push(&uniform_buffer.bind_group_layout);
+        }
+
         // Create the render pipeline.
         let layout = create_pipeline_layout(device, &bind_group_layouts);
         let render_pipeline = match (vs.module.as_ref(), fs.module.as_ref()) {
@@ -298,11 +402,19 @@ impl IsfPipeline {
         Self {
             isf,
             isf_data,
+            extensions,
+            header,
             isf_err: error,
             widget_ids: None,
             updated: false,
             audio_source,
+            audio_features,
+            faces,
+            flow,
             pass_index: 0,
+            video_audio: VideoAudioSettings::default(),
+            video_sync: false,
+            webcams: vec![],
             image_loader,
             vs,
             fs,
@@ -372,11 +484,23 @@ impl IsfPipeline {
                 // Update the `Isf` instance.
                 let isf_res = util::read_isf_from_path(&path);
                 let (new_isf, new_isf_err) = util::split_result(isf_res);
+                let new_extensions = extensions::read_input_extensions_from_path(&path);
+                let new_header = extensions::read_header_extension_from_path(&path);
                 self.isf_err = new_isf_err;
-                if (self.isf.is_none() || new_isf.is_some()) && self.isf != new_isf {
+                if (self.isf.is_none() || new_isf.is_some())
+                    && (self.isf != new_isf
+                        || self.extensions != new_extensions
+                        || self.header != new_header)
+                {
                     isf_updated = true;
                     self.isf = new_isf;
-                    self.isf_data.end_session(&mut self.audio_source);
+                    self.extensions = new_extensions;
+                    self.end_session();
+                    self.header = new_header;
+                    self.audio_features =
+                        create_audio_features(device, &self.header, &mut self.audio_source);
+                    self.faces = create_faces(device, &self.header, images_path);
+                    self.flow = create_flow(device, &self.header);
                 }
             }
         }
@@ -390,6 +514,15 @@ impl IsfPipeline {
             Some(ref isf) => isf,
         };
 
+        // Detect audio device loss and reconnect.
+        self.isf_data.sync_video_audio(&mut self.audio_source);
+        self.audio_source.update();
+
+        if let Some((ref mut audio_features, _)) = self.audio_features {
+            audio_features.update();
+            audio_features.update_texture(device, encoder);
+        }
+
         // Synchronise the ISF data.
         let textures_updated = data::sync_isf_data(
             device,
@@ -399,10 +532,30 @@ impl IsfPipeline {
             &self.image_loader,
             images_path,
             &mut self.audio_source,
+            &self.extensions,
             &mut self.isf_data,
             num_samples,
         );
 
+        if let Some((ref mut faces, _)) = self.faces {
+            if let Some(capture) = self.isf_data.webcam_mut(0) {
+                faces.update(capture);
+            }
+        }
+
+        if let (Some(settings), Some((flow, uniform_buffer))) = (&self.header.flow, &mut self.flow)
+        {
+            if let Some(capture) = flow_capture(isf, settings, &mut self.isf_data) {
+                flow.update(device, capture);
+                // the bind group holds the flow texture, which is replaced when its size changes
+                if flow.updated {
+                    flow.updated = false;
+                    *uniform_buffer = UniformBuffer::new(device, flow);
+                    self.updated = true;
+                }
+            }
+        }
+
         // rebuild input buffer if isf config updated
         if isf_updated {
             let isf_input_uniforms_bytes_vec =
@@ -455,6 +608,18 @@ impl IsfPipeline {
                 bind_group_layouts.push(isf_inputs_bind_group_layout);
             }
 
+            if let Some((_, ref uniform_buffer)) = self.audio_features {
+                bind_group_layouts.push(&uniform_buffer.bind_group_layout);
+            }
+
+            if let Some((_, ref uniform_buffer)) = self.faces {
+                bind_group_layouts.push(&uniform_buffer.bind_group_layout);
+            }
+
+            if let Some((_, ref uniform_buffer)) = self.flow {
+                bind_group_layouts.push(&uniform_buffer.bind_group_layout);
+            }
+
             self.layout = create_pipeline_layout(device, &bind_group_layouts);
         }
 
@@ -532,6 +697,19 @@ impl IsfPipeline {
                 encoder.copy_buffer_to_buffer(&new_buffer, 0, &uniform_buffer, 0, inputs_size);
             }
 
+            if let Some((ref audio_features, ref uniform_buffer)) = self.audio_features {
+                uniform_buffer.update(device, encoder, audio_features);
+            }
+
+            if let Some((ref faces, ref uniform_buffer)) = self.faces {
+                uniform_buffer.update(device, encoder, faces);
+            }
+
+            if let Some((ref flow, ref uniform_buffer)) = self.flow {
+                flow.update_texture(device, encoder);
+                uniform_buffer.update(device, encoder, flow);
+            }
+
             // Encode the render pass.
             let mut render_pass = wgpu::RenderPassBuilder::new()
                 .color_attachment(dst_texture, |color| color)
@@ -540,8 +718,21 @@ impl IsfPipeline {
             render_pass.set_vertex_buffer(0, &self.vertex_buffer, 0, 0);
             render_pass.set_bind_group(0, &self.isf_bind_group, &[]);
             render_pass.set_bind_group(1, &self.isf_textures_bind_group, &[]);
+            let mut set = 2;
             if let Some(ref bind_group) = self.isf_inputs_bind_group {
-                render_pass.set_bind_group(2, &bind_group, &[]);
+                render_pass.set_bind_group(set, &bind_group, &[]);
+                set += 1;
+            }
+            if let Some((_, ref uniform_buffer)) = self.audio_features {
+                render_pass.set_bind_group(set, &uniform_buffer.bind_group, &[]);
+                set += 1;
+            }
+            if let Some((_, ref uniform_buffer)) = self.faces {
+                render_pass.set_bind_group(set, &uniform_buffer.bind_group, &[]);
+                set += 1;
+            }
+            if let Some((_, ref uniform_buffer)) = self.flow {
+                render_pass.set_bind_group(set, &uniform_buffer.bind_group, &[]);
             }
 
             let vertex_range = 0..VERTICES.len() as u32;
@@ -587,6 +778,95 @@ impl IsfPipeline {
         self.audio_source.error.clone()
     }
 
+    /// Feed the audio inputs from the signal generator instead of an input device.
+    pub fn set_audio_generator(&mut self, generator: Option<GeneratorSettings>) {
+        self.audio_source.set_generator(generator);
+    }
+
+    /// Set the input gain applied to all audio inputs.
+    pub fn set_audio_gain(&mut self, gain: GainSettings) {
+        self.audio_source.set_gain(gain);
+    }
+
+    /// Set the audio/visual latency compensation in ms.
+    pub fn set_audio_latency(&mut self, latency: f32) {
+        self.audio_source.set_latency(latency);
+    }
+
+    pub fn audio_source(&self) -> &AudioSource {
+        &self.audio_source
+    }
+
+    /// Show the frames of video inputs at `time` seconds of the render clock, if synced.
+    pub fn decode_videos(&mut self, time: f64) {
+        if self.video_sync {
+            self.isf_data.decode_videos(time);
+        }
+    }
+
+    /// Switch the devices of the cameras, reopening the webcam inputs of those that changed.
+    pub fn set_webcams(&mut self, device: &wgpu::Device, webcams: &[WebcamSettings]) {
+        let size = pt2(
+            self.dst_texture_size[0] as f32,
+            self.dst_texture_size[1] as f32,
+        );
+
+        for (index, settings) in webcams.iter().enumerate() {
+            let current = self.webcams.get(index);
+            if current == Some(settings) {
+                continue;
+            }
+
+            if current.map_or(false, |c| c.same_capture(settings)) {
+                if self
+                    .isf_data
+                    .transform_webcams(device, index, settings.transform)
+                {
+                    self.updated = true;
+                }
+                continue;
+            }
+
+            self.isf_data.restart_webcams(device, size, index, settings);
+            self.updated = true;
+        }
+
+        self.webcams = webcams.to_vec();
+    }
+
+    /// The first running webcam input of a camera.
+    pub fn webcam(&self, index: usize) -> Option<&VideoCapture> {
+        self.isf_data.webcam(index)
+    }
+
+    /// Names of the image inputs showing an image or video, in declaration order.
+    pub fn transformable_inputs(&self) -> Vec<String> {
+        let isf = match &self.isf {
+            Some(isf) => isf,
+            None => return vec![],
+        };
+
+        let inputs = self.isf_data.inputs();
+        isf.inputs
+            .iter()
+            .filter(|input| match inputs.get(&input.name) {
+                Some(data::IsfInputData::Image(image_input)) => {
+                    !matches!(image_input.source, data::ImageSource::Webcam(_))
+                }
+                _ => false,
+            })
+            .map(|input| input.name.clone())
+            .collect()
+    }
+
+    /// The transform of an image input.
+    pub fn input_transform_mut(&mut self, name: &str) -> Option<&mut SourceTransform> {
+        match self.isf_data.inputs_mut().get_mut(name) {
+            Some(data::IsfInputData::Image(image_input)) => Some(&mut image_input.transform),
+            _ => None,
+        }
+    }
+
     pub fn get_data_errors(&self) -> HashMap<String, Vec<String>> {
         let mut errors = self.isf_data.get_errors();
 
@@ -594,6 +874,24 @@ impl IsfPipeline {
             errors.insert(String::from("Audio"), vec![error]);
         }
 
+        if let Some((ref audio_features, _)) = self.audio_features {
+            if let Some(error) = audio_features.error.clone() {
+                errors.insert(String::from("Audio Features"), vec![error]);
+            }
+        }
+
+        if let Some((ref faces, _)) = self.faces {
+            if let Some(error) = faces.error.clone() {
+                errors.insert(String::from("Faces"), vec![error]);
+            }
+        }
+
+        if let Some((ref flow, _)) = self.flow {
+            if let Some(error) = flow.error.clone() {
+                errors.insert(String::from("Flow"), vec![error]);
+            }
+        }
+
         errors
     }
 
@@ -640,14 +938,23 @@ impl IsfPipeline {
 
     pub fn end_session(&mut self) {
         self.isf_data.end_session(&mut self.audio_source);
+        if let Some((ref mut audio_features, _)) = self.audio_features {
+            audio_features.end_session(&mut self.audio_source);
+        }
     }
 
     pub fn pause(&mut self) {
         self.isf_data.pause(&mut self.audio_source);
+        if let Some((ref mut audio_features, _)) = self.audio_features {
+            audio_features.end_session(&mut self.audio_source);
+        }
     }
 
     pub fn unpause(&mut self) {
         self.isf_data.unpause(&mut self.audio_source);
+        if let Some((ref mut audio_features, _)) = self.audio_features {
+            audio_features.start_session(&mut self.audio_source);
+        }
     }
 
     pub fn get_render_texture(&self, index: usize) -> &wgpu::Texture {
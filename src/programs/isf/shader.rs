@@ -5,7 +5,9 @@ use regex::Regex;
 use std::path::{Path, PathBuf};
 use thiserror::Error;
 
+use crate::programs::isf::extensions::{self, HeaderExtension, InputExtensions};
 use crate::programs::isf::util;
+use crate::programs::uniforms::faces::MAX_FACES;
 
 /// Errors that might occur while loading a shader.
 #[derive(Debug, Error)]
@@ -27,12 +29,28 @@ pub enum ShaderError {
     },
 }
 
+// Whether the given input has a spectrum history texture and so needs a write row uniform.
+fn input_has_history(input: &isf::Input, extensions: &InputExtensions) -> bool {
+    match input.ty {
+        isf::InputType::AudioFft(_) => extensions
+            .get(&input.name)
+            .and_then(|e| e.history)
+            .map_or(false, |h| h > 0),
+        _ => false,
+    }
+}
+
 // Check whether or not any of the given list of isf inputs would require the `IsfDataInputs`
 // uniform.
-fn inputs_require_isf_data_input(inputs: &[isf::Input]) -> bool {
+fn inputs_require_isf_data_input(inputs: &[isf::Input], extensions: &InputExtensions) -> bool {
     for input in inputs {
         match input.ty {
-            isf::InputType::Image | isf::InputType::Audio(_) | isf::InputType::AudioFft(_) => (),
+            isf::InputType::Image | isf::InputType::Audio(_) => (),
+            isf::InputType::AudioFft(_) => {
+                if input_has_history(input, extensions) {
+                    return true;
+                }
+            }
             _ => return true,
         }
     }
@@ -43,7 +61,11 @@ fn inputs_require_isf_data_input(inputs: &[isf::Input]) -> bool {
 /// from which the ISF was parsed.
 ///
 /// This string should be inserted directly after the version preprocessor.
-pub fn glsl_string_from_isf(isf: &isf::Isf) -> String {
+pub fn glsl_string_from_isf(
+    isf: &isf::Isf,
+    extensions: &InputExtensions,
+    header: &HeaderExtension,
+) -> String {
     // The normalised coords passed through from the vertex shader.
     let frag_norm_coord_str = "
         layout(location = 0) in vec2 isf_FragNormCoord;
@@ -107,8 +129,8 @@ pub fn glsl_string_from_isf(isf: &isf::Isf) -> String {
     }
 
     // Create the `IsfDataInputs` uniform buffer with a field for each event, float, long, bool,
-    // point2d and color.
-    let isf_data_input_str = match inputs_require_isf_data_input(&isf.inputs) {
+    // point2d and color, plus the write row of each audioFFT history texture.
+    let isf_data_input_str = match inputs_require_isf_data_input(&isf.inputs, extensions) {
         false => None,
         true => {
             let mut isf_data_input_string =
@@ -120,6 +142,10 @@ pub fn glsl_string_from_isf(isf: &isf::Isf) -> String {
                     isf::InputType::Float(_) => "float",
                     isf::InputType::Point2d(_) => "vec2",
                     isf::InputType::Color(_) => "vec4",
+                    isf::InputType::AudioFft(_) if input_has_history(input, extensions) => {
+                        isf_data_input_string.push_str(&format!("int {}_row;\n", input.name));
+                        continue;
+                    }
                     isf::InputType::Image
                     | isf::InputType::Audio(_)
                     | isf::InputType::AudioFft(_) => continue,
@@ -131,6 +157,84 @@ pub fn glsl_string_from_isf(isf: &isf::Isf) -> String {
         }
     };
 
+    // Create the `AUDIO_FEATURES` uniform buffer and `AUDIO_MFCC` texture in the set after the
+    // inputs. Binding 0 holds a sampler that is not needed since `img_sampler` can be used.
+    let audio_features_str = match header.audio_features {
+        false => None,
+        true => {
+            let set = match isf_data_input_str {
+                Some(_) => 3,
+                None => 2,
+            };
+            Some(format!(
+                "
+        layout(set = {set}, binding = 1) uniform texture2D AUDIO_MFCC;
+        layout(set = {set}, binding = 2) uniform AudioFeatures {{
+            float dissonance;
+            float energy;
+            float loudness;
+            float noisiness;
+            float onset;
+            float pitch;
+            float rms;
+            float spectralCentroid;
+            float spectralComplexity;
+            float spectralContrast;
+            float tristimulus1;
+            float tristimulus2;
+            float tristimulus3;
+            int key;
+            int keyScale;
+            float keyStrength;
+        }} AUDIO_FEATURES;
+    ",
+                set = set
+            ))
+        }
+    };
+
+    // Create the `FACES` uniform buffer in the set after the audio features. Rects and centers
+    // are normalized coords of the webcam image, as used with `IMG_NORM_PIXEL`.
+    let faces_str = match header.faces {
+        None => None,
+        Some(_) => {
+            let set = 2 + isf_data_input_str.is_some() as u32 + header.audio_features as u32;
+            Some(format!(
+                "
+        layout(set = {set}, binding = 0) uniform Faces {{
+            vec4 rects[{max}];
+            vec2 centers[{max}];
+            int count;
+        }} FACES;
+    ",
+                set = set,
+                max = MAX_FACES
+            ))
+        }
+    };
+
+    // Create the `FLOW` uniform buffer and `FLOW_IMAGE` texture in the set after the faces.
+    // Binding 0 holds a sampler that is not needed since `img_sampler` can be used.
+    let flow_str = match header.flow {
+        None => None,
+        Some(_) => {
+            let set = 2
+                + isf_data_input_str.is_some() as u32
+                + header.audio_features as u32
+                + header.faces.is_some() as u32;
+            Some(format!(
+                "
+        layout(set = {set}, binding = 1) uniform texture2D FLOW_IMAGE;
+        layout(set = {set}, binding = 2) uniform Flow {{
+            vec2 motion;
+            float magnitude;
+        }} FLOW;
+    ",
+                set = set
+            ))
+        }
+    };
+
     // Image functions.
     let img_fns_str = "
         // ISF provided short-hand for retrieving image size.
@@ -172,6 +276,9 @@ pub fn glsl_string_from_isf(isf: &isf::Isf) -> String {
     s.extend(input_textures);
     s.extend(pass_textures);
     s.extend(isf_data_input_str);
+    s.extend(audio_features_str);
+    s.extend(faces_str);
+    s.extend(flow_str);
     s.push_str(&img_fns_str);
     s
 }
@@ -263,7 +370,9 @@ pub fn compile_isf_shader(
         .map_err(ShaderError::from)
         .and_then(|s| isf::parse(&s).map(|isf| (s, isf)).map_err(From::from))
         .and_then(|(old_str, isf)| {
-            let isf_str = glsl_string_from_isf(&isf);
+            let extensions = extensions::parse_input_extensions(&old_str);
+            let header = extensions::parse_header_extension(&old_str);
+            let isf_str = glsl_string_from_isf(&isf, &extensions, &header);
             println!("{}", isf_str);
 
             let (new_str, offset) = prefix_isf_glsl_str(&isf_str, old_str);
@@ -5,7 +5,13 @@ use std::path::PathBuf;
 use std::sync::mpsc::{channel, Receiver};
 use std::time;
 
+use crate::programs::uniforms::audio_source::AudioSource;
 use crate::programs::uniforms::base::Bufferable;
+use crate::programs::uniforms::input_gain::GainSettings;
+use crate::programs::uniforms::source_transform::SourceTransform;
+use crate::programs::uniforms::video_capture::VideoCapture;
+use crate::programs::uniforms::webcam::{self, MAX_WEBCAMS};
+use crate::programs::uniforms::webcam_devices::{self, WebcamDevice, WebcamSettings};
 use crate::util;
 
 mod config;
@@ -24,6 +30,10 @@ pub mod uniforms;
 /// - manages uniform buffers
 #[allow(dead_code)] // needed for shader_watcher
 pub struct ProgramStore {
+    /// Input gain of whichever audio source the current program uses.
+    pub audio_gain: GainSettings,
+    /// Audio/visual latency compensation in ms, applied like `audio_gain`.
+    pub audio_latency: f32,
     pub buffer_store: uniforms::BufferStore,
     pub current_subscriptions: Option<uniforms::UniformSubscriptions>,
     pub error: Option<String>,
@@ -33,6 +43,10 @@ pub struct ProgramStore {
     pub isf_time: Option<isf::IsfTime>,
     pub program_names: Option<Vec<String>>,
     pub program_index: usize,
+    /// Device of each camera the current program can use, applied like `audio_gain`.
+    pub webcams: Vec<WebcamSettings>,
+    /// Available webcams, listed again when the webcam controls are opened.
+    pub webcam_devices: Vec<WebcamDevice>,
 
     changes_channel: Receiver<DebouncedEvent>,
     config: Option<config::Config>,
@@ -58,7 +72,18 @@ impl ProgramStore {
         let render_texture = util::create_app_texture(device, size, num_samples);
         let texture_reshaper = util::create_texture_reshaper(device, &render_texture, num_samples);
 
+        // cameras default to the available devices in order
+        let webcam_devices = webcam_devices::list_devices();
+        let webcams = (0..MAX_WEBCAMS)
+            .map(|index| WebcamSettings {
+                device: webcam_devices.get(index).map_or(index as i32, |d| d.index),
+                ..Default::default()
+            })
+            .collect();
+
         Self {
+            audio_gain: GainSettings::default(),
+            audio_latency: 0.0,
             buffer_store,
             changes_channel,
             config: None,
@@ -74,6 +99,8 @@ impl ProgramStore {
             shader_watcher,
             render_texture,
             texture_reshaper,
+            webcams,
+            webcam_devices,
         }
     }
 
@@ -145,7 +172,7 @@ impl ProgramStore {
 
         let media_path = app.project_path().unwrap().join("media");
 
-        let isf_pipeline = isf::IsfPipeline::new(
+        let mut isf_pipeline = isf::IsfPipeline::new(
             device,
             encoder,
             None,
@@ -157,6 +184,30 @@ impl ProgramStore {
             num_samples,
         );
 
+        // the signal generator, input gain, latency, render fps, video audio and video sync are
+        // the only program settings that apply to isf programs
+        isf_pipeline.set_audio_generator(
+            program_config
+                .config
+                .as_ref()
+                .and_then(|c| c.audio_generator.clone()),
+        );
+        isf_pipeline.video_audio = program_config
+            .config
+            .as_ref()
+            .and_then(|c| c.video_audio)
+            .unwrap_or_default();
+        isf_pipeline.video_sync = program_config
+            .config
+            .as_ref()
+            .and_then(|c| c.video_sync)
+            .unwrap_or(false);
+        isf_pipeline.webcams = self.webcams.clone();
+
+        // isf programs share the clock of the general uniforms
+        self.buffer_store
+            .general_uniforms
+            .configure(&program_config.config);
         let isf_time = Default::default();
 
         self.isf_pipeline = Some(isf_pipeline);
@@ -184,6 +235,24 @@ impl ProgramStore {
             isf_pipeline.end_session();
         }
 
+        // programs may bring their own gain and latency, otherwise the current ones carry over
+        if let Some(settings) = &program_config.config {
+            if let Some(audio_gain) = settings.audio_gain {
+                self.audio_gain = audio_gain;
+            }
+            if let Some(audio_latency) = settings.audio_latency {
+                self.audio_latency = audio_latency;
+            }
+            if let Some(webcams) = &settings.webcams {
+                for (index, webcam) in webcams.iter().take(MAX_WEBCAMS).enumerate() {
+                    self.webcams[index] = webcam.clone();
+                }
+            }
+            if let Some(webcam) = &settings.webcam {
+                self.webcams[0] = webcam.clone();
+            }
+        }
+
         if let Some(isf) = program_config.isf {
             if isf {
                 self.configure_isf_program(
@@ -209,6 +278,14 @@ impl ProgramStore {
         // get subscriptions and initialize
         let current_subscriptions =
             uniforms::get_subscriptions(&program_config.uniforms.as_ref().unwrap());
+        for (uniforms, settings) in self
+            .buffer_store
+            .webcam_uniforms
+            .iter_mut()
+            .zip(self.webcams.iter())
+        {
+            uniforms.settings = settings.clone();
+        }
         self.buffer_store.configure(
             app,
             device,
@@ -464,7 +541,6 @@ impl ProgramStore {
     pub fn encode_update(
         &mut self,
         app: &App,
-        update: Update,
         device: &wgpu::Device,
         encoder: &mut wgpu::CommandEncoder,
         size: Point2,
@@ -478,20 +554,131 @@ impl ProgramStore {
                 touched.push(String::from(path.to_str().unwrap()));
             }
 
+            isf_pipeline.set_audio_gain(self.audio_gain);
+            isf_pipeline.set_audio_latency(self.audio_latency);
+            isf_pipeline.set_webcams(device, &self.webcams);
+
+            let clock = &mut self.buffer_store.general_uniforms;
+            clock.update();
+            isf_pipeline.decode_videos(clock.data.time as f64);
             let images_path = app.project_path().unwrap().join("media");
             isf_pipeline.encode_update(device, encoder, &images_path, touched, num_samples);
 
             if let Some(isf_time) = self.isf_time.as_mut() {
-                isf_time.time = update.since_start.secs() as _;
-                isf_time.time_delta = update.since_last.secs() as _;
+                isf_time.time = clock.data.time;
+                isf_time.time_delta = clock.time_delta();
+                isf_time.frame_index = clock.frame_index() as _;
             }
         } else {
+            for index in self.webcams_in_use() {
+                self.buffer_store.webcam_uniforms[index].set_settings(
+                    device,
+                    &self.webcams[index],
+                    size,
+                );
+            }
+            self.buffer_store.audio_source.set_gain(self.audio_gain);
+            self.buffer_store
+                .audio_source
+                .set_latency(self.audio_latency);
             self.update_uniforms(device, encoder, size, num_samples);
         }
 
         self.update_shaders(app, device, encoder, size, num_samples, path_changed);
     }
 
+    /// The audio source of the current program.
+    pub fn audio_source(&self) -> &AudioSource {
+        match &self.isf_pipeline {
+            Some(isf_pipeline) => isf_pipeline.audio_source(),
+            None => &self.buffer_store.audio_source,
+        }
+    }
+
+    /// The cameras the current program captures from.
+    pub fn webcams_in_use(&self) -> Vec<usize> {
+        (0..MAX_WEBCAMS)
+            .filter(
+                |index| match (&self.current_subscriptions, &self.isf_pipeline) {
+                    (Some(subscriptions), _) => subscriptions.webcam[*index],
+                    (None, Some(isf_pipeline)) => isf_pipeline.webcam(*index).is_some(),
+                    _ => false,
+                },
+            )
+            .collect()
+    }
+
+    /// Whether the current program captures from a webcam.
+    pub fn uses_webcam(&self) -> bool {
+        !self.webcams_in_use().is_empty()
+    }
+
+    /// The capture of a camera of the current program, if running.
+    pub fn webcam_capture(&self, index: usize) -> Option<&VideoCapture> {
+        match &self.isf_pipeline {
+            Some(isf_pipeline) => isf_pipeline.webcam(index),
+            None => self.buffer_store.webcam_uniforms[index]
+                .video_capture
+                .as_ref(),
+        }
+    }
+
+    /// Names of the sources of the current program that can be transformed.
+    pub fn transform_sources(&self) -> Vec<String> {
+        let mut sources = vec![];
+
+        match (&self.current_subscriptions, &self.isf_pipeline) {
+            (Some(subscriptions), _) => {
+                if subscriptions.video {
+                    sources.push(String::from("video"));
+                }
+                if subscriptions.image {
+                    sources.push(String::from("image1"));
+                    sources.push(String::from("image2"));
+                }
+            }
+            (None, Some(isf_pipeline)) => sources.extend(isf_pipeline.transformable_inputs()),
+            _ => (),
+        }
+
+        sources.extend(
+            self.webcams_in_use()
+                .iter()
+                .map(|index| format!("webcam{}", index)),
+        );
+        sources
+    }
+
+    /// The transform of a source listed by `transform_sources`.
+    pub fn transform_mut(&mut self, source: &str) -> Option<&mut SourceTransform> {
+        if let Some(isf_pipeline) = &mut self.isf_pipeline {
+            if isf_pipeline
+                .transformable_inputs()
+                .iter()
+                .any(|n| n == source)
+            {
+                return isf_pipeline.input_transform_mut(source);
+            }
+        }
+
+        if let Some(index) = webcam::parse_name(source) {
+            return Some(&mut self.webcams[index].transform);
+        }
+
+        let image_uniforms = &mut self.buffer_store.image_uniforms;
+        match source {
+            "video" => Some(&mut self.buffer_store.video_uniforms.transform),
+            "image1" => Some(&mut image_uniforms.image1_transform),
+            "image2" => Some(&mut image_uniforms.image2_transform),
+            _ => None,
+        }
+    }
+
+    /// List the available webcams again.
+    pub fn refresh_webcam_devices(&mut self) {
+        self.webcam_devices = webcam_devices::list_devices();
+    }
+
     /// Fetch current GPU program.
     pub fn current_pipeline(&self) -> Option<&wgpu::RenderPipeline> {
         let current_program = &self.current_program.as_ref()?;
@@ -654,6 +841,7 @@ impl ProgramStore {
 
         if let Some(ref mut isf_pipeline) = self.isf_pipeline {
             isf_pipeline.pause();
+            self.buffer_store.general_uniforms.pause();
         }
     }
 
@@ -664,6 +852,7 @@ impl ProgramStore {
 
         if let Some(ref mut isf_pipeline) = self.isf_pipeline {
             isf_pipeline.unpause();
+            self.buffer_store.general_uniforms.unpause();
         }
     }
 
@@ -0,0 +1,227 @@
+use nannou::prelude::*;
+use std::collections::HashMap;
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use crate::programs::config;
+use crate::programs::uniforms::background::{self, MaskUniforms};
+use crate::programs::uniforms::base::{UniformBuffers, UniformBuffersMethods};
+use crate::programs::uniforms::optical_flow::{self, FlowUniforms};
+use crate::programs::uniforms::video_capture::{FrameAnalysis, VideoCapture};
+
+/// The latest result of a `FrameAnalysis` and its last error.
+#[derive(Debug)]
+pub struct Latest<T> {
+    /// `None` when there was no new result since it was last taken.
+    pub value: Option<T>,
+    pub error: Option<String>,
+}
+
+impl<T> Default for Latest<T> {
+    fn default() -> Self {
+        Self {
+            value: None,
+            error: None,
+        }
+    }
+}
+
+/// Where a `FrameAnalysis` publishes its results for an `AnalysisOutput`, on the capture
+/// thread.
+pub struct AnalysisSender<T>(Arc<Mutex<Latest<T>>>);
+
+impl<T> AnalysisSender<T> {
+    /// Publish the result for a frame, `Ok(None)` when there is none for it.
+    pub fn send(&self, result: opencv::Result<Option<T>>) {
+        let mut latest = self.0.lock().unwrap();
+        match result {
+            Ok(Some(value)) => {
+                latest.value = Some(value);
+                latest.error = None;
+            }
+            Ok(None) => (),
+            Err(e) => latest.error = Some(e.to_string()),
+        }
+    }
+}
+
+/// Results of a `FrameAnalysis` running on the thread of a capture.
+#[derive(Debug)]
+pub struct AnalysisOutput<T> {
+    /// Name of the analysis on the capture.
+    name: &'static str,
+    latest: Arc<Mutex<Latest<T>>>,
+    /// Whether the analysis was added to the capture since it was detached.
+    attached: bool,
+    /// Set when the analysis couldn't be created, it isn't tried again until it is detached.
+    failed: bool,
+}
+
+impl<T> AnalysisOutput<T> {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            latest: Arc::new(Mutex::new(Latest::default())),
+            attached: false,
+            failed: false,
+        }
+    }
+
+    /// Start the analysis over with the next `update`, e.g. with new settings.
+    pub fn detach(&mut self) {
+        self.attached = false;
+        self.failed = false;
+    }
+
+    /// Add the analysis `create` makes to `capture` unless it already runs there, and take
+    /// its latest result. When it can't be created its error is returned once.
+    pub fn update<F>(&mut self, capture: &mut VideoCapture, create: F) -> Latest<T>
+    where
+        F: FnOnce(AnalysisSender<T>) -> Result<Box<dyn FrameAnalysis>, String>,
+    {
+        if !self.failed && !(self.attached && capture.has_analysis(self.name)) {
+            self.attached = true;
+            // results of an analysis that was replaced are dropped
+            self.latest = Arc::new(Mutex::new(Latest::default()));
+            match create(AnalysisSender(self.latest.clone())) {
+                Ok(analysis) => capture.add_analysis(analysis),
+                Err(e) => {
+                    self.failed = true;
+                    return Latest {
+                        value: None,
+                        error: Some(e),
+                    };
+                }
+            }
+        }
+
+        mem::take(&mut *self.latest.lock().unwrap())
+    }
+}
+
+/// Optical flow and foreground mask of a source, the video or the first webcam, in the
+/// buffers named after it, e.g. `video_flow`.
+pub struct SourceAnalyses {
+    pub flow: FlowUniforms,
+    pub mask: MaskUniforms,
+
+    /// Source name, the prefix of the buffer names.
+    source: &'static str,
+    /// Source name in errors.
+    label: &'static str,
+}
+
+impl SourceAnalyses {
+    pub fn new(
+        device: &wgpu::Device,
+        buffers: &mut UniformBuffers,
+        source: &'static str,
+        label: &'static str,
+    ) -> Self {
+        let analyses = Self {
+            flow: FlowUniforms::new(device),
+            mask: MaskUniforms::new(device),
+            source,
+            label,
+        };
+        buffers.add(device, &analyses.flow_buffer(), &analyses.flow);
+        buffers.add(device, &analyses.mask_buffer(), &analyses.mask);
+        analyses
+    }
+
+    fn flow_buffer(&self) -> String {
+        format!("{}_flow", self.source)
+    }
+
+    fn mask_buffer(&self) -> String {
+        format!("{}_mask", self.source)
+    }
+
+    /// Take the settings of the subscribed analyses, and stop the others on `capture`.
+    pub fn configure(
+        &mut self,
+        settings: &Option<config::ProgramSettings>,
+        flow: bool,
+        mask: bool,
+        mut capture: Option<&mut VideoCapture>,
+    ) {
+        if flow {
+            self.flow.configure(settings);
+        } else if let Some(capture) = capture.as_mut() {
+            capture.remove_analysis(optical_flow::ANALYSIS);
+        }
+
+        if mask {
+            self.mask.configure(settings);
+        } else if let Some(capture) = capture.as_mut() {
+            capture.remove_analysis(background::ANALYSIS);
+        }
+    }
+
+    /// Take the latest results of the subscribed analyses of `capture`.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        buffers: &mut UniformBuffers,
+        flow: bool,
+        mask: bool,
+        capture: Option<&mut VideoCapture>,
+    ) {
+        let capture = match capture {
+            Some(capture) => capture,
+            None => return,
+        };
+
+        if flow {
+            self.flow.update(device, capture);
+            if self.flow.updated {
+                buffers.add(device, &self.flow_buffer(), &self.flow);
+            }
+        }
+
+        if mask {
+            self.mask.update(device, capture);
+            if self.mask.updated {
+                buffers.add(device, &self.mask_buffer(), &self.mask);
+            }
+        }
+    }
+
+    pub fn update_buffers(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        buffers: &UniformBuffers,
+        flow: bool,
+        mask: bool,
+    ) {
+        if flow {
+            self.flow.update_texture(device, encoder);
+            buffers.update(device, encoder, &self.flow_buffer(), &self.flow);
+        }
+
+        if mask {
+            self.mask.update_texture(device, encoder);
+            buffers.update(device, encoder, &self.mask_buffer(), &self.mask);
+        }
+    }
+
+    pub fn updated(&self) -> bool {
+        self.flow.updated || self.mask.updated
+    }
+
+    pub fn finish_update(&mut self) {
+        self.flow.updated = false;
+        self.mask.updated = false;
+    }
+
+    pub fn get_errors(&self, errors: &mut HashMap<String, Vec<String>>) {
+        if let Some(flow_error) = self.flow.error.clone() {
+            errors.insert(format!("{} Flow", self.label), vec![flow_error]);
+        }
+
+        if let Some(mask_error) = self.mask.error.clone() {
+            errors.insert(format!("{} Mask", self.label), vec![mask_error]);
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use nannou::image::{self, AnimationDecoder};
+use nannou::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::programs::uniforms::frame_upload::FrameUploader;
+
+/// Frames shown for less than this are slowed down to `DEFAULT_DELAY`, like browsers do.
+const MIN_DELAY: f64 = 0.02;
+const DEFAULT_DELAY: f64 = 0.1;
+
+/// How animated images are played.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnimationSettings {
+    /// Start over after the last frame, otherwise the last frame stays.
+    #[serde(rename = "loop")]
+    pub looping: bool,
+    pub speed: f32,
+}
+
+impl Default for AnimationSettings {
+    fn default() -> Self {
+        Self {
+            looping: true,
+            speed: 1.0,
+        }
+    }
+}
+
+/// Index of the frame shown `time` seconds into an animation.
+fn frame_at(delays: &[f64], time: f64, looping: bool) -> usize {
+    let total = delays.iter().sum::<f64>();
+    if total <= 0.0 {
+        return 0;
+    }
+
+    let mut time = time.max(0.0);
+    if looping {
+        time %= total;
+    }
+
+    let mut end = 0.0;
+    for (index, delay) in delays.iter().enumerate() {
+        end += delay;
+        if time < end {
+            return index;
+        }
+    }
+
+    delays.len() - 1
+}
+
+fn decode_frames(path: &Path) -> image::ImageResult<Vec<image::Frame>> {
+    let file = BufReader::new(File::open(path)?);
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    match extension.as_str() {
+        "gif" => image::gif::GifDecoder::new(file)?
+            .into_frames()
+            .collect_frames(),
+        "png" | "apng" => image::png::PngDecoder::new(file)?
+            .apng()
+            .into_frames()
+            .collect_frames(),
+        _ => Ok(vec![]),
+    }
+}
+
+/// All frames of an animated GIF or PNG, played back on the texture of an image input.
+#[derive(Debug)]
+pub struct Animation {
+    frames: Vec<image::RgbaImage>,
+    /// Seconds each frame is shown.
+    delays: Vec<f64>,
+    /// When the animation started playing.
+    start: Option<Instant>,
+    current: usize,
+}
+
+impl Animation {
+    /// Decode all frames, `None` when the file isn't animated or can't be decoded as such.
+    pub fn open(path: &Path) -> Option<Self> {
+        let frames = decode_frames(path).ok()?;
+        if frames.len() < 2 {
+            return None;
+        }
+
+        let delays = frames
+            .iter()
+            .map(|frame| {
+                let (numer, denom) = frame.delay().numer_denom_ms();
+                let delay = numer as f64 / denom.max(1) as f64 / 1000.0;
+                if delay < MIN_DELAY {
+                    DEFAULT_DELAY
+                } else {
+                    delay
+                }
+            })
+            .collect();
+
+        Some(Self {
+            frames: frames.into_iter().map(|f| f.into_buffer()).collect(),
+            delays,
+            start: None,
+            current: 0,
+        })
+    }
+
+    pub fn first_frame(&self) -> &image::RgbaImage {
+        &self.frames[0]
+    }
+
+    /// Upload the frame for the current time through `uploader` when it changed.
+    /// `uploader` has to transform the texture the first frame was loaded into.
+    pub fn play(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        uploader: &FrameUploader,
+        settings: AnimationSettings,
+    ) {
+        let start = *self.start.get_or_insert_with(Instant::now);
+        let time = start.elapsed().as_secs_f64() * settings.speed.max(0.0) as f64;
+        let frame = frame_at(&self.delays, time, settings.looping);
+        if frame == self.current {
+            return;
+        }
+
+        self.current = frame;
+        uploader.upload(device, encoder, self.frames[frame].as_raw());
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_frame_at() {
+    let delays = [0.1, 0.5, 0.2];
+    assert_eq!(frame_at(&delays, 0.0, true), 0);
+    assert_eq!(frame_at(&delays, 0.15, true), 1);
+    assert_eq!(frame_at(&delays, 0.7, true), 2);
+    assert_eq!(frame_at(&delays, 0.85, true), 0);
+    assert_eq!(frame_at(&delays, 0.85, false), 2);
+    assert_eq!(frame_at(&[], 1.0, true), 0);
+}
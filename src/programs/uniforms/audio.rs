@@ -4,17 +4,30 @@ use std::fmt;
 use std::sync::mpsc::{channel, Sender};
 use std::thread;
 
+use crate::programs::config;
 use crate::programs::uniforms::audio_source;
 use crate::programs::uniforms::base::Bufferable;
+use crate::programs::uniforms::envelope::{Envelope, EnvelopeSettings};
 use crate::util;
 
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Data {
+    pub rms: f32,
+}
+
 pub struct AudioUniforms {
     pub audio_texture: wgpu::Texture,
+    pub channel_mode: audio_source::ChannelMode,
+    pub data: Data,
+    pub envelope: Envelope,
+    pub updated: bool,
 
     audio_channel_tx: Option<Sender<audio_source::AudioMessage>>,
-    audio_consumer: Option<Consumer<Vec<f32>>>,
+    audio_consumer: Option<Consumer<(Vec<f32>, f32)>>,
     audio_thread: Option<std::thread::JoinHandle<()>>,
     frame: Vec<f32>,
+    rms: f32,
     texture_size: usize,
 }
 
@@ -24,18 +37,34 @@ impl fmt::Debug for AudioUniforms {
     }
 }
 
-impl Bufferable for AudioUniforms {
+impl Bufferable<Data> for AudioUniforms {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { wgpu::bytes::from(&self.data) }
+    }
+
     fn textures(&self) -> Vec<&wgpu::Texture> {
         vec![&self.audio_texture]
     }
 }
 
+/// Root mean square of a frame over all channels.
+fn rms(frame: &[Vec<f32>]) -> f32 {
+    let count: usize = frame.iter().map(|c| c.len()).sum();
+    if count == 0 {
+        return 0.0;
+    }
+
+    let sum: f32 = frame.iter().flatten().map(|s| s * s).sum();
+    (sum / count as f32).sqrt()
+}
+
 impl AudioUniforms {
     pub fn new(device: &wgpu::Device, num_samples: Option<usize>) -> Self {
         let texture_size = num_samples.unwrap_or(audio_source::FRAME_SIZE);
+        let channel_mode = audio_source::ChannelMode::Mono;
         let audio_texture = util::create_texture(
             device,
-            [texture_size as u32, 1],
+            [texture_size as u32, channel_mode.num_rows() as u32],
             wgpu::TextureFormat::R32Float,
         );
 
@@ -44,9 +73,47 @@ impl AudioUniforms {
             audio_consumer: None,
             audio_texture,
             audio_thread: None,
-            frame: vec![0.0; texture_size],
+            channel_mode,
+            data: Data { rms: 0.0 },
+            envelope: Envelope::new(1),
+            frame: vec![0.0; texture_size * channel_mode.num_rows()],
+            rms: 0.0,
             texture_size,
+            updated: false,
+        }
+    }
+
+    pub fn configure(&mut self, device: &wgpu::Device, settings: &Option<config::ProgramSettings>) {
+        let mut channel_mode = audio_source::ChannelMode::Mono;
+        self.envelope.settings = EnvelopeSettings::default();
+
+        if let Some(cnfg) = settings {
+            if let Some(mode) = cnfg.audio_channels {
+                channel_mode = mode;
+            }
+
+            if let Some(envelope) = cnfg.audio_envelope {
+                self.envelope.settings = envelope;
+            }
         }
+
+        self.set_channel_mode(device, channel_mode);
+    }
+
+    /// Set how input channels map to texture rows, recreating the texture if needed.
+    pub fn set_channel_mode(&mut self, device: &wgpu::Device, mode: audio_source::ChannelMode) {
+        if mode == self.channel_mode {
+            return;
+        }
+
+        self.channel_mode = mode;
+        self.audio_texture = util::create_texture(
+            device,
+            [self.texture_size as u32, mode.num_rows() as u32],
+            wgpu::TextureFormat::R32Float,
+        );
+        self.frame = vec![0.0; self.texture_size * mode.num_rows()];
+        self.updated = true;
     }
 
     pub fn start_session(&mut self, audio_source: &mut audio_source::AudioSource) {
@@ -54,33 +121,37 @@ impl AudioUniforms {
         audio_source.subscribe(String::from("audio"), audio_channel_tx.clone());
         self.audio_channel_tx = Some(audio_channel_tx);
 
-        let ring_buffer = RingBuffer::<Vec<f32>>::new(2);
+        let ring_buffer = RingBuffer::<(Vec<f32>, f32)>::new(2);
         let (mut producer, consumer) = ring_buffer.split();
-        producer.push(vec![0.0; self.texture_size]).unwrap();
+        producer.push((vec![0.0; self.frame.len()], 0.0)).unwrap();
         self.audio_consumer = Some(consumer);
 
         let texture_size = self.texture_size;
-        let group_size = audio_source::FRAME_SIZE / texture_size;
+        let group_size = (audio_source::FRAME_SIZE / texture_size).max(1);
+        let channel_mode = self.channel_mode;
 
         self.audio_thread = Some(thread::spawn(move || {
             for msg in audio_channel_rx.iter() {
                 match msg {
                     audio_source::AudioMessage::Data(frame) => {
-                        let mut reduced_frame = vec![0.0; texture_size];
-
-                        for i in 0..texture_size {
-                            let mut sum = 0.0;
-                            for j in 0..group_size {
-                                sum += frame[(i * group_size) + j];
+                        // one texture row per output channel
+                        let rows = channel_mode.apply(&frame);
+                        let mut reduced_frame = vec![0.0; texture_size * rows.len()];
+
+                        for (r, row) in rows.iter().enumerate() {
+                            for i in 0..texture_size {
+                                let mut sum = 0.0;
+                                for j in 0..group_size {
+                                    sum += row.get((i * group_size) + j).unwrap_or(&0.0);
+                                }
+                                reduced_frame[r * texture_size + i] = sum / group_size as f32;
                             }
-                            reduced_frame[i] = sum / group_size as f32;
                         }
 
-                        producer.push(reduced_frame).ok();
-                    }
-                    audio_source::AudioMessage::Close | audio_source::AudioMessage::Error(_) => {
-                        break;
+                        producer.push((reduced_frame, rms(&frame))).ok();
                     }
+                    audio_source::AudioMessage::SampleRate(_) => (),
+                    audio_source::AudioMessage::Close => break,
                 }
             }
         }));
@@ -103,12 +174,17 @@ impl AudioUniforms {
             let popped = c.pop();
             self.audio_consumer = Some(c);
 
-            if let Some(f) = popped {
-                if !f.is_empty() {
-                    self.frame[..self.texture_size].clone_from_slice(&f[..self.texture_size]);
+            if let Some((f, rms)) = popped {
+                if f.len() == self.frame.len() {
+                    self.frame.clone_from_slice(&f);
                 }
+                self.rms = rms;
             }
         };
+
+        let mut smoothed = [0.0];
+        self.envelope.process(&[self.rms], &mut smoothed);
+        self.data.rms = smoothed[0];
     }
 
     pub fn update_texture(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
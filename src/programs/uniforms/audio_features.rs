@@ -1,18 +1,68 @@
 use nannou::prelude::*;
-use ringbuf::{Consumer, RingBuffer};
+use ringbuf::{Consumer, Producer, RingBuffer};
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
 use std::thread;
+use std::time::Instant;
 use websocket::client::ClientBuilder;
+use websocket::sync::{Reader, Writer};
 use websocket::OwnedMessage;
 
 use crate::programs::config;
 use crate::programs::uniforms::audio_source;
 use crate::programs::uniforms::base::Bufferable;
+use crate::programs::uniforms::chroma::PITCH_CLASSES;
+use crate::programs::uniforms::envelope::{Envelope, EnvelopeSettings};
 use crate::util;
 
-const CONNECTION: &str = "ws://127.0.0.1:9002";
-const NUM_MFCCS: usize = 12;
+const NUM_FEATURES: usize = 13;
+/// Number of MFCC coefficients exposed in the texture, including the 0th.
+const NUM_MFCCS: usize = 13;
+
+/// Connection settings for the mirlin feature extraction server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeatureServerSettings {
+    pub url: String,
+    /// Features requested from the server, missing ones keep their last value.
+    pub features: Vec<String>,
+    /// Number of samples sent per frame.
+    pub hop_size: usize,
+    /// Number of frames the server remembers, including the current one.
+    pub memory: usize,
+}
+
+impl Default for FeatureServerSettings {
+    fn default() -> Self {
+        Self {
+            url: String::from("ws://127.0.0.1:9002"),
+            features: [
+                "centroid",
+                "dissonance",
+                "energy",
+                "key",
+                "loudness",
+                "mfcc",
+                "noisiness",
+                "onset",
+                "pitch",
+                "rms",
+                "spectral_complexity",
+                "spectral_contrast",
+                "tristimulus",
+            ]
+            .iter()
+            .map(|f| f.to_string())
+            .collect(),
+            hop_size: audio_source::FRAME_SIZE,
+            memory: 4,
+        }
+    }
+}
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
@@ -30,20 +80,51 @@ pub struct Data {
     pub tristimulus1: f32,
     pub tristimulus2: f32,
     pub tristimulus3: f32,
+    /// Pitch class of the detected key, 0 = C, -1 if unknown.
+    pub key: i32,
+    /// 0 for major, 1 for minor.
+    pub key_scale: i32,
+    pub key_strength: f32,
+}
+
+impl Data {
+    /// Set the continuous features from values in field order.
+    fn set_values(&mut self, v: &[f32]) {
+        self.dissonance = v[0];
+        self.energy = v[1];
+        self.loudness = v[2];
+        self.noisiness = v[3];
+        self.onset = v[4];
+        self.pitch = v[5];
+        self.rms = v[6];
+        self.spectral_centroid = v[7];
+        self.spectral_complexity = v[8];
+        self.spectral_contrast = v[9];
+        self.tristimulus1 = v[10];
+        self.tristimulus2 = v[11];
+        self.tristimulus3 = v[12];
+    }
+}
+
+/// Connection state reported by the session thread.
+enum ConnectionStatus {
+    Connected,
+    Disconnected(String),
 }
 
 pub struct AudioFeaturesUniforms {
     pub audio_channel_tx: Option<Sender<audio_source::AudioMessage>>,
     pub data: Data,
+    pub envelope: Envelope,
     pub error: Option<String>,
-    pub smoothing: f32,
+    pub server: FeatureServerSettings,
 
-    error_channel_rx: Option<Receiver<String>>,
-    recv_thread: Option<std::thread::JoinHandle<()>>,
+    status_channel_rx: Option<Receiver<ConnectionStatus>>,
     feature_consumer: Option<Consumer<serde_json::Value>>,
     mfccs: [f32; NUM_MFCCS],
     mfcc_texture: wgpu::Texture,
-    send_thread: Option<std::thread::JoinHandle<()>>,
+    /// Latest raw feature values followed by the MFCCs, targets for the envelope.
+    targets: [f32; NUM_FEATURES + NUM_MFCCS],
 }
 
 impl Bufferable<Data> for AudioFeaturesUniforms {
@@ -56,6 +137,282 @@ impl Bufferable<Data> for AudioFeaturesUniforms {
     }
 }
 
+/// Read a value as a list of numbers, accepting a single number or an array.
+fn numbers(value: &Value) -> Option<Vec<f32>> {
+    match value {
+        Value::Number(n) => Some(vec![n.as_f64()? as f32]),
+        Value::Array(a) => Some(
+            a.iter()
+                .filter_map(|v| v.as_f64())
+                .map(|v| v as f32)
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+fn feature_values(features: &Value, key: &str) -> Option<Vec<f32>> {
+    numbers(features.get(key)?)
+}
+
+fn feature_value(features: &Value, key: &str) -> Option<f32> {
+    feature_values(features, key)?.first().cloned()
+}
+
+/// Pitch class of a key name like "C#" or "Bb", 0 = C.
+fn pitch_class(name: &str) -> Option<i32> {
+    let mut chars = name.trim().chars();
+    let base = chars.next()?.to_ascii_uppercase().to_string();
+    let mut class = PITCH_CLASSES.iter().position(|p| *p == base)? as i32;
+    match chars.next() {
+        Some('#') => class += 1,
+        Some('b') => class -= 1,
+        _ => (),
+    }
+    Some((class + 12) % 12)
+}
+
+/// Read the key feature as (pitch class, scale, strength).
+/// Accepts `key` as a name, an array `[name, scale, strength]` or an object,
+/// or separate `key.key`, `key.scale` and `key.strength` entries.
+fn parse_key(features: &Value) -> Option<(i32, i32, f32)> {
+    let first_str = |v: Option<&Value>| -> Option<String> {
+        match v? {
+            Value::String(s) => Some(s.clone()),
+            Value::Array(a) => a.first()?.as_str().map(String::from),
+            _ => None,
+        }
+    };
+
+    let (name, scale, strength) = match features.get("key") {
+        Some(Value::Array(a)) => (
+            a.get(0).and_then(|v| v.as_str()).map(String::from),
+            a.get(1).and_then(|v| v.as_str()).map(String::from),
+            a.get(2).and_then(|v| v.as_f64()).map(|v| v as f32),
+        ),
+        Some(Value::Object(o)) => (
+            first_str(o.get("key")),
+            first_str(o.get("scale")),
+            o.get("strength")
+                .and_then(numbers)
+                .and_then(|n| n.first().cloned()),
+        ),
+        Some(v @ Value::String(_)) => (first_str(Some(v)), None, None),
+        _ => (
+            first_str(features.get("key.key")),
+            first_str(features.get("key.scale")),
+            feature_value(features, "key.strength"),
+        ),
+    };
+
+    let class = pitch_class(&name?)?;
+    let minor = scale.map(|s| s.to_lowercase() == "minor").unwrap_or(false);
+    Some((class, minor as i32, strength.unwrap_or(0.0)))
+}
+
+/// Connect to the server and configure a feature subscription.
+fn connect(
+    server: &FeatureServerSettings,
+    sample_rate: f32,
+) -> Result<(Reader<TcpStream>, Writer<TcpStream>), String> {
+    // create websocket client
+    let client_builder = match ClientBuilder::new(&server.url) {
+        Ok(client) => client,
+        Err(e) => return Err(format!("Error building audio websocket client: {:?}", e)),
+    };
+
+    // connect to mirlin
+    let client = match client_builder
+        .add_protocol("rust-websocket")
+        .connect_insecure()
+    {
+        Ok(client) => client,
+        Err(e) => return Err(format!("Error connecting to the mirlin server: {:?}", e)),
+    };
+
+    let (mut receiver, mut sender) = match client.split() {
+        Ok(split) => split,
+        Err(e) => return Err(format!("Error opening mirlin connection: {:?}", e)),
+    };
+
+    // build subscription request
+    let session_request = json!({
+        "type": "session_request",
+        "payload": {
+            "features": server.features,
+            "sample_rate": sample_rate,
+            "hop_size": server.hop_size,
+            "memory": server.memory,
+        }
+    });
+
+    // request subscription
+    let request_message = OwnedMessage::Text(session_request.to_string());
+    if let Err(e) = sender.send_message(&request_message) {
+        return Err(format!(
+            "Error requesting audio subscription with mirlin: {:?}",
+            e
+        ));
+    }
+
+    // wait for confirmation
+    match receiver.recv_message() {
+        Ok(OwnedMessage::Text(_json_string)) => Ok((receiver, sender)),
+        Ok(msg) => Err(format!(
+            "Received invalid confirmation from mirlin server: {:?}",
+            msg
+        )),
+        Err(e) => Err(format!(
+            "Error configuring audio subscription with mirlin: {:?}",
+            e
+        )),
+    }
+}
+
+/// Receiver thread body, pushes parsed server responses until the connection fails.
+/// Returns the producer so the next connection can reuse it.
+fn run_receiver(
+    mut receiver: Reader<TcpStream>,
+    mut producer: Producer<Value>,
+    alive: Arc<AtomicBool>,
+) -> (Producer<Value>, Option<String>) {
+    let mut error = None;
+
+    for raw in receiver.incoming_messages() {
+        match raw {
+            Ok(OwnedMessage::Text(json_string)) => match serde_json::from_str(&json_string) {
+                Ok(value) => {
+                    producer.push(value).ok();
+                }
+                Err(e) => println!("Error parsing message from mirlin: {:?}", e),
+            },
+            Ok(OwnedMessage::Close(_)) => {
+                error = Some(String::from("The mirlin server closed the connection"));
+                break;
+            }
+            Ok(_) => (),
+            Err(e) => {
+                error = Some(format!("Error receiving message from mirlin: {:?}", e));
+                break;
+            }
+        }
+    }
+
+    alive.store(false, Ordering::Relaxed);
+    (producer, error)
+}
+
+/// Session thread body. Keeps a connection to the server alive, reconnecting with backoff,
+/// and forwards audio while connected. Runs until the audio channel is closed.
+fn run_session(
+    server: FeatureServerSettings,
+    mut sample_rate: f32,
+    audio_channel_rx: Receiver<audio_source::AudioMessage>,
+    status_channel_tx: Sender<ConnectionStatus>,
+    producer: Producer<Value>,
+) {
+    let hop_size = server.hop_size.max(1);
+    let mut producer = Some(producer);
+    let mut attempt = 0;
+
+    'session: loop {
+        let (receiver, mut sender) = match connect(&server, sample_rate) {
+            Ok(connection) => connection,
+            Err(e) => {
+                status_channel_tx
+                    .send(ConnectionStatus::Disconnected(e))
+                    .ok();
+
+                // drop audio while waiting to retry, leaving as soon as the session closes
+                let retry_at = Instant::now() + audio_source::backoff(attempt);
+                attempt += 1;
+                loop {
+                    let timeout = retry_at.saturating_duration_since(Instant::now());
+                    match audio_channel_rx.recv_timeout(timeout) {
+                        Ok(audio_source::AudioMessage::Close) => break 'session,
+                        Ok(audio_source::AudioMessage::Data(_)) => (),
+                        Ok(audio_source::AudioMessage::SampleRate(rate)) => sample_rate = rate,
+                        Err(RecvTimeoutError::Timeout) => continue 'session,
+                        Err(RecvTimeoutError::Disconnected) => break 'session,
+                    }
+                }
+            }
+        };
+
+        status_channel_tx.send(ConnectionStatus::Connected).ok();
+        attempt = 0;
+
+        let alive = Arc::new(AtomicBool::new(true));
+        let alive2 = alive.clone();
+        let receiver_producer = producer.take().unwrap();
+        let recv_thread = thread::spawn(move || run_receiver(receiver, receiver_producer, alive2));
+
+        // forward audio from the audio thread to mirlin in hop sized frames
+        let mut pending = vec![];
+        let mut closed = false;
+        let mut reconnect = false;
+        let mut error = None;
+        'forward: for message in audio_channel_rx.iter() {
+            match message {
+                audio_source::AudioMessage::Data(frame) => {
+                    pending.extend(audio_source::mix_down(&frame));
+
+                    while pending.len() >= hop_size {
+                        let data = pending.drain(..hop_size).collect::<Vec<f32>>();
+                        let frame_message = json!({
+                            "type": "audio_frame",
+                            "payload": &data[..],
+                        });
+                        let message = OwnedMessage::Text(frame_message.to_string());
+                        if let Err(e) = sender.send_message(&message) {
+                            error = Some(format!("Error sending frame to mirlin: {:?}", e));
+                            break 'forward;
+                        }
+                    }
+                }
+                audio_source::AudioMessage::SampleRate(rate) => {
+                    // mirlin is told the sample rate when connecting
+                    if rate != sample_rate {
+                        sample_rate = rate;
+                        reconnect = true;
+                        break 'forward;
+                    }
+                }
+                audio_source::AudioMessage::Close => {
+                    closed = true;
+                    break 'forward;
+                }
+            }
+
+            if !alive.load(Ordering::Relaxed) {
+                break 'forward;
+            }
+        }
+
+        // close the connection, which also ends the receiver thread
+        sender.shutdown_all().ok();
+        match recv_thread.join() {
+            Ok((p, recv_error)) => {
+                producer = Some(p);
+                error = error.or(recv_error);
+            }
+            Err(_) => break 'session,
+        }
+
+        if closed {
+            break 'session;
+        }
+        if reconnect {
+            continue 'session;
+        }
+
+        let reason = error.unwrap_or_else(|| String::from("Lost connection to mirlin"));
+        status_channel_tx
+            .send(ConnectionStatus::Disconnected(reason))
+            .ok();
+    }
+}
+
 impl AudioFeaturesUniforms {
     pub fn new(device: &wgpu::Device) -> Self {
         let mfcc_texture =
@@ -63,7 +420,6 @@ impl AudioFeaturesUniforms {
 
         Self {
             audio_channel_tx: None,
-            error_channel_rx: None,
             data: Data {
                 dissonance: 0.0,
                 energy: 0.0,
@@ -78,195 +434,63 @@ impl AudioFeaturesUniforms {
                 tristimulus1: 0.0,
                 tristimulus2: 0.0,
                 tristimulus3: 0.0,
+                key: -1,
+                key_scale: 0,
+                key_strength: 0.0,
             },
+            envelope: Envelope::new(NUM_FEATURES + NUM_MFCCS),
             error: None,
             feature_consumer: None,
             mfccs: [0.0; NUM_MFCCS],
             mfcc_texture,
-            recv_thread: None,
-            smoothing: 0.5,
-            send_thread: None,
+            server: FeatureServerSettings::default(),
+            status_channel_rx: None,
+            targets: [0.0; NUM_FEATURES + NUM_MFCCS],
         }
     }
 
     pub fn configure(&mut self, settings: &Option<config::ProgramSettings>) {
-        self.smoothing = 0.5;
+        self.envelope.settings = EnvelopeSettings::default();
+        self.server = FeatureServerSettings::default();
 
         if let Some(cnfg) = settings {
-            if let Some(smoothing) = cnfg.audio_feature_smoothing {
-                self.smoothing = smoothing;
+            if let Some(envelope) = cnfg.audio_feature_envelope {
+                self.envelope.settings = envelope;
+            }
+
+            if let Some(server) = &cnfg.audio_feature_server {
+                self.server = server.clone();
             }
         }
     }
 
-    pub fn start_session(&mut self, audio_source: &mut audio_source::AudioSource) -> bool {
+    /// Subscribe to the audio source and start a session thread which connects to mirlin in
+    /// the background, so a missing server never blocks rendering.
+    pub fn start_session(&mut self, audio_source: &mut audio_source::AudioSource) {
         let (audio_channel_tx, audio_channel_rx) = channel();
         audio_source.subscribe(String::from("audio_features"), audio_channel_tx.clone());
         self.audio_channel_tx = Some(audio_channel_tx);
 
-        let (error_channel_tx, error_channel_rx) = channel();
-        self.error_channel_rx = Some(error_channel_rx);
-
-        // create websocket client
-        let client_builder = match ClientBuilder::new(CONNECTION) {
-            Ok(client) => client,
-            Err(e) => {
-                self.error = Some(format!("Error building audio websocket client: {:?}", e));
-                return false;
-            }
-        };
-
-        // connect to mirlin
-        let client = match client_builder
-            .add_protocol("rust-websocket")
-            .connect_insecure()
-        {
-            Ok(client) => client,
-            Err(e) => {
-                self.error = Some(format!("Error connecting to the mirlin server: {:?}", e));
-                return false;
-            }
-        };
-
-        let (mut receiver, mut sender) = client.split().unwrap();
-
-        // build subscription request
-        let session_request = json!({
-            "type": "session_request",
-            "payload": {
-                "features": [
-                    "centroid",
-                    "dissonance",
-                    "energy",
-                    // "key",
-                    "loudness",
-                    "mfcc",
-                    "noisiness",
-                    "onset",
-                    "pitch",
-                    "rms",
-                    "spectral_complexity",
-                    "spectral_contrast",
-                    "tristimulus",
-                ],
-                "sample_rate": audio_source.sample_rate,
-                "hop_size": 512, // happens to be cpal's buffer size
-                "memory": 4, // rember 4 frames including current
-            }
-        });
-
-        // request subscription
-        let request_message = OwnedMessage::Text(session_request.to_string());
-        match sender.send_message(&request_message) {
-            Ok(()) => (),
-            Err(e) => {
-                self.error = Some(format!(
-                    "Error requesting audio subscription with mirlin: {:?}",
-                    e
-                ));
-                return false;
-            }
-        };
-
-        // wait for confirmation
-        let confirmation_msg = match receiver.recv_message() {
-            Ok(msg) => msg,
-            Err(e) => {
-                self.error = Some(format!(
-                    "Error configuring audio subscription with mirlin: {:?}",
-                    e
-                ));
-                return false;
-            }
-        };
-
-        match confirmation_msg {
-            OwnedMessage::Text(_json_string) => (),
-            _ => {
-                self.error = Some(format!(
-                    "Received invalid confirmation from mirlin server: {:?}",
-                    confirmation_msg
-                ));
-                return false;
-            }
-        };
-
-        // sender thread
-        // forward audio from the audio thread to mirlin
-        // check the close channel for messages to end the session
-        let error_channel_tx2 = error_channel_tx.clone();
-        self.send_thread = Some(thread::spawn(move || {
-            'sender: for message in audio_channel_rx.iter() {
-                match message {
-                    audio_source::AudioMessage::Data(data) => {
-                        let frame_message = json!({
-                            "type": "audio_frame",
-                            "payload": &data[..],
-                        });
-                        let message = OwnedMessage::Text(frame_message.to_string());
-                        match sender.send_message(&message) {
-                            Ok(()) => (),
-                            Err(e) => {
-                                println!("Error sending frame: {:?}", e);
-                                break 'sender;
-                            }
-                        }
-                    }
-                    audio_source::AudioMessage::Close => break 'sender,
-                    audio_source::AudioMessage::Error(error) => {
-                        error_channel_tx2.send(error).unwrap();
-                        break;
-                    }
-                }
-            }
-
-            // close the connection and end the session
-            sender.shutdown_all().unwrap();
-        }));
+        let (status_channel_tx, status_channel_rx) = channel();
+        self.status_channel_rx = Some(status_channel_rx);
 
         // create a ring buffer for server responses (features)
         let feature_ring_buffer = RingBuffer::<serde_json::Value>::new(2);
         let (mut feature_producer, feature_consumer) = feature_ring_buffer.split();
-        feature_producer.push(json!(null)).unwrap();
+        feature_producer.push(json!(null)).ok();
         self.feature_consumer = Some(feature_consumer);
 
-        // listen for messages from server, push to ring buffer
-        self.recv_thread = Some(thread::spawn(move || {
-            for raw in receiver.incoming_messages() {
-                let message = match raw {
-                    Ok(m) => m,
-                    Err(e) => {
-                        error_channel_tx
-                            .send(format!("Error receiving message from mirlin: {:?}", e))
-                            .unwrap();
-                        return;
-                    }
-                };
-                let value: Value = match message {
-                    OwnedMessage::Text(json_string) => match serde_json::from_str(&json_string) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            error_channel_tx
-                                .send(format!("Error parsing message from mirlin: {:?}", e))
-                                .unwrap();
-                            return;
-                        }
-                    },
-                    _ => {
-                        error_channel_tx
-                            .send(format!(
-                                "Received unexpected message from mirlin: {:?}",
-                                message
-                            ))
-                            .unwrap();
-                        return;
-                    }
-                };
-                feature_producer.push(value).ok();
-            }
-        }));
-
-        true
+        let server = self.server.clone();
+        let sample_rate = audio_source.sample_rate;
+        thread::spawn(move || {
+            run_session(
+                server,
+                sample_rate,
+                audio_channel_rx,
+                status_channel_tx,
+                feature_producer,
+            );
+        });
     }
 
     pub fn end_session(&mut self, audio_source: &mut audio_source::AudioSource) {
@@ -274,116 +498,83 @@ impl AudioFeaturesUniforms {
 
         audio_source.unsubscribe(String::from("audio_features"));
 
-        if let Some(channel) = &self.audio_channel_tx {
-            channel.send(audio_source::AudioMessage::Close).unwrap();
+        // the session thread is not joined, it may be waiting on a connection attempt
+        if let Some(channel) = self.audio_channel_tx.take() {
+            channel.send(audio_source::AudioMessage::Close).ok();
         }
 
-        // join the sender thread
-        if let Some(handle) = self.send_thread.take() {
-            handle.join().unwrap();
-        }
+        self.status_channel_rx = None;
+    }
 
-        // join the receiver thread
-        if let Some(handle) = self.recv_thread.take() {
-            handle.join().unwrap();
+    pub fn update(&mut self) {
+        // check the status channel for connection changes
+        if let Some(rx) = &self.status_channel_rx {
+            for status in rx.try_iter() {
+                match status {
+                    ConnectionStatus::Connected => self.error = None,
+                    ConnectionStatus::Disconnected(e) => {
+                        println!("Audio features error: {:?}", e);
+                        self.error = Some(format!("{}\nReconnecting...", e));
+                    }
+                }
+            }
         }
-    }
 
-    fn unwrap_feature(&self, v: Option<&Value>) -> f32 {
-        v.unwrap().as_array().unwrap()[0].as_f64().unwrap() as f32
-    }
+        self.read_features();
 
-    fn lerp(&self, prev: f32, next: f32) -> f32 {
-        audio_source::lerp(prev, next, self.smoothing)
+        let mut values = [0.0; NUM_FEATURES + NUM_MFCCS];
+        self.envelope.process(&self.targets, &mut values);
+        self.data.set_values(&values[..NUM_FEATURES]);
+        self.mfccs.copy_from_slice(&values[NUM_FEATURES..]);
     }
 
-    pub fn update(&mut self, audio_source: &mut audio_source::AudioSource) {
-        // check the error channel for errors
-        if let Ok(err) = self.error_channel_rx.as_ref().unwrap().try_recv() {
-            println!("Audio error: {:?}", err);
-            self.error = Some(err);
-            self.end_session(audio_source);
-            return;
-        }
-
-        let current = match self.feature_consumer.take() {
-            Some(mut c) => {
-                let popped = c.pop();
-                self.feature_consumer = Some(c);
-                match popped {
-                    Some(v) => v,
-                    None => return,
-                }
-            }
+    /// Pop the latest response from mirlin, if any, and store its values as envelope targets.
+    /// Features missing from the response keep their previous values.
+    fn read_features(&mut self) {
+        let current = match self.feature_consumer.as_mut().and_then(|c| c.pop()) {
+            Some(v) => v,
             None => return,
         };
 
-        let payload = match current.get("payload") {
-            Some(p) => p,
-            None => return,
-        };
-
-        let features = match payload.get("features") {
+        let features = match current.get("payload").and_then(|p| p.get("features")) {
             Some(f) => f,
             None => return,
         };
 
-        if let Some(onset) = features.get("onset").unwrap().as_array() {
-            self.data.onset = onset[0].as_f64().unwrap() as f32;
+        let keys = [
+            "dissonance.mean",
+            "energy.mean",
+            "loudness.mean",
+            "noisiness.mean",
+            "onset",
+            "f0.mean",
+            "rms.mean",
+            "centroid.mean",
+            "spectral_complexity.mean",
+            "spectral_contrast.mean",
+        ];
+        for (i, key) in keys.iter().enumerate() {
+            if let Some(value) = feature_value(features, key) {
+                self.targets[i] = value;
+            }
         }
 
-        let dissonance = self.unwrap_feature(features.get("dissonance.mean"));
-        self.data.dissonance = self.lerp(self.data.dissonance, dissonance);
-
-        let energy = self.unwrap_feature(features.get("energy.mean"));
-        self.data.energy = self.lerp(self.data.energy, energy);
-
-        let loudness = self.unwrap_feature(features.get("loudness.mean"));
-        self.data.loudness = self.lerp(self.data.loudness, loudness);
-
-        let noisiness = self.unwrap_feature(features.get("noisiness.mean"));
-        self.data.noisiness = self.lerp(self.data.noisiness, noisiness);
-
-        let pitch = self.unwrap_feature(features.get("f0.mean"));
-        self.data.pitch = self.lerp(self.data.pitch, pitch);
-
-        let rms = self.unwrap_feature(features.get("rms.mean"));
-        self.data.rms = self.lerp(self.data.rms, rms);
-
-        let centroid = self.unwrap_feature(features.get("centroid.mean"));
-        self.data.spectral_centroid = self.lerp(self.data.spectral_centroid, centroid);
-
-        let spectral_complexity = self.unwrap_feature(features.get("spectral_complexity.mean"));
-        self.data.spectral_complexity =
-            self.lerp(self.data.spectral_complexity, spectral_complexity);
-
-        let spectral_contrast = self.unwrap_feature(features.get("spectral_contrast.mean"));
-        self.data.spectral_contrast = self.lerp(self.data.spectral_contrast, spectral_contrast);
-
-        let tristimulus = features
-            .get("tristimulus.mean")
-            .unwrap()
-            .as_array()
-            .unwrap();
-        self.data.tristimulus1 = self.lerp(
-            self.data.tristimulus1,
-            tristimulus[0].as_f64().unwrap() as f32,
-        );
-        self.data.tristimulus2 = self.lerp(
-            self.data.tristimulus2,
-            tristimulus[1].as_f64().unwrap() as f32,
-        );
-        self.data.tristimulus3 = self.lerp(
-            self.data.tristimulus3,
-            tristimulus[2].as_f64().unwrap() as f32,
-        );
-
-        let mfccs = features.get("mfcc.mean").unwrap().as_array().unwrap();
-        for i in 0..NUM_MFCCS {
-            self.mfccs[i] = self.lerp(
-                self.mfccs[i],
-                mfccs[i + 1].as_f64().unwrap().max(0.0) as f32,
-            );
+        if let Some(tristimulus) = feature_values(features, "tristimulus.mean") {
+            for (i, value) in tristimulus.iter().take(3).enumerate() {
+                self.targets[10 + i] = *value;
+            }
+        }
+
+        if let Some(mfccs) = feature_values(features, "mfcc.mean") {
+            for (i, value) in mfccs.iter().take(NUM_MFCCS).enumerate() {
+                self.targets[NUM_FEATURES + i] = *value;
+            }
+        }
+
+        if let Some((key, scale, strength)) = parse_key(features) {
+            self.data.key = key;
+            self.data.key_scale = scale;
+            self.data.key_strength = strength;
         }
     }
 
@@ -392,3 +583,20 @@ impl AudioFeaturesUniforms {
             .upload_data(device, encoder, bytemuck::bytes_of(&self.mfccs));
     }
 }
+
+#[cfg(test)]
+#[test]
+fn test_parse_features() {
+    let features = json!({
+        "rms.mean": [0.5, 0.4],
+        "energy.mean": 2.0,
+        "unknown": "extra",
+        "key": ["Bb", "minor", 0.8],
+    });
+    assert_eq!(feature_value(&features, "rms.mean"), Some(0.5));
+    assert_eq!(feature_value(&features, "energy.mean"), Some(2.0));
+    assert_eq!(feature_value(&features, "loudness.mean"), None);
+    assert_eq!(parse_key(&features), Some((10, 1, 0.8)));
+    assert_eq!(parse_key(&json!({ "key.key": ["C#"] })), Some((1, 0, 0.0)));
+    assert_eq!(pitch_class("Cb"), Some(11));
+}
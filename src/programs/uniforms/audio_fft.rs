@@ -1,27 +1,252 @@
 use nannou::prelude::*;
 use ringbuf::{Consumer, RingBuffer};
-use rustfft::{num_complex::Complex, FftPlanner};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::fmt;
-use std::sync::mpsc::{channel, Sender};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
 use std::thread;
 
 use crate::programs::config;
 use crate::programs::uniforms::audio_source;
 use crate::programs::uniforms::base::Bufferable;
+use crate::programs::uniforms::chroma::{self, Chroma, NUM_PITCH_CLASSES};
+use crate::programs::uniforms::envelope::{Envelope, EnvelopeSettings};
 use crate::util;
 
 const DEFAULT_SPECTRUM_SIZE: usize = 32;
-const WINDOW_SIZE: usize = 1024;
+pub const MIN_FFT_SIZE: usize = 256;
+pub const MAX_FFT_SIZE: usize = 8192;
+pub const FFT_SIZES: &[usize] = &[256, 512, 1024, 2048, 4096, 8192];
+pub const MAX_OVERLAP: f32 = 0.95;
+
+// The sum of a 1024 sample Hann window, used to keep levels consistent across settings.
+const REFERENCE_WINDOW_GAIN: f32 = 512.0;
+// Time constant of the chromagram average the key is estimated from, in seconds.
+const KEY_TIME: f32 = 4.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowFunction {
+    Hann,
+    Hamming,
+    Blackman,
+    Nuttall,
+}
+
+pub const WINDOW_FUNCTIONS: &[WindowFunction] = &[
+    WindowFunction::Hann,
+    WindowFunction::Hamming,
+    WindowFunction::Blackman,
+    WindowFunction::Nuttall,
+];
+
+impl WindowFunction {
+    pub fn build(&self, size: usize) -> Vec<f32> {
+        let window = match self {
+            WindowFunction::Hann => apodize::hanning_iter(size).collect::<Vec<f64>>(),
+            WindowFunction::Hamming => apodize::hamming_iter(size).collect::<Vec<f64>>(),
+            WindowFunction::Blackman => apodize::blackman_iter(size).collect::<Vec<f64>>(),
+            WindowFunction::Nuttall => apodize::nuttall_iter(size).collect::<Vec<f64>>(),
+        };
+        window.into_iter().map(|w| w as f32).collect()
+    }
+}
+
+/// Parameters of the short time fourier transform.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FftSettings {
+    pub size: usize,
+    pub window: WindowFunction,
+    /// Fraction of each window shared with the previous one.
+    pub overlap: f32,
+}
+
+impl Default for FftSettings {
+    fn default() -> Self {
+        Self {
+            size: 1024,
+            window: WindowFunction::Hann,
+            overlap: 0.5,
+        }
+    }
+}
+
+impl FftSettings {
+    /// Clamp the settings to the supported ranges.
+    /// The size is rounded up to a power of two.
+    pub fn clamped(&self) -> Self {
+        Self {
+            size: self
+                .size
+                .clamp(MIN_FFT_SIZE, MAX_FFT_SIZE)
+                .next_power_of_two(),
+            window: self.window,
+            overlap: self.overlap.clamp(0.0, MAX_OVERLAP),
+        }
+    }
+
+    /// The number of new samples between consecutive windows.
+    pub fn hop_size(&self) -> usize {
+        ((self.size as f32 * (1.0 - self.overlap)) as usize).max(1)
+    }
+}
+
+/// The result of analysing one hop.
+struct Analysis {
+    /// The reduced spectrum of every row.
+    spectrum: Vec<f32>,
+    /// The chromagram of all rows combined.
+    chroma: Chroma,
+    /// The key estimated from the recent chromagrams as (tonic, scale, strength).
+    key: (i32, i32, f32),
+}
+
+impl Analysis {
+    fn silent(spectrum_len: usize) -> Self {
+        Self {
+            spectrum: vec![0.0; spectrum_len],
+            chroma: [0.0; NUM_PITCH_CLASSES],
+            key: (-1, 0, 0.0),
+        }
+    }
+}
+
+/// The FFT plan, window and sample history for an analysis thread.
+/// Rebuilt whenever the settings change.
+struct Analyzer {
+    chroma_average: Chroma,
+    fft: Arc<dyn Fft<f32>>,
+    hop_size: usize,
+    key_decay: f32,
+    new_samples: usize,
+    rows: Vec<VecDeque<f32>>,
+    sample_rate: f32,
+    scale: f32,
+    size: usize,
+    window: Vec<f32>,
+}
+
+impl Analyzer {
+    fn new(
+        planner: &mut FftPlanner<f32>,
+        settings: FftSettings,
+        num_rows: usize,
+        sample_rate: f32,
+    ) -> Self {
+        let window = settings.window.build(settings.size);
+        let window_gain: f32 = window.iter().sum();
+        let hop_size = settings.hop_size();
+
+        Self {
+            chroma_average: [0.0; NUM_PITCH_CLASSES],
+            fft: planner.plan_fft_forward(settings.size),
+            hop_size,
+            key_decay: (-(hop_size as f32 / sample_rate) / KEY_TIME).exp(),
+            new_samples: 0,
+            rows: vec![vec![0.0; settings.size].into_iter().collect(); num_rows],
+            sample_rate,
+            scale: REFERENCE_WINDOW_GAIN / window_gain,
+            size: settings.size,
+            window,
+        }
+    }
+
+    /// Add a frame of samples for each row.
+    /// Returns the analysis of the latest window if a hop has completed.
+    fn process(&mut self, rows: Vec<Vec<f32>>, spectrum_size: usize) -> Option<Analysis> {
+        let mut analysis = None;
+        let num_samples = rows[0].len();
+
+        for i in 0..num_samples {
+            for (history, row) in self.rows.iter_mut().zip(rows.iter()) {
+                history.pop_front();
+                history.push_back(row[i]);
+            }
+
+            self.new_samples += 1;
+            if self.new_samples >= self.hop_size {
+                self.new_samples = 0;
+                analysis = Some(self.analyze(spectrum_size));
+            }
+        }
+
+        analysis
+    }
+
+    fn analyze(&mut self, spectrum_size: usize) -> Analysis {
+        let mut spectrum = vec![];
+        let mut magnitudes = vec![0.0; self.size / 2];
+        let num_rows = self.rows.len() as f32;
+
+        for history in self.rows.iter() {
+            let mut window = history
+                .iter()
+                .zip(self.window.iter())
+                .map(|(s, w)| Complex {
+                    re: s * w * self.scale,
+                    im: 0.0,
+                })
+                .collect::<Vec<Complex<f32>>>();
+
+            self.fft.process(&mut window[..]);
+            spectrum.extend(reduce_spectrum(&window, spectrum_size));
+            for (magnitude, s) in magnitudes.iter_mut().zip(window.iter()) {
+                *magnitude += s.norm() / num_rows;
+            }
+        }
+
+        let chroma = chroma::chromagram(&magnitudes, self.sample_rate, self.size);
+        for (average, c) in self.chroma_average.iter_mut().zip(chroma.iter()) {
+            *average = c + (*average - c) * self.key_decay;
+        }
+
+        Analysis {
+            spectrum,
+            chroma,
+            key: chroma::estimate_key(&self.chroma_average),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Data {
+    pub history_row: i32,
+    pub history_size: i32,
+    /// The strongest pitch class of the chromagram, 0 = C, -1 for silence.
+    pub pitch_class: i32,
+    /// Tonic of the estimated key, 0 = C, -1 if unknown.
+    pub key: i32,
+    /// 0 for major, 1 for minor.
+    pub key_scale: i32,
+    /// Correlation of the recent chromagrams with the estimated key's profile.
+    pub key_strength: f32,
+}
 
 pub struct AudioFftUniforms {
-    pub smoothing: f32,
+    pub channel_mode: audio_source::ChannelMode,
+    pub chroma_texture: wgpu::Texture,
+    pub fft_settings: FftSettings,
+    pub history_size: usize,
+    pub envelope: Envelope,
+    pub history_texture: wgpu::Texture,
     pub spectrum_texture: wgpu::Texture,
+    pub updated: bool,
 
     audio_channel_tx: Option<Sender<audio_source::AudioMessage>>,
+    chroma: Chroma,
+    chroma_envelope: Envelope,
+    chroma_target: Chroma,
+    data: Data,
     fft_thread: Option<std::thread::JoinHandle<()>>,
-    spectrum_consumer: Option<Consumer<Vec<f32>>>,
+    history: Vec<f32>,
+    settings_channel_tx: Option<Sender<FftSettings>>,
+    spectrum_consumer: Option<Consumer<Analysis>>,
     spectrum: Vec<f32>,
     spectrum_size: usize,
+    target: Vec<f32>,
 }
 
 impl fmt::Debug for AudioFftUniforms {
@@ -30,40 +255,242 @@ impl fmt::Debug for AudioFftUniforms {
     }
 }
 
-impl Bufferable for AudioFftUniforms {
+impl Bufferable<Data> for AudioFftUniforms {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { wgpu::bytes::from(&self.data) }
+    }
+
     fn textures(&self) -> Vec<&wgpu::Texture> {
-        vec![&self.spectrum_texture]
+        vec![
+            &self.spectrum_texture,
+            &self.history_texture,
+            &self.chroma_texture,
+        ]
+    }
+}
+
+/// Take the magnitudes of the lower half of an FFT output and average them down to
+/// `spectrum_size` bands.
+fn reduce_spectrum(window: &[Complex<f32>], spectrum_size: usize) -> Vec<f32> {
+    let spectrum = window
+        .iter()
+        .take(window.len() / 2)
+        .map(|s| s.norm())
+        .collect::<Vec<f32>>();
+
+    let group_size = (spectrum.len() / spectrum_size).max(1);
+    let mut reduced_spectrum = vec![0.0; spectrum_size];
+    for (i, band) in reduced_spectrum.iter_mut().enumerate() {
+        let mut sum = 0.0;
+        for j in 0..group_size {
+            sum += spectrum.get((i * group_size) + j).unwrap_or(&0.0);
+        }
+        *band = sum / group_size as f32;
     }
+
+    reduced_spectrum
+}
+
+/// Analysis thread body, computes spectra from incoming frames until the session closes.
+/// The analyzer is rebuilt whenever new settings or a new sample rate are received.
+fn run_analysis(
+    audio_channel_rx: Receiver<audio_source::AudioMessage>,
+    settings_channel_rx: Receiver<FftSettings>,
+    mut producer: ringbuf::Producer<Analysis>,
+    mut settings: FftSettings,
+    spectrum_size: usize,
+    channel_mode: audio_source::ChannelMode,
+    mut sample_rate: f32,
+) {
+    let mut planner = FftPlanner::<f32>::new();
+    let num_rows = channel_mode.num_rows();
+    let mut analyzer = Analyzer::new(&mut planner, settings, num_rows, sample_rate);
+
+    for message in audio_channel_rx.iter() {
+        if let Some(new_settings) = settings_channel_rx.try_iter().last() {
+            settings = new_settings;
+            analyzer = Analyzer::new(&mut planner, settings, num_rows, sample_rate);
+        }
+
+        match message {
+            audio_source::AudioMessage::Data(frame) => {
+                let rows = channel_mode.apply(&frame);
+                if let Some(analysis) = analyzer.process(rows, spectrum_size) {
+                    producer.push(analysis).ok();
+                }
+            }
+            audio_source::AudioMessage::SampleRate(rate) => {
+                if rate != sample_rate {
+                    sample_rate = rate;
+                    analyzer = Analyzer::new(&mut planner, settings, num_rows, sample_rate);
+                }
+            }
+            audio_source::AudioMessage::Close => break,
+        }
+    }
+}
+
+fn create_spectrum_texture(
+    device: &wgpu::Device,
+    spectrum_size: usize,
+    channel_mode: audio_source::ChannelMode,
+) -> wgpu::Texture {
+    util::create_texture(
+        device,
+        [spectrum_size as u32, channel_mode.num_rows() as u32],
+        wgpu::TextureFormat::R32Float,
+    )
+}
+
+fn create_history_texture(
+    device: &wgpu::Device,
+    spectrum_size: usize,
+    history_size: usize,
+) -> wgpu::Texture {
+    util::create_texture(
+        device,
+        [spectrum_size as u32, history_size.max(1) as u32],
+        wgpu::TextureFormat::R32Float,
+    )
 }
 
 impl AudioFftUniforms {
     pub fn new(device: &wgpu::Device, spectrum_size_opt: Option<usize>) -> Self {
         let spectrum_size = spectrum_size_opt.unwrap_or(DEFAULT_SPECTRUM_SIZE);
-        let spectrum_texture = util::create_texture(
+        let channel_mode = audio_source::ChannelMode::Mono;
+        let spectrum_texture = create_spectrum_texture(device, spectrum_size, channel_mode);
+        let history_texture = create_history_texture(device, spectrum_size, 0);
+        let chroma_texture = util::create_texture(
             device,
-            [spectrum_size as u32, 1],
+            [NUM_PITCH_CLASSES as u32, 1],
             wgpu::TextureFormat::R32Float,
         );
 
         Self {
             audio_channel_tx: None,
+            channel_mode,
+            chroma: [0.0; NUM_PITCH_CLASSES],
+            chroma_envelope: Envelope::new(NUM_PITCH_CLASSES),
+            chroma_target: [0.0; NUM_PITCH_CLASSES],
+            chroma_texture,
+            data: Data {
+                history_row: 0,
+                history_size: 0,
+                pitch_class: -1,
+                key: -1,
+                key_scale: 0,
+                key_strength: 0.0,
+            },
+            envelope: Envelope::new(spectrum_size * channel_mode.num_rows()),
+            fft_settings: FftSettings::default(),
             fft_thread: None,
-            smoothing: 0.5,
+            history: vec![],
+            history_size: 0,
+            history_texture,
+            settings_channel_tx: None,
             spectrum_consumer: None,
             spectrum_texture,
-            spectrum: vec![0.0; spectrum_size],
+            spectrum: vec![0.0; spectrum_size * channel_mode.num_rows()],
             spectrum_size,
+            target: vec![0.0; spectrum_size * channel_mode.num_rows()],
+            updated: false,
         }
     }
 
-    pub fn configure(&mut self, settings: &Option<config::ProgramSettings>) {
-        self.smoothing = 0.5;
+    pub fn configure(&mut self, device: &wgpu::Device, settings: &Option<config::ProgramSettings>) {
+        self.envelope.settings = EnvelopeSettings::default();
+        let mut history_size = 0;
+        let mut channel_mode = audio_source::ChannelMode::Mono;
+        let mut fft_settings = FftSettings::default();
 
         if let Some(cnfg) = settings {
-            if let Some(smoothing) = cnfg.audio_fft_smoothing {
-                self.smoothing = smoothing;
+            if let Some(size) = cnfg.audio_fft_size {
+                fft_settings.size = size;
+            }
+
+            if let Some(window) = cnfg.audio_fft_window {
+                fft_settings.window = window;
+            }
+
+            if let Some(overlap) = cnfg.audio_fft_overlap {
+                fft_settings.overlap = overlap;
+            }
+
+            if let Some(mode) = cnfg.audio_channels {
+                channel_mode = mode;
+            }
+
+            if let Some(envelope) = cnfg.audio_fft_envelope {
+                self.envelope.settings = envelope;
+            }
+
+            if let Some(history) = cnfg.audio_fft_history {
+                history_size = history;
+            }
+        }
+
+        self.set_channel_mode(device, channel_mode);
+        self.set_history_size(device, history_size);
+        self.set_fft_settings(fft_settings);
+    }
+
+    /// Set how input channels map to spectrum rows, recreating the texture if needed.
+    pub fn set_channel_mode(&mut self, device: &wgpu::Device, mode: audio_source::ChannelMode) {
+        if mode == self.channel_mode {
+            return;
+        }
+
+        self.channel_mode = mode;
+        self.spectrum_texture = create_spectrum_texture(device, self.spectrum_size, mode);
+        self.spectrum = vec![0.0; self.spectrum_size * mode.num_rows()];
+        self.target = vec![0.0; self.spectrum_size * mode.num_rows()];
+        self.envelope.resize(self.spectrum_size * mode.num_rows());
+        self.updated = true;
+    }
+
+    /// Set the number of past spectra kept in the history texture.
+    /// A size of 0 disables the history.
+    pub fn set_history_size(&mut self, device: &wgpu::Device, history_size: usize) {
+        if history_size == self.history_size {
+            return;
+        }
+
+        self.history_size = history_size;
+        self.history = vec![0.0; self.spectrum_size * history_size];
+        self.history_texture = create_history_texture(device, self.spectrum_size, history_size);
+        self.data.history_row = 0;
+        self.data.history_size = history_size as i32;
+        self.updated = true;
+    }
+
+    /// The estimated key, e.g. "A minor".
+    pub fn key_name(&self) -> Option<String> {
+        chroma::key_name(self.data.key, self.data.key_scale)
+    }
+
+    /// The row of the history texture holding the latest spectrum.
+    pub fn history_row(&self) -> i32 {
+        self.data.history_row
+    }
+
+    /// Write the current spectrum, averaged over all channel rows, into the next row of the
+    /// history ring buffer.
+    fn push_history(&mut self) {
+        if self.history_size == 0 {
+            return;
+        }
+
+        let row = (self.data.history_row as usize + 1) % self.history_size;
+        let start = row * self.spectrum_size;
+        let num_rows = self.channel_mode.num_rows();
+        for i in 0..self.spectrum_size {
+            let mut sum = 0.0;
+            for r in 0..num_rows {
+                sum += self.spectrum[r * self.spectrum_size + i];
             }
+            self.history[start + i] = sum / num_rows as f32;
         }
+        self.data.history_row = row as i32;
     }
 
     pub fn start_session(&mut self, audio_source: &mut audio_source::AudioSource) {
@@ -71,71 +498,46 @@ impl AudioFftUniforms {
         audio_source.subscribe(String::from("audio_fft"), audio_channel_tx.clone());
         self.audio_channel_tx = Some(audio_channel_tx);
 
-        // setup the FFT
-        let mut planner = FftPlanner::<f32>::new();
-        let fft = planner.plan_fft_forward(WINDOW_SIZE);
-        let hanning_window = apodize::hanning_iter(WINDOW_SIZE).collect::<Vec<f64>>();
+        let (settings_channel_tx, settings_channel_rx) = channel();
+        self.settings_channel_tx = Some(settings_channel_tx);
 
-        // create a ring buffer for spectrum results
-        let ring_buffer = RingBuffer::<Vec<f32>>::new(2);
+        // create a ring buffer for analysis results
+        let ring_buffer = RingBuffer::<Analysis>::new(2);
         let (mut producer, consumer) = ring_buffer.split();
-        producer.push(vec![0.0; self.spectrum_size]).unwrap();
+        producer.push(Analysis::silent(self.spectrum.len())).ok();
         self.spectrum_consumer = Some(consumer);
 
-        let spec_group_size = (WINDOW_SIZE / 2) / self.spectrum_size;
         let spectrum_size = self.spectrum_size;
+        let channel_mode = self.channel_mode;
+        let settings = self.fft_settings;
+        let sample_rate = audio_source.sample_rate;
 
         self.fft_thread = Some(thread::spawn(move || {
-            let mut frames = vec![];
-            frames.push(vec![0.0; audio_source::FRAME_SIZE]);
-            frames.push(vec![0.0; audio_source::FRAME_SIZE]);
-
-            for message in audio_channel_rx.iter() {
-                match message {
-                    audio_source::AudioMessage::Data(frame) => {
-                        // add new spectrum to memory and build the window
-                        frames.remove(0);
-                        frames.push(frame);
-                        let mut window = frames
-                            .clone()
-                            .into_iter()
-                            .flatten()
-                            .enumerate()
-                            .take(WINDOW_SIZE)
-                            .map(|(i, s)| Complex {
-                                re: s * hanning_window[i] as f32,
-                                im: 0.0,
-                            })
-                            .collect::<Vec<Complex<f32>>>();
-
-                        // perform the fft to get the spectrum
-                        fft.process(&mut window[..]);
-                        let spectrum = window
-                            .iter()
-                            .take(WINDOW_SIZE / 2)
-                            .map(|s| s.norm())
-                            .collect::<Vec<f32>>();
-
-                        // downsample the spectrum
-                        let mut reduced_spectrum = vec![0.0; spectrum_size];
-                        for i in 0..spectrum_size {
-                            let mut sum = 0.0;
-                            for j in 0..spec_group_size {
-                                sum += spectrum[(i * spec_group_size) + j];
-                            }
-                            reduced_spectrum[i] = sum / spec_group_size as f32;
-                        }
-
-                        producer.push(reduced_spectrum).ok();
-                    }
-                    audio_source::AudioMessage::Close | audio_source::AudioMessage::Error(_) => {
-                        break
-                    }
-                }
-            }
+            run_analysis(
+                audio_channel_rx,
+                settings_channel_rx,
+                producer,
+                settings,
+                spectrum_size,
+                channel_mode,
+                sample_rate,
+            );
         }));
     }
 
+    /// Change the FFT settings, the running analysis thread rebuilds its plan on the next frame.
+    pub fn set_fft_settings(&mut self, settings: FftSettings) {
+        let settings = settings.clamped();
+        if settings == self.fft_settings {
+            return;
+        }
+
+        self.fft_settings = settings;
+        if let Some(channel) = &self.settings_channel_tx {
+            channel.send(settings).ok();
+        }
+    }
+
     pub fn end_session(&mut self, audio_source: &mut audio_source::AudioSource) {
         audio_source.unsubscribe(String::from("audio_fft"));
 
@@ -149,21 +551,63 @@ impl AudioFftUniforms {
     }
 
     pub fn update(&mut self) {
+        let mut received = false;
+
         if let Some(mut c) = self.spectrum_consumer.take() {
             let popped = c.pop();
             self.spectrum_consumer = Some(c);
 
-            if let Some(f) = popped {
-                for (i, &sample) in f.iter().enumerate().take(self.spectrum_size) {
-                    self.spectrum[i] = audio_source::lerp(self.spectrum[i], sample, self.smoothing);
+            if let Some(analysis) = popped {
+                for (i, &sample) in analysis.spectrum.iter().enumerate().take(self.target.len()) {
+                    self.target[i] = sample;
                 }
+                self.chroma_target = analysis.chroma;
+                let (key, key_scale, key_strength) = analysis.key;
+                self.data.key = key;
+                self.data.key_scale = key_scale;
+                self.data.key_strength = key_strength;
+                received = true;
             }
         }
+
+        // followers run every frame so the release stays smooth between analysis results
+        self.envelope.process(&self.target, &mut self.spectrum);
+        self.chroma_envelope.settings = self.envelope.settings;
+        self.chroma_envelope
+            .process(&self.chroma_target, &mut self.chroma);
+        self.data.pitch_class = chroma::dominant_pitch_class(&self.chroma);
+
+        if received {
+            self.push_history();
+        }
     }
 
     pub fn update_texture(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
         let bytes = util::floats_as_byte_vec(&self.spectrum);
         self.spectrum_texture
             .upload_data(device, encoder, &bytes[..]);
+
+        if self.history_size > 0 {
+            let history_bytes = util::floats_as_byte_vec(&self.history);
+            self.history_texture
+                .upload_data(device, encoder, &history_bytes[..]);
+        }
+
+        self.chroma_texture
+            .upload_data(device, encoder, bytemuck::bytes_of(&self.chroma));
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_fft_settings_clamped() {
+    let settings = FftSettings {
+        size: 3000,
+        window: WindowFunction::Blackman,
+        overlap: 1.0,
     }
+    .clamped();
+    assert_eq!(settings.size, 4096);
+    assert_eq!(settings.overlap, MAX_OVERLAP);
+    assert_eq!(FftSettings::default().hop_size(), 512);
 }
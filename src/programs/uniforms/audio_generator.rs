@@ -0,0 +1,233 @@
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+/// Length of a single click in a click train, in seconds.
+const CLICK_LENGTH: f32 = 0.01;
+
+/// A test signal that can be produced by the generator.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Signal {
+    /// A sine wave at a fixed frequency in Hz.
+    Sine {
+        frequency: f32,
+    },
+    /// A sine wave sweeping logarithmically between two frequencies, repeating every
+    /// `duration` seconds.
+    Sweep {
+        from: f32,
+        to: f32,
+        duration: f32,
+    },
+    WhiteNoise,
+    PinkNoise,
+    /// Short noise bursts on every beat of the given tempo.
+    Clicks {
+        bpm: f32,
+    },
+}
+
+fn default_amplitude() -> f32 {
+    0.5
+}
+
+/// A signal and its level in the mix.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeneratorSource {
+    #[serde(flatten)]
+    pub signal: Signal,
+    #[serde(default = "default_amplitude")]
+    pub amplitude: f32,
+}
+
+/// Configuration of the signal generator backend.
+/// All sources are summed and written identically to every channel.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeneratorSettings {
+    pub channels: usize,
+    pub sample_rate: f32,
+    pub sources: Vec<GeneratorSource>,
+}
+
+impl Default for GeneratorSettings {
+    fn default() -> Self {
+        Self {
+            channels: 2,
+            sample_rate: 44100.0,
+            sources: vec![GeneratorSource {
+                signal: Signal::Sine { frequency: 440.0 },
+                amplitude: default_amplitude(),
+            }],
+        }
+    }
+}
+
+/// Small xorshift generator, keeps the noise deterministic between runs.
+#[derive(Debug, Clone)]
+struct Rng {
+    state: u32,
+}
+
+impl Rng {
+    /// Next value in [-1, 1).
+    fn next(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        (self.state as f32 / u32::MAX as f32) * 2.0 - 1.0
+    }
+}
+
+/// Frequency of a logarithmic sweep `t` of the way from `from` to `to`, `from` is at least
+/// 1 Hz.
+fn sweep_frequency(from: f32, to: f32, t: f32) -> f32 {
+    let from = from.max(1.0);
+    from * (to / from).powf(t)
+}
+
+/// Playback state of a single source.
+#[derive(Debug, Clone)]
+struct Voice {
+    source: GeneratorSource,
+    phase: f32,
+    position: u64,
+    pink: [f32; 7],
+    rng: Rng,
+}
+
+impl Voice {
+    fn new(source: GeneratorSource, seed: u32) -> Self {
+        Self {
+            source,
+            phase: 0.0,
+            position: 0,
+            pink: [0.0; 7],
+            rng: Rng { state: seed },
+        }
+    }
+
+    fn sample(&mut self, sample_rate: f32) -> f32 {
+        let dt = 1.0 / sample_rate;
+        let time = self.position as f64 / sample_rate as f64;
+
+        let value = match self.source.signal {
+            Signal::Sine { frequency } => self.oscillate(frequency, dt),
+            Signal::Sweep { from, to, duration } => {
+                let duration = duration.max(dt);
+                let t = ((time % duration as f64) / duration as f64) as f32;
+                self.oscillate(sweep_frequency(from, to, t), dt)
+            }
+            Signal::WhiteNoise => self.rng.next(),
+            Signal::PinkNoise => {
+                // Paul Kellet's refined pink noise filter
+                let white = self.rng.next();
+                let b = &mut self.pink;
+                b[0] = 0.99886 * b[0] + white * 0.0555179;
+                b[1] = 0.99332 * b[1] + white * 0.0750759;
+                b[2] = 0.96900 * b[2] + white * 0.1538520;
+                b[3] = 0.86650 * b[3] + white * 0.3104856;
+                b[4] = 0.55000 * b[4] + white * 0.5329522;
+                b[5] = -0.7616 * b[5] - white * 0.0168980;
+                let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+                b[6] = white * 0.115926;
+                pink * 0.11
+            }
+            Signal::Clicks { bpm } => {
+                let period = 60.0 / bpm.max(1.0);
+                let t = (time % period as f64) as f32;
+                if t < CLICK_LENGTH {
+                    self.rng.next() * (1.0 - t / CLICK_LENGTH)
+                } else {
+                    0.0
+                }
+            }
+        };
+
+        self.position += 1;
+        value * self.source.amplitude
+    }
+
+    fn oscillate(&mut self, frequency: f32, dt: f32) -> f32 {
+        let value = (self.phase * 2.0 * PI).sin();
+        self.phase = (self.phase + frequency * dt).fract();
+        value
+    }
+}
+
+/// Produces a mix of test signals in place of an input device.
+#[derive(Debug, Clone)]
+pub struct SignalGenerator {
+    pub settings: GeneratorSettings,
+
+    voices: Vec<Voice>,
+}
+
+impl SignalGenerator {
+    pub fn new(settings: GeneratorSettings) -> Self {
+        let voices = settings
+            .sources
+            .iter()
+            .enumerate()
+            .map(|(i, s)| Voice::new(s.clone(), 0x9E37_79B9 ^ (i as u32 + 1)))
+            .collect();
+
+        Self { settings, voices }
+    }
+
+    /// The next sample of the mix, clamped to [-1, 1].
+    pub fn sample(&mut self) -> f32 {
+        let sample_rate = self.settings.sample_rate;
+        let sum: f32 = self.voices.iter_mut().map(|v| v.sample(sample_rate)).sum();
+        sum.max(-1.0).min(1.0)
+    }
+
+    /// Generate `num_frames` frames of interleaved samples.
+    pub fn generate(&mut self, num_frames: usize) -> Vec<f32> {
+        let channels = self.settings.channels.max(1);
+        let mut data = Vec::with_capacity(num_frames * channels);
+        for _ in 0..num_frames {
+            let sample = self.sample();
+            data.extend(std::iter::repeat(sample).take(channels));
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_signal_generator() {
+    let settings: GeneratorSettings = serde_json::from_str(
+        r#"{
+            "channels": 1,
+            "sample_rate": 1000,
+            "sources": [{ "type": "clicks", "bpm": 120, "amplitude": 1.0 }]
+        }"#,
+    )
+    .unwrap();
+    let mut generator = SignalGenerator::new(settings);
+
+    // a click every 500 samples, lasting 10 samples
+    let data = generator.generate(1000);
+    assert!(data[0..10].iter().any(|s| *s != 0.0));
+    assert!(data[10..500].iter().all(|s| *s == 0.0));
+    assert!(data[500..510].iter().any(|s| *s != 0.0));
+
+    let mut sine = SignalGenerator::new(GeneratorSettings {
+        channels: 2,
+        sample_rate: 4.0,
+        sources: vec![GeneratorSource {
+            signal: Signal::Sine { frequency: 1.0 },
+            amplitude: 1.0,
+        }],
+    });
+    let data = sine.generate(2);
+    assert_eq!(data.len(), 4);
+    assert!((data[2] - 1.0).abs() < 1e-6);
+    assert_eq!(data[2], data[3]);
+
+    // sweeps end at `to`, also when starting below 1 Hz
+    assert!((sweep_frequency(20.0, 20000.0, 1.0) - 20000.0).abs() < 0.01);
+    assert!((sweep_frequency(0.0, 440.0, 1.0) - 440.0).abs() < 1e-3);
+    assert_eq!(sweep_frequency(0.0, 440.0, 0.0), 1.0);
+}
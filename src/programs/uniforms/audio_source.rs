@@ -1,21 +1,138 @@
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use std::collections::HashMap;
-use std::sync::mpsc::{channel, Receiver, Sender};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
+use crate::programs::uniforms::audio_generator::{GeneratorSettings, SignalGenerator};
+use crate::programs::uniforms::input_gain::{GainSettings, GainStage, Levels};
+
+/// Number of samples per channel in each frame sent to subscribers.
 pub const FRAME_SIZE: usize = 512;
 
-pub fn lerp(prev: f32, next: f32, smoothing: f32) -> f32 {
-    smoothing * prev + (1.0 - smoothing) * next
-}
+/// A frame of audio with one buffer of `FRAME_SIZE` samples per input channel.
+pub type AudioFrame = Vec<Vec<f32>>;
 
 #[derive(Debug, Clone)]
 pub enum AudioMessage {
     Close,
+    Data(AudioFrame),
+    /// Frames from here on have this sample rate, sent when a subscriber is added and when
+    /// a stream with another rate replaces the current one.
+    SampleRate(f32),
+}
+
+/// Determines how the input channels are mapped to the rows of an audio texture.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelMode {
+    /// A single row with all channels mixed down.
+    Mono,
+    /// A single row with the left channel.
+    Left,
+    /// A single row with the right channel.
+    Right,
+    /// Two rows, left and right.
+    Stereo,
+    /// Two rows, mid (L + R) / 2 and side (L - R) / 2.
+    MidSide,
+}
+
+impl ChannelMode {
+    /// The number of texture rows produced by this mode.
+    pub fn num_rows(&self) -> usize {
+        match self {
+            ChannelMode::Mono | ChannelMode::Left | ChannelMode::Right => 1,
+            ChannelMode::Stereo | ChannelMode::MidSide => 2,
+        }
+    }
+
+    /// Map an input frame to the rows described by this mode.
+    /// Mono inputs are duplicated where two channels are expected.
+    pub fn apply(&self, frame: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        let left = &frame[0];
+        let right = frame.get(1).unwrap_or(left);
+
+        match self {
+            ChannelMode::Mono => vec![mix_down(frame)],
+            ChannelMode::Left => vec![left.clone()],
+            ChannelMode::Right => vec![right.clone()],
+            ChannelMode::Stereo => vec![left.clone(), right.clone()],
+            ChannelMode::MidSide => {
+                let mid = left
+                    .iter()
+                    .zip(right.iter())
+                    .map(|(l, r)| (l + r) * 0.5)
+                    .collect();
+                let side = left
+                    .iter()
+                    .zip(right.iter())
+                    .map(|(l, r)| (l - r) * 0.5)
+                    .collect();
+                vec![mid, side]
+            }
+        }
+    }
+}
+
+/// Average all channels of a frame into one.
+pub fn mix_down(frame: &[Vec<f32>]) -> Vec<f32> {
+    let num_channels = frame.len() as f32;
+    let mut mixed = vec![0.0; frame[0].len()];
+    for channel in frame {
+        for (m, s) in mixed.iter_mut().zip(channel.iter()) {
+            *m += s / num_channels;
+        }
+    }
+    mixed
+}
+
+/// Split interleaved samples into per channel buffers.
+pub fn deinterleave(data: &[f32], num_channels: usize, channels: &mut [Vec<f32>]) {
+    for (i, sample) in data.iter().enumerate() {
+        channels[i % num_channels].push(*sample);
+    }
+}
+
+/// Messages forwarded to the control thread from the main thread and the cpal stream callbacks.
+enum StreamMessage {
+    /// A new stream is about to start with the given number of channels and sample rate.
+    Started(usize, f32),
     Data(Vec<f32>),
     Error(String),
 }
 
+/// Audio pushed into the source from elsewhere in the app, e.g. the soundtrack of a video.
+#[derive(Debug, Clone)]
+pub struct AudioFeed {
+    pub channels: usize,
+    pub sample_rate: f32,
+
+    receiver: Arc<Mutex<Receiver<Vec<f32>>>>,
+}
+
+impl AudioFeed {
+    /// Create a feed and the sender that interleaved samples are pushed into, in real time.
+    pub fn new(channels: usize, sample_rate: f32) -> (Self, Sender<Vec<f32>>) {
+        let (sender, receiver) = channel();
+        let feed = Self {
+            channels,
+            sample_rate,
+            receiver: Arc::new(Mutex::new(receiver)),
+        };
+        (feed, sender)
+    }
+}
+
+impl PartialEq for AudioFeed {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.receiver, &other.receiver)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Subscriber {
     name: String,
@@ -25,157 +142,629 @@ pub struct Subscriber {
 #[derive(Debug, Clone)]
 pub enum ControlMessage {
     Close,
+    SetGain(GainSettings),
+    SetLatency(Duration),
     Subscribe(Subscriber),
     Unsubscribe(String),
 }
 
 pub type Subscribers = HashMap<String, Sender<AudioMessage>>;
 
+/// How long the control thread waits for stream data before checking for control messages.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+/// How long a running stream may go without delivering data before it is considered lost.
+const STALL_TIMEOUT: Duration = Duration::from_secs(2);
+/// Delay before the first attempt to reopen a lost stream, doubled after every failure.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(8);
+/// How long the clip indicator stays lit after the last clipped frame.
+const CLIP_HOLD: Duration = Duration::from_secs(1);
+/// Fraction of the displayed peak kept per update when the level falls.
+const PEAK_DECAY: f32 = 0.9;
+/// Largest latency compensation in either direction, in ms.
+pub const MAX_LATENCY: f32 = 1000.0;
+
+/// Delay applied to frames before they reach subscribers for a latency setting in ms.
+/// Negative settings mean the sound is ahead of the visuals, so analysis is not delayed.
+pub fn analysis_delay(latency: f32) -> Duration {
+    Duration::from_micros((latency.clamp(0.0, MAX_LATENCY) * 1000.0).round() as u64)
+}
+
+/// Delay for audio played back by the app for a latency setting in ms.
+pub fn monitor_delay(latency: f32) -> Duration {
+    analysis_delay(-latency)
+}
+
+/// Progress of reopening a lost audio stream.
+#[derive(Debug)]
+struct Recovery {
+    attempt: u32,
+    next_attempt: Instant,
+    reason: String,
+}
+
+/// Delay before the given retry attempt.
+pub fn backoff(attempt: u32) -> Duration {
+    INITIAL_BACKOFF
+        .checked_mul(2u32.saturating_pow(attempt))
+        .unwrap_or(MAX_BACKOFF)
+        .min(MAX_BACKOFF)
+}
+
+/// Available input devices, the preferred device first, then the default, then the rest.
+fn input_devices(preferred: Option<&str>) -> Vec<cpal::Device> {
+    let host = cpal::default_host();
+    let mut devices: Vec<cpal::Device> = match host.input_devices() {
+        Ok(d) => d.collect(),
+        Err(_) => vec![],
+    };
+
+    let default_name = host.default_input_device().and_then(|d| d.name().ok());
+    let rank = |device: &cpal::Device| {
+        let name = device.name().ok();
+        if name.is_some() && name.as_deref() == preferred {
+            0
+        } else if name.is_some() && name == default_name {
+            1
+        } else {
+            2
+        }
+    };
+    devices.sort_by_key(rank);
+
+    devices
+}
+
+/// Control thread body. Splits incoming audio into frames, applies the gain stage and sends
+/// them to subscribers once they have been delayed for latency compensation. Reports the levels
+/// of every frame, and stream errors or stalls to the main thread, which is responsible for
+/// reopening the stream.
+/// Subscribers are kept across stream restarts.
+fn run_control(
+    stream_channel_rx: Receiver<StreamMessage>,
+    control_channel_rx: Receiver<ControlMessage>,
+    error_channel_tx: Sender<String>,
+    levels_channel_tx: Sender<Levels>,
+    gain: GainSettings,
+    delay: Duration,
+) {
+    let mut subscribers = Subscribers::new();
+    let mut gain_stage = GainStage::new(gain);
+    let mut delay = delay;
+    let mut delayed: VecDeque<(Instant, AudioMessage)> = VecDeque::new();
+    let mut sample_rate = 44100.0;
+    let mut num_channels = 1;
+    let mut pending = vec![vec![]; num_channels];
+    let mut streaming = false;
+    let mut last_data = Instant::now();
+
+    'outer: loop {
+        // de-interleave incoming audio and forward full frames to subscribers
+        match stream_channel_rx.recv_timeout(POLL_INTERVAL) {
+            Ok(StreamMessage::Started(channels, rate)) => {
+                num_channels = channels;
+                if rate != sample_rate {
+                    // queued behind the frames of the previous stream
+                    sample_rate = rate;
+                    delayed.push_back((Instant::now(), AudioMessage::SampleRate(rate)));
+                }
+                pending = vec![vec![]; num_channels];
+                streaming = true;
+                last_data = Instant::now();
+            }
+            // a stopped generator or feed may still deliver a chunk with the previous
+            // channel layout after the next stream started
+            Ok(StreamMessage::Data(data)) if data.len() % num_channels != 0 => (),
+            Ok(StreamMessage::Data(data)) => {
+                streaming = true;
+                last_data = Instant::now();
+                deinterleave(&data, num_channels, &mut pending);
+
+                while pending.iter().all(|c| c.len() >= FRAME_SIZE) {
+                    let mut frame = pending
+                        .iter_mut()
+                        .map(|c| c.drain(..FRAME_SIZE).collect())
+                        .collect::<AudioFrame>();
+                    let levels = gain_stage.process(&mut frame, sample_rate);
+                    levels_channel_tx.send(levels).ok();
+                    delayed.push_back((Instant::now(), AudioMessage::Data(frame)));
+                }
+            }
+            Ok(StreamMessage::Error(error)) => {
+                // only report the first error of a stream, the next one starts a new report
+                if streaming {
+                    streaming = false;
+                    error_channel_tx.send(error).ok();
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => break 'outer,
+        }
+
+        // forward frames that have been held back long enough
+        while let Some((received, _)) = delayed.front() {
+            if received.elapsed() < delay {
+                break;
+            }
+
+            if let Some((_, msg)) = delayed.pop_front() {
+                // drop subscribers that have gone away
+                subscribers.retain(|_, s| s.send(msg.clone()).is_ok());
+            }
+        }
+
+        // some backends stop calling back without an error when a device disappears
+        if streaming && last_data.elapsed() > STALL_TIMEOUT {
+            streaming = false;
+            error_channel_tx
+                .send(String::from("Audio input stopped delivering data"))
+                .ok();
+        }
+
+        // receive message from the main thread
+        for msg in control_channel_rx.try_iter() {
+            match msg {
+                ControlMessage::Close => {
+                    for s in subscribers.values() {
+                        s.send(AudioMessage::Close).ok();
+                    }
+                    break 'outer;
+                }
+                ControlMessage::SetGain(settings) => {
+                    gain_stage.settings = settings;
+                }
+                ControlMessage::SetLatency(latency) => {
+                    delay = latency;
+                }
+                ControlMessage::Subscribe(Subscriber { name, channel }) => {
+                    channel.send(AudioMessage::SampleRate(sample_rate)).ok();
+                    subscribers.insert(name, channel);
+                }
+                ControlMessage::Unsubscribe(name) => {
+                    subscribers.remove(&name);
+                }
+            }
+        }
+    }
+}
+
+/// Feed thread body, forwards pushed audio until stopped or the sender goes away.
+fn run_feed(feed: AudioFeed, stream_channel_tx: Sender<StreamMessage>, stop: Arc<AtomicBool>) {
+    let receiver = feed.receiver.lock().unwrap();
+
+    while !stop.load(Ordering::Relaxed) {
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(data) => {
+                if stream_channel_tx.send(StreamMessage::Data(data)).is_err() {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+}
+
+/// Generator thread body, produces frames paced in real time until stopped.
+fn run_generator(
+    settings: GeneratorSettings,
+    stream_channel_tx: Sender<StreamMessage>,
+    stop: Arc<AtomicBool>,
+) {
+    let sample_rate = settings.sample_rate.max(1.0) as f64;
+    let mut generator = SignalGenerator::new(settings);
+    let start = Instant::now();
+    let mut generated: u64 = 0;
+
+    while !stop.load(Ordering::Relaxed) {
+        // pace against the start time so the stream doesn't drift
+        let due = start + Duration::from_secs_f64(generated as f64 / sample_rate);
+        let now = Instant::now();
+        if due > now {
+            // wake up regularly so stopping doesn't wait for a slow generator
+            thread::sleep((due - now).min(POLL_INTERVAL));
+            continue;
+        }
+
+        let data = generator.generate(FRAME_SIZE);
+        if stream_channel_tx.send(StreamMessage::Data(data)).is_err() {
+            break;
+        }
+        generated += FRAME_SIZE as u64;
+    }
+}
+
 pub struct AudioSource {
+    pub channels: usize,
+    pub device_name: Option<String>,
     pub error: Option<String>,
+    /// When set, sessions are fed from elsewhere in the app, this takes precedence over the
+    /// generator and input devices.
+    pub feed: Option<AudioFeed>,
+    /// Input gain applied before frames are sent to subscribers.
+    pub gain: GainSettings,
+    /// When set, sessions are fed by the signal generator instead of an input device.
+    pub generator: Option<GeneratorSettings>,
+    /// Audio/visual latency compensation in ms. Positive values delay the audio analysis,
+    /// negative values delay audio played back by the app.
+    pub latency: f32,
+    /// Levels after the gain stage for the level meter, the peak falls back gradually.
+    pub levels: Levels,
     pub sample_rate: f32,
 
+    clip_time: Option<Instant>,
     control_channel_tx: Option<Sender<ControlMessage>>,
     control_thread: Option<std::thread::JoinHandle<()>>,
     error_channel_rx: Option<Receiver<String>>,
+    levels_channel_rx: Option<Receiver<Levels>>,
+    recovery: Option<Recovery>,
     running: bool,
     stream: Option<cpal::Stream>,
+    stream_channel_tx: Option<Sender<StreamMessage>>,
     subscriber_count: i32,
+    /// The generator or feed thread, joined when the stream stops so none of its data arrives
+    /// after the next stream started.
+    stream_thread: Option<std::thread::JoinHandle<()>>,
+    /// Stops the generator or feed thread.
+    thread_stop: Option<Arc<AtomicBool>>,
 }
 
 impl AudioSource {
     pub fn new() -> Self {
         Self {
+            channels: 1,
+            clip_time: None,
             control_channel_tx: None,
             control_thread: None,
+            device_name: None,
             error: None,
             error_channel_rx: None,
+            feed: None,
+            gain: GainSettings::default(),
+            generator: None,
+            latency: 0.0,
+            levels: Levels::default(),
+            levels_channel_rx: None,
+            recovery: None,
             sample_rate: 44100.0,
             running: false,
             stream: None,
+            stream_channel_tx: None,
             subscriber_count: 0,
+            stream_thread: None,
+            thread_stop: None,
         }
     }
 
-    pub fn start_session(&mut self) -> bool {
-        // get default audio input device
-        let audio_device = match cpal::default_host().default_input_device() {
-            Some(device) => device,
-            None => {
-                self.error = Some(String::from("Unable to connect to default audio device"));
-                return false;
-            }
+    /// Build and start a stream on the given device, forwarding its data to the control thread.
+    fn open_device(&mut self, device: &cpal::Device) -> Result<(), String> {
+        let stream_channel_tx = match &self.stream_channel_tx {
+            Some(tx) => tx.clone(),
+            None => return Err(String::from("Audio session not started")),
         };
 
         // find supported config
-        let supported_configs = match audio_device.supported_input_configs() {
+        let supported_configs = match device.supported_input_configs() {
             Ok(mut configs) => match configs.next() {
                 Some(config) => config,
-                None => {
-                    self.error = Some(String::from("No audio configuration available"));
-                    return false;
-                }
+                None => return Err(String::from("No audio configuration available")),
             },
-            Err(e) => {
-                self.error = Some(format!("Error configuring audio input: {:?}", e));
-                return false;
-            }
+            Err(e) => return Err(format!("Error configuring audio input: {:?}", e)),
         };
 
         let audio_config = supported_configs.with_max_sample_rate();
         let cpal::SampleRate(sample_rate) = audio_config.sample_rate();
-        self.sample_rate = sample_rate as f32;
-
-        let (control_channel_tx, control_channel_rx) = channel();
-        self.control_channel_tx = Some(control_channel_tx);
-
-        let (audio_channel_tx, audio_channel_rx) = channel();
-        let audio_channel_tx2 = audio_channel_tx.clone();
+        let num_channels = audio_config.channels().max(1) as usize;
 
         // build audio stream
-        let stream_builder = audio_device.build_input_stream(
+        let data_tx = stream_channel_tx.clone();
+        let error_tx = stream_channel_tx.clone();
+        let stream = match device.build_input_stream(
             &audio_config.config(),
             move |data: &[f32], _: &cpal::InputCallbackInfo| {
-                audio_channel_tx
-                    .send(AudioMessage::Data(data.to_vec()))
-                    .unwrap();
+                data_tx.send(StreamMessage::Data(data.to_vec())).ok();
             },
             move |err| {
                 let message = format!("Error reading frame from audio stream: {:?}", err);
-                audio_channel_tx2
-                    .send(AudioMessage::Error(message))
-                    .unwrap();
+                error_tx.send(StreamMessage::Error(message)).ok();
             },
-        );
+        ) {
+            Ok(s) => s,
+            Err(e) => return Err(format!("Error creating audio stream: {:?}", e)),
+        };
+
+        // let the control thread know the channel layout before any data arrives
+        stream_channel_tx
+            .send(StreamMessage::Started(num_channels, sample_rate as f32))
+            .ok();
+
+        // start stream
+        if let Err(e) = stream.play() {
+            return Err(format!("Error starting audio stream: {:?}", e));
+        }
+
+        self.sample_rate = sample_rate as f32;
+        self.channels = num_channels;
+        self.device_name = device.name().ok();
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    /// Start feeding the control thread from the signal generator.
+    fn start_generator(&mut self, settings: GeneratorSettings) -> Result<(), String> {
+        let stream_channel_tx = match &self.stream_channel_tx {
+            Some(tx) => tx.clone(),
+            None => return Err(String::from("Audio session not started")),
+        };
+
+        let num_channels = settings.channels.max(1);
+        stream_channel_tx
+            .send(StreamMessage::Started(num_channels, settings.sample_rate))
+            .ok();
+
+        self.sample_rate = settings.sample_rate;
+        self.channels = num_channels;
+        self.device_name = None;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        self.thread_stop = Some(stop.clone());
+        self.stream_thread = Some(thread::spawn(move || {
+            run_generator(settings, stream_channel_tx, stop)
+        }));
+
+        Ok(())
+    }
+
+    /// Start forwarding audio pushed into a feed to the control thread.
+    fn start_feed(&mut self, feed: AudioFeed) -> Result<(), String> {
+        let stream_channel_tx = match &self.stream_channel_tx {
+            Some(tx) => tx.clone(),
+            None => return Err(String::from("Audio session not started")),
+        };
+
+        let num_channels = feed.channels.max(1);
+        stream_channel_tx
+            .send(StreamMessage::Started(num_channels, feed.sample_rate))
+            .ok();
+
+        self.sample_rate = feed.sample_rate;
+        self.channels = num_channels;
+        self.device_name = None;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        self.thread_stop = Some(stop.clone());
+        self.stream_thread = Some(thread::spawn(move || {
+            run_feed(feed, stream_channel_tx, stop)
+        }));
+
+        Ok(())
+    }
+
+    /// Stop the current input stream or generator, if any.
+    fn stop_stream(&mut self) {
+        if let Some(stream) = self.stream.take() {
+            stream.pause().ok();
+        }
+
+        if let Some(stop) = self.thread_stop.take() {
+            stop.store(true, Ordering::Relaxed);
+        }
+        if let Some(thread) = self.stream_thread.take() {
+            thread.join().ok();
+        }
+    }
+
+    /// Open a stream on the feed or generator if one is configured, otherwise on the first
+    /// working input device, trying the last used device first.
+    fn open_stream(&mut self) -> Result<(), String> {
+        self.stop_stream();
+
+        if let Some(feed) = self.feed.clone() {
+            return self.start_feed(feed);
+        }
+
+        if let Some(settings) = self.generator.clone() {
+            return self.start_generator(settings);
+        }
+
+        let devices = input_devices(self.device_name.as_deref());
+        if devices.is_empty() {
+            return Err(String::from("No audio input device available"));
+        }
+
+        let mut errors = vec![];
+        for device in devices.iter() {
+            match self.open_device(device) {
+                Ok(()) => return Ok(()),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        Err(errors.join("\n"))
+    }
+
+    pub fn start_session(&mut self) {
+        if self.running {
+            return;
+        }
+
+        let (control_channel_tx, control_channel_rx) = channel();
+        self.control_channel_tx = Some(control_channel_tx);
+
+        let (stream_channel_tx, stream_channel_rx) = channel();
+        self.stream_channel_tx = Some(stream_channel_tx);
 
         let (error_channel_tx, error_channel_rx) = channel();
         self.error_channel_rx = Some(error_channel_rx);
 
-        self.control_thread = Some(thread::spawn(move || {
-            let mut subscribers = Subscribers::new();
-
-            'outer: loop {
-                // forward audio messages to subscribers
-                if let Ok(msg) = audio_channel_rx.try_recv() {
-                    subscribers
-                        .values()
-                        .into_iter()
-                        .for_each(|s| s.send(msg.clone()).unwrap());
-
-                    // break if error
-                    if let AudioMessage::Error(error) = msg {
-                        error_channel_tx.send(error).unwrap();
-                        break 'outer;
-                    }
-                }
+        let (levels_channel_tx, levels_channel_rx) = channel();
+        self.levels_channel_rx = Some(levels_channel_rx);
 
-                // receive message from control thread
-                if let Ok(msg) = control_channel_rx.try_recv() {
-                    match msg {
-                        ControlMessage::Close => {
-                            subscribers
-                                .values()
-                                .into_iter()
-                                .for_each(|s| s.send(AudioMessage::Close).unwrap());
-                            break 'outer;
-                        }
-                        ControlMessage::Subscribe(Subscriber { name, channel }) => {
-                            subscribers.insert(name, channel);
-                        }
-                        ControlMessage::Unsubscribe(name) => {
-                            subscribers.remove(&name);
-                        }
-                    }
-                }
-            }
+        let gain = self.gain;
+        let delay = analysis_delay(self.latency);
+        self.control_thread = Some(thread::spawn(move || {
+            run_control(
+                stream_channel_rx,
+                control_channel_rx,
+                error_channel_tx,
+                levels_channel_tx,
+                gain,
+                delay,
+            );
         }));
 
-        // create stream
-        let stream = match stream_builder {
-            Ok(s) => s,
-            Err(e) => {
-                self.error = Some(format!("Error creating audio stream: {:?}", e));
-                return false;
+        self.running = true;
+        self.recovery = None;
+
+        // keep the session alive without a device so subscribers start receiving once one appears
+        if let Err(e) = self.open_stream() {
+            self.begin_recovery(e);
+        }
+    }
+
+    /// Switch between the signal generator and input devices.
+    /// A running session switches over without losing its subscribers.
+    pub fn set_generator(&mut self, generator: Option<GeneratorSettings>) {
+        if generator == self.generator {
+            return;
+        }
+
+        self.generator = generator;
+        self.reopen_stream();
+    }
+
+    /// Switch between a feed and the generator or input devices, see `set_generator`.
+    pub fn set_feed(&mut self, feed: Option<AudioFeed>) {
+        if feed == self.feed {
+            return;
+        }
+
+        self.feed = feed;
+        self.reopen_stream();
+    }
+
+    /// Reopen the stream of a running session after its backend changed.
+    fn reopen_stream(&mut self) {
+        if self.running {
+            self.recovery = None;
+            self.error = None;
+            if let Err(e) = self.open_stream() {
+                self.begin_recovery(e);
             }
+        }
+    }
+
+    /// Change the input gain, a running session applies it from the next frame.
+    pub fn set_gain(&mut self, gain: GainSettings) {
+        if gain == self.gain {
+            return;
+        }
+
+        self.gain = gain;
+        self.send_control_message(ControlMessage::SetGain(gain));
+    }
+
+    /// Change the latency compensation in ms, see `latency`.
+    pub fn set_latency(&mut self, latency: f32) {
+        let latency = latency.clamp(-MAX_LATENCY, MAX_LATENCY);
+        if latency == self.latency {
+            return;
+        }
+
+        self.latency = latency;
+        self.send_control_message(ControlMessage::SetLatency(analysis_delay(latency)));
+    }
+
+    /// Whether a frame was clipped recently.
+    pub fn clipping(&self) -> bool {
+        match self.clip_time {
+            Some(time) => time.elapsed() < CLIP_HOLD,
+            None => false,
+        }
+    }
+
+    /// Whether a session is running.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Fold the levels of the frames processed since the last update into the meter.
+    fn update_levels(&mut self) {
+        let received = match self.levels_channel_rx.as_ref() {
+            Some(rx) => rx.try_iter().collect::<Vec<Levels>>(),
+            None => return,
         };
 
-        // start stream
-        match stream.play() {
-            Ok(()) => (),
+        let last = match received.last() {
+            Some(levels) => *levels,
+            None => return,
+        };
+
+        let peak = received.iter().map(|l| l.peak).fold(0.0, f32::max);
+        if received.iter().any(|l| l.clipped) {
+            self.clip_time = Some(Instant::now());
+        }
+
+        self.levels = Levels {
+            peak: peak.max(self.levels.peak * PEAK_DECAY),
+            ..last
+        };
+    }
+
+    /// Drop the current stream and schedule attempts to reopen one.
+    fn begin_recovery(&mut self, reason: String) {
+        println!("Audio error: {}", reason);
+        self.stop_stream();
+
+        if self.recovery.is_none() {
+            self.recovery = Some(Recovery {
+                attempt: 0,
+                next_attempt: Instant::now() + backoff(0),
+                reason,
+            });
+        }
+
+        self.update_recovery_error();
+    }
+
+    fn attempt_recovery(&mut self) {
+        match self.open_stream() {
+            Ok(()) => {
+                println!(
+                    "Audio input recovered on {}",
+                    self.device_name.as_deref().unwrap_or("unknown device")
+                );
+                self.recovery = None;
+                self.error = None;
+            }
             Err(e) => {
-                self.error = Some(format!("Error starting audio stream: {:?}", e));
-                return false;
+                if let Some(recovery) = self.recovery.as_mut() {
+                    recovery.attempt += 1;
+                    recovery.next_attempt = Instant::now() + backoff(recovery.attempt);
+                    recovery.reason = e;
+                }
+                self.update_recovery_error();
             }
-        };
+        }
+    }
 
-        self.stream = Some(stream);
-        self.running = true;
-        true
+    fn update_recovery_error(&mut self) {
+        if let Some(recovery) = &self.recovery {
+            let wait = recovery
+                .next_attempt
+                .saturating_duration_since(Instant::now());
+            self.error = Some(format!(
+                "{}\nReconnecting in {:.1}s (attempt {})",
+                recovery.reason,
+                wait.as_secs_f32(),
+                recovery.attempt + 1
+            ));
+        }
     }
 
     pub fn send_control_message(&mut self, msg: ControlMessage) {
         if let Some(control_channel) = &self.control_channel_tx {
-            control_channel.send(msg).unwrap();
+            control_channel.send(msg).ok();
         }
     }
 
@@ -185,26 +774,43 @@ impl AudioSource {
         }
 
         self.error = None;
-
-        // stop the stream
-        if let Some(stream) = self.stream.as_ref() {
-            stream.pause().ok();
-        }
+        self.recovery = None;
+        self.stop_stream();
 
         self.send_control_message(ControlMessage::Close);
+        self.stream_channel_tx = None;
+        self.levels_channel_rx = None;
+        self.levels = Levels::default();
+        self.clip_time = None;
         self.running = false;
     }
 
+    /// Check for stream errors and drive recovery, never blocks for longer than a device open.
     pub fn update(&mut self) {
         if !self.running {
             return;
         }
 
+        self.update_levels();
+
         // check the error channel for errors
-        if let Ok(err) = self.error_channel_rx.as_ref().unwrap().try_recv() {
-            println!("Audio error: {:?}", err);
-            self.end_session();
-            return;
+        let error = match self.error_channel_rx.as_ref() {
+            Some(rx) => rx.try_iter().last(),
+            None => None,
+        };
+        if let Some(err) = error {
+            self.begin_recovery(err);
+        }
+
+        let due = match &self.recovery {
+            Some(recovery) => Instant::now() >= recovery.next_attempt,
+            None => return,
+        };
+
+        if due {
+            self.attempt_recovery();
+        } else {
+            self.update_recovery_error();
         }
     }
 
@@ -229,3 +835,65 @@ impl AudioSource {
         }
     }
 }
+
+#[cfg(test)]
+#[test]
+fn test_channel_modes() {
+    let mut channels = vec![vec![], vec![]];
+    deinterleave(&[1.0, 0.0, 0.5, 0.5], 2, &mut channels);
+    assert_eq!(channels, vec![vec![1.0, 0.5], vec![0.0, 0.5]]);
+
+    assert_eq!(ChannelMode::Mono.apply(&channels), vec![vec![0.5, 0.5]]);
+    assert_eq!(ChannelMode::Right.apply(&channels), vec![vec![0.0, 0.5]]);
+    assert_eq!(
+        ChannelMode::MidSide.apply(&channels),
+        vec![vec![0.5, 0.5], vec![0.5, 0.0]]
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_backoff() {
+    assert_eq!(backoff(0), INITIAL_BACKOFF);
+    assert_eq!(backoff(1), INITIAL_BACKOFF * 2);
+    assert_eq!(backoff(10), MAX_BACKOFF);
+    assert_eq!(backoff(u32::MAX), MAX_BACKOFF);
+}
+
+#[cfg(test)]
+#[test]
+fn test_latency() {
+    assert_eq!(analysis_delay(120.0), Duration::from_millis(120));
+    assert_eq!(analysis_delay(-120.0), Duration::from_secs(0));
+    assert_eq!(monitor_delay(-120.0), Duration::from_millis(120));
+    assert_eq!(monitor_delay(120.0), Duration::from_secs(0));
+    assert_eq!(analysis_delay(5000.0), Duration::from_secs(1));
+}
+
+#[cfg(test)]
+#[test]
+fn test_generator_backend() {
+    let mut audio_source = AudioSource::new();
+    audio_source.set_generator(Some(GeneratorSettings::default()));
+
+    let (tx, rx) = channel();
+    audio_source.subscribe(String::from("test"), tx);
+
+    // subscribers are told the sample rate first
+    match rx.recv_timeout(Duration::from_secs(1)) {
+        Ok(AudioMessage::SampleRate(rate)) => {
+            assert_eq!(rate, GeneratorSettings::default().sample_rate)
+        }
+        other => panic!("expected the sample rate, got {:?}", other),
+    }
+
+    match rx.recv_timeout(Duration::from_secs(1)) {
+        Ok(AudioMessage::Data(frame)) => {
+            assert_eq!(frame.len(), 2);
+            assert_eq!(frame[0].len(), FRAME_SIZE);
+        }
+        other => panic!("expected a frame, got {:?}", other),
+    }
+
+    audio_source.unsubscribe(String::from("test"));
+}
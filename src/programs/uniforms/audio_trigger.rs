@@ -0,0 +1,351 @@
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+
+use crate::programs::uniforms::audio_source;
+
+/// Number of past spectral flux values the onset threshold adapts to, about half a second.
+const ONSET_HISTORY: usize = 43;
+/// Lowest spectral flux that may count as an onset, keeps noise in silence from firing.
+const ONSET_FLOOR: f32 = 0.01;
+
+fn default_sensitivity() -> f32 {
+    1.5
+}
+
+fn default_min_interval() -> f32 {
+    100.0
+}
+
+/// An audio event that fires an ISF `event` input.
+/// All intervals are in milliseconds and frequencies in Hz.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Trigger {
+    /// Fires when the spectral flux jumps above its recent average times `sensitivity`.
+    Onset {
+        #[serde(default = "default_sensitivity")]
+        sensitivity: f32,
+        #[serde(default = "default_min_interval")]
+        min_interval: f32,
+    },
+    /// Fires when the level of the band between `from` and `to` rises above `threshold`,
+    /// and rearms once it falls below `threshold - hysteresis`.
+    Threshold {
+        from: f32,
+        to: f32,
+        threshold: f32,
+        #[serde(default)]
+        hysteresis: f32,
+        #[serde(default = "default_min_interval")]
+        min_interval: f32,
+    },
+    /// Fires on every `every`th onset. There is no tempo tracking, so onsets between beats
+    /// like hi-hats and fills count as well, this follows the beat on sparse material.
+    EveryNthOnset {
+        every: u32,
+        #[serde(default = "default_sensitivity")]
+        sensitivity: f32,
+        #[serde(default = "default_min_interval")]
+        min_interval: f32,
+    },
+}
+
+impl Trigger {
+    fn min_interval(&self) -> f32 {
+        match *self {
+            Trigger::Onset { min_interval, .. }
+            | Trigger::Threshold { min_interval, .. }
+            | Trigger::EveryNthOnset { min_interval, .. } => min_interval,
+        }
+    }
+}
+
+/// Detects a trigger in consecutive frames of mono audio.
+pub struct TriggerDetector {
+    pub trigger: Trigger,
+
+    armed: bool,
+    onset_count: u32,
+    fft: Option<Arc<dyn Fft<f32>>>,
+    flux_history: VecDeque<f32>,
+    magnitudes: Vec<f32>,
+    planner: FftPlanner<f32>,
+    sample_rate: f32,
+    since_last: f32,
+    window: Vec<f32>,
+}
+
+impl TriggerDetector {
+    pub fn new(trigger: Trigger, sample_rate: f32) -> Self {
+        Self {
+            trigger,
+            armed: true,
+            onset_count: 0,
+            fft: None,
+            flux_history: VecDeque::with_capacity(ONSET_HISTORY),
+            magnitudes: vec![],
+            planner: FftPlanner::new(),
+            sample_rate,
+            since_last: f32::INFINITY,
+            window: vec![],
+        }
+    }
+
+    /// Magnitude spectrum of a frame, normalised so a full scale sine peaks at about 1.
+    /// The FFT is planned again whenever the frame size changes.
+    fn spectrum(&mut self, samples: &[f32]) -> Vec<f32> {
+        if self.window.len() != samples.len() {
+            self.fft = Some(self.planner.plan_fft_forward(samples.len()));
+            self.window = apodize::hanning_iter(samples.len())
+                .map(|w| w as f32)
+                .collect();
+        }
+
+        let mut buffer = samples
+            .iter()
+            .zip(self.window.iter())
+            .map(|(s, w)| Complex::new(s * w, 0.0))
+            .collect::<Vec<_>>();
+        if let Some(fft) = &self.fft {
+            fft.process(&mut buffer);
+        }
+
+        let scale = 2.0 / self.window.iter().sum::<f32>().max(1.0);
+        buffer
+            .iter()
+            .take(buffer.len() / 2)
+            .map(|c| c.norm() * scale)
+            .collect()
+    }
+
+    /// Whether the spectral flux of this spectrum stands out against the recent average.
+    fn onset(&mut self, spectrum: &[f32], sensitivity: f32) -> bool {
+        let flux: f32 = spectrum
+            .iter()
+            .zip(self.magnitudes.iter())
+            .map(|(m, prev)| (m - prev).max(0.0))
+            .sum();
+
+        let average = match self.flux_history.len() {
+            0 => 0.0,
+            n => self.flux_history.iter().sum::<f32>() / n as f32,
+        };
+
+        if self.flux_history.len() == ONSET_HISTORY {
+            self.flux_history.pop_front();
+        }
+        self.flux_history.push_back(flux);
+
+        flux > ONSET_FLOOR && flux > average * sensitivity
+    }
+
+    /// Mean magnitude of the bins between two frequencies.
+    fn band_level(&self, spectrum: &[f32], from: f32, to: f32) -> f32 {
+        let bin_width = self.sample_rate / (spectrum.len() * 2) as f32;
+        let start = ((from.min(to) / bin_width) as usize).min(spectrum.len() - 1);
+        let end = ((from.max(to) / bin_width).ceil() as usize).clamp(start + 1, spectrum.len());
+        spectrum[start..end].iter().sum::<f32>() / (end - start) as f32
+    }
+
+    /// Analyse the next frame, returns whether the trigger fired.
+    pub fn process(&mut self, samples: &[f32]) -> bool {
+        if samples.is_empty() {
+            return false;
+        }
+
+        self.since_last += samples.len() as f32 / self.sample_rate * 1000.0;
+        let spectrum = self.spectrum(samples);
+        let ready = self.since_last >= self.trigger.min_interval();
+
+        let fired = match self.trigger.clone() {
+            Trigger::Onset { sensitivity, .. } => self.onset(&spectrum, sensitivity) && ready,
+            Trigger::Threshold {
+                from,
+                to,
+                threshold,
+                hysteresis,
+                ..
+            } => {
+                let level = self.band_level(&spectrum, from, to);
+                if !self.armed && level < threshold - hysteresis {
+                    self.armed = true;
+                }
+                if self.armed && ready && level > threshold {
+                    self.armed = false;
+                    true
+                } else {
+                    false
+                }
+            }
+            Trigger::EveryNthOnset {
+                every, sensitivity, ..
+            } => {
+                if self.onset(&spectrum, sensitivity) && ready {
+                    self.onset_count += 1;
+                    // the interval restarts on every onset, not only the ones that fire
+                    self.since_last = 0.0;
+                    self.onset_count % every.max(1) == 0
+                } else {
+                    false
+                }
+            }
+        };
+
+        self.magnitudes = spectrum;
+        if fired {
+            self.since_last = 0.0;
+        }
+
+        fired
+    }
+}
+
+/// Detection thread body, counts fired triggers until the session closes.
+fn run_detection(
+    audio_channel_rx: Receiver<audio_source::AudioMessage>,
+    mut detector: TriggerDetector,
+    fired: Arc<AtomicUsize>,
+) {
+    for message in audio_channel_rx.iter() {
+        match message {
+            audio_source::AudioMessage::Data(frame) => {
+                if detector.process(&audio_source::mix_down(&frame)) {
+                    fired.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            audio_source::AudioMessage::SampleRate(rate) => {
+                if rate != detector.sample_rate {
+                    detector = TriggerDetector::new(detector.trigger, rate);
+                }
+            }
+            audio_source::AudioMessage::Close => break,
+        }
+    }
+}
+
+/// Runs a trigger detector on the audio source in the background.
+#[derive(Debug)]
+pub struct AudioTrigger {
+    pub trigger: Trigger,
+
+    audio_channel_tx: Option<Sender<audio_source::AudioMessage>>,
+    fired: Arc<AtomicUsize>,
+    name: String,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl AudioTrigger {
+    /// `name` identifies the subscription to the audio source and must be unique.
+    pub fn new(trigger: Trigger, name: String) -> Self {
+        Self {
+            trigger,
+            audio_channel_tx: None,
+            fired: Arc::new(AtomicUsize::new(0)),
+            name,
+            thread: None,
+        }
+    }
+
+    pub fn start_session(&mut self, audio_source: &mut audio_source::AudioSource) {
+        let (audio_channel_tx, audio_channel_rx) = channel();
+        audio_source.subscribe(self.name.clone(), audio_channel_tx.clone());
+        self.audio_channel_tx = Some(audio_channel_tx);
+
+        let detector = TriggerDetector::new(self.trigger.clone(), audio_source.sample_rate);
+        let fired = self.fired.clone();
+        self.thread = Some(thread::spawn(move || {
+            run_detection(audio_channel_rx, detector, fired);
+        }));
+    }
+
+    pub fn end_session(&mut self, audio_source: &mut audio_source::AudioSource) {
+        audio_source.unsubscribe(self.name.clone());
+
+        if let Some(channel) = self.audio_channel_tx.take() {
+            channel.send(audio_source::AudioMessage::Close).ok();
+        }
+
+        if let Some(handle) = self.thread.take() {
+            handle.join().unwrap();
+        }
+
+        self.fired.store(0, Ordering::Relaxed);
+    }
+
+    /// Whether the trigger has fired since the last poll.
+    pub fn poll(&self) -> bool {
+        self.fired.swap(0, Ordering::Relaxed) > 0
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_trigger_detector() {
+    use crate::programs::uniforms::audio_generator::{
+        GeneratorSettings, GeneratorSource, Signal, SignalGenerator,
+    };
+
+    let sample_rate = 44100.0;
+    let frame_size = audio_source::FRAME_SIZE;
+    let generate = |signal: Signal, num_frames: usize| {
+        let mut generator = SignalGenerator::new(GeneratorSettings {
+            channels: 1,
+            sample_rate,
+            sources: vec![GeneratorSource {
+                signal,
+                amplitude: 1.0,
+            }],
+        });
+        generator.generate(frame_size * num_frames)
+    };
+    let count = |detector: &mut TriggerDetector, samples: &[f32]| {
+        samples
+            .chunks(frame_size)
+            .filter(|frame| detector.process(frame))
+            .count()
+    };
+
+    // just under 4 seconds of clicks at 120 bpm
+    let clicks = generate(Signal::Clicks { bpm: 120.0 }, 340);
+    let mut onset = TriggerDetector::new(
+        serde_json::from_str(r#"{ "type": "onset" }"#).unwrap(),
+        sample_rate,
+    );
+    assert_eq!(count(&mut onset, &clicks), 8);
+
+    let mut every_nth = TriggerDetector::new(
+        Trigger::EveryNthOnset {
+            every: 4,
+            sensitivity: default_sensitivity(),
+            min_interval: default_min_interval(),
+        },
+        sample_rate,
+    );
+    assert_eq!(count(&mut every_nth, &clicks), 2);
+
+    // a steady tone crosses the threshold once, then stays above it
+    let tone = generate(Signal::Sine { frequency: 1000.0 }, 100);
+    let threshold = Trigger::Threshold {
+        from: 800.0,
+        to: 1200.0,
+        threshold: 0.1,
+        hysteresis: 0.05,
+        min_interval: 0.0,
+    };
+    let mut detector = TriggerDetector::new(threshold.clone(), sample_rate);
+    assert_eq!(count(&mut detector, &tone), 1);
+
+    // and rearms after silence
+    assert_eq!(count(&mut detector, &vec![0.0; frame_size * 10]), 0);
+    assert_eq!(count(&mut detector, &tone), 1);
+
+    // a tone outside the band never fires
+    let low = generate(Signal::Sine { frequency: 100.0 }, 100);
+    let mut detector = TriggerDetector::new(threshold, sample_rate);
+    assert_eq!(count(&mut detector, &low), 0);
+}
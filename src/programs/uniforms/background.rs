@@ -0,0 +1,261 @@
+use nannou::prelude::*;
+use opencv::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::programs::config;
+use crate::programs::uniforms::analysis::{AnalysisOutput, AnalysisSender};
+use crate::programs::uniforms::base::Bufferable;
+use crate::programs::uniforms::video_capture::{FrameAnalysis, VideoCapture};
+
+/// Name of the analysis running on the capture thread.
+pub const ANALYSIS: &str = "background";
+
+/// OpenCV background subtraction algorithms.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubtractionMethod {
+    Mog2,
+    Knn,
+}
+
+/// How the background is learned and the foreground is separated from it.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackgroundSettings {
+    pub method: SubtractionMethod,
+    /// Number of frames the background model is learned from.
+    pub history: i32,
+    /// Distance from the background a pixel needs to be foreground, the algorithm's default
+    /// when `None` (16 for MOG2, 400 for KNN).
+    pub threshold: Option<f64>,
+    /// Mark shadows as half foreground instead of background.
+    pub shadows: bool,
+    /// Frames are scaled by this before the subtraction, which is also the mask's resolution.
+    pub scale: f64,
+    /// Remove speckles from the mask with a median filter.
+    pub denoise: bool,
+}
+
+impl Default for BackgroundSettings {
+    fn default() -> Self {
+        Self {
+            method: SubtractionMethod::Mog2,
+            history: 500,
+            threshold: None,
+            shadows: false,
+            scale: 0.5,
+            denoise: true,
+        }
+    }
+}
+
+/// 0 for background and 255 for foreground, row by row from the top, and the size of the
+/// mask.
+type Mask = (Vec<u8>, [u32; 2]);
+
+enum Subtractor {
+    Mog2(opencv::core::Ptr<dyn opencv::video::BackgroundSubtractorMOG2>),
+    Knn(opencv::core::Ptr<dyn opencv::video::BackgroundSubtractorKNN>),
+}
+
+/// Separates moving foreground from a learned background on the capture thread.
+struct BackgroundAnalysis {
+    settings: BackgroundSettings,
+    output: AnalysisSender<Mask>,
+    /// Created with the first frame.
+    subtractor: Option<Subtractor>,
+    small: opencv::core::Mat,
+    mask: opencv::core::Mat,
+    denoised: opencv::core::Mat,
+}
+
+impl BackgroundAnalysis {
+    fn create_subtractor(&self) -> opencv::Result<Subtractor> {
+        let settings = self.settings;
+        let history = settings.history.max(1);
+        Ok(match settings.method {
+            SubtractionMethod::Mog2 => {
+                Subtractor::Mog2(opencv::video::create_background_subtractor_mog2(
+                    history,
+                    settings.threshold.unwrap_or(16.0),
+                    settings.shadows,
+                )?)
+            }
+            SubtractionMethod::Knn => {
+                Subtractor::Knn(opencv::video::create_background_subtractor_knn(
+                    history,
+                    settings.threshold.unwrap_or(400.0),
+                    settings.shadows,
+                )?)
+            }
+        })
+    }
+
+    fn subtract(&mut self, frame: &opencv::core::Mat) -> opencv::Result<Mask> {
+        if self.subtractor.is_none() {
+            self.subtractor = Some(self.create_subtractor()?);
+        }
+
+        let scale = self.settings.scale.max(0.05).min(1.0);
+        opencv::imgproc::resize(
+            frame,
+            &mut self.small,
+            opencv::core::Size::default(),
+            scale,
+            scale,
+            opencv::imgproc::INTER_AREA,
+        )?;
+        let mut bgr = opencv::core::Mat::default();
+        opencv::imgproc::cvt_color(&self.small, &mut bgr, opencv::imgproc::COLOR_BGRA2BGR, 0)?;
+
+        // a negative learning rate lets the algorithm pick one from the history
+        match self.subtractor.as_mut().unwrap() {
+            Subtractor::Mog2(subtractor) => subtractor.apply(&bgr, &mut self.mask, -1.0)?,
+            Subtractor::Knn(subtractor) => subtractor.apply(&bgr, &mut self.mask, -1.0)?,
+        }
+
+        let mask = if self.settings.denoise {
+            opencv::imgproc::median_blur(&self.mask, &mut self.denoised, 5)?;
+            &self.denoised
+        } else {
+            &self.mask
+        };
+
+        let size = mask.size()?;
+        Ok((
+            mask.data_typed::<u8>()?.to_vec(),
+            [size.width as u32, size.height as u32],
+        ))
+    }
+}
+
+impl FrameAnalysis for BackgroundAnalysis {
+    fn name(&self) -> &'static str {
+        ANALYSIS
+    }
+
+    fn analyze(&mut self, frame: &opencv::core::Mat) {
+        let result = self.subtract(frame);
+        self.output.send(result.map(Some));
+    }
+}
+
+/// Fraction of a mask that is foreground, shadows count half.
+fn motion_amount(mask: &[u8]) -> f32 {
+    if mask.is_empty() {
+        return 0.0;
+    }
+    let sum = mask.iter().map(|m| *m as u64).sum::<u64>();
+    sum as f32 / (mask.len() as f32 * 255.0)
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Data {
+    /// Fraction of the source that is foreground, 0 to 1.
+    pub motion_amount: f32,
+}
+
+/// Foreground mask of a video or webcam as an `R8Unorm` texture, the same orientation as the
+/// source's texture, 1 where something moves in front of the learned background.
+pub struct MaskUniforms {
+    pub error: Option<String>,
+    pub settings: BackgroundSettings,
+    pub updated: bool,
+
+    data: Data,
+    output: AnalysisOutput<Mask>,
+    mask: Vec<u8>,
+    texture: wgpu::Texture,
+}
+
+impl Bufferable<Data> for MaskUniforms {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { wgpu::bytes::from(&self.data) }
+    }
+
+    fn textures(&self) -> Vec<&wgpu::Texture> {
+        vec![&self.texture]
+    }
+}
+
+fn create_texture(device: &wgpu::Device, size: [u32; 2]) -> wgpu::Texture {
+    wgpu::TextureBuilder::new()
+        .size(size)
+        .format(wgpu::TextureFormat::R8Unorm)
+        .usage(wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST)
+        .build(device)
+}
+
+impl MaskUniforms {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            error: None,
+            settings: BackgroundSettings::default(),
+            updated: false,
+            data: Data { motion_amount: 0.0 },
+            output: AnalysisOutput::new(ANALYSIS),
+            mask: vec![0],
+            texture: create_texture(device, [1, 1]),
+        }
+    }
+
+    pub fn configure(&mut self, settings: &Option<config::ProgramSettings>) {
+        self.settings = settings
+            .as_ref()
+            .and_then(|s| s.background_subtraction)
+            .unwrap_or_default();
+        self.relearn();
+    }
+
+    /// Forget the background, it is learned again from the next frames.
+    pub fn relearn(&mut self) {
+        self.error = None;
+        self.output.detach();
+    }
+
+    /// Subtract the background from the frames of `capture`, starting the analysis if it
+    /// doesn't run yet, and take the latest mask.
+    pub fn update(&mut self, device: &wgpu::Device, capture: &mut VideoCapture) {
+        let settings = self.settings;
+        let latest = self.output.update(capture, |output| {
+            Ok(Box::new(BackgroundAnalysis {
+                settings,
+                output,
+                subtractor: None,
+                small: opencv::core::Mat::default(),
+                mask: opencv::core::Mat::default(),
+                denoised: opencv::core::Mat::default(),
+            }))
+        });
+
+        if let Some(error) = latest.error {
+            println!("Background subtraction error: {}", error);
+            self.error = Some(error);
+        }
+        let (mask, size) = match latest.value {
+            Some((mask, size)) => capture.transform().resample(&mask, size),
+            None => return,
+        };
+
+        if self.texture.size() != size {
+            self.texture = create_texture(device, size);
+            self.updated = true;
+        }
+
+        self.data.motion_amount = motion_amount(&mask);
+        self.mask = mask;
+    }
+
+    pub fn update_texture(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        self.texture.upload_data(device, encoder, &self.mask);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_motion_amount() {
+    assert_eq!(motion_amount(&[]), 0.0);
+    assert_eq!(motion_amount(&[0, 255, 255, 0]), 0.5);
+    assert_eq!(motion_amount(&[255, 255]), 1.0);
+}
@@ -0,0 +1,143 @@
+use std::convert::TryFrom;
+
+pub const NUM_PITCH_CLASSES: usize = 12;
+pub const PITCH_CLASSES: &[&str] = &[
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Frequency range folded into the chromagram, roughly A1 to D#8.
+const MIN_FREQUENCY: f32 = 55.0;
+const MAX_FREQUENCY: f32 = 5000.0;
+
+/// Krumhansl-Kessler key profiles, starting from the tonic.
+const MAJOR_PROFILE: [f32; NUM_PITCH_CLASSES] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f32; NUM_PITCH_CLASSES] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+pub type Chroma = [f32; NUM_PITCH_CLASSES];
+
+/// Pitch class of a frequency in Hz, 0 = C.
+fn pitch_class(frequency: f32) -> usize {
+    let semitones_from_a = (12.0 * (frequency / 440.0).log2()).round() as i32;
+    (semitones_from_a + 9).rem_euclid(NUM_PITCH_CLASSES as i32) as usize
+}
+
+/// Fold the energy of the first half of an FFT's magnitudes into 12 pitch classes,
+/// normalised so the strongest class is 1.
+pub fn chromagram(magnitudes: &[f32], sample_rate: f32, fft_size: usize) -> Chroma {
+    let mut chroma = [0.0; NUM_PITCH_CLASSES];
+    let bin_width = sample_rate / fft_size as f32;
+
+    for (bin, magnitude) in magnitudes.iter().enumerate().skip(1) {
+        let frequency = bin as f32 * bin_width;
+        if frequency < MIN_FREQUENCY {
+            continue;
+        }
+        if frequency > MAX_FREQUENCY {
+            break;
+        }
+        chroma[pitch_class(frequency)] += magnitude * magnitude;
+    }
+
+    let max = chroma.iter().cloned().fold(0.0, f32::max);
+    if max > 0.0 {
+        chroma.iter_mut().for_each(|c| *c /= max);
+    }
+    chroma
+}
+
+/// Pearson correlation of a chromagram with a key profile rotated to the given tonic.
+fn correlation(chroma: &Chroma, profile: &Chroma, tonic: usize) -> f32 {
+    let n = NUM_PITCH_CLASSES as f32;
+    let chroma_mean = chroma.iter().sum::<f32>() / n;
+    let profile_mean = profile.iter().sum::<f32>() / n;
+
+    let mut covariance = 0.0;
+    let mut chroma_variance = 0.0;
+    let mut profile_variance = 0.0;
+    for (i, c) in chroma.iter().enumerate() {
+        let p = profile[(i + NUM_PITCH_CLASSES - tonic) % NUM_PITCH_CLASSES];
+        covariance += (c - chroma_mean) * (p - profile_mean);
+        chroma_variance += (c - chroma_mean).powi(2);
+        profile_variance += (p - profile_mean).powi(2);
+    }
+
+    let denominator = (chroma_variance * profile_variance).sqrt();
+    if denominator > 0.0 {
+        covariance / denominator
+    } else {
+        0.0
+    }
+}
+
+/// Estimate the key of a chromagram as (tonic, scale, strength).
+/// The scale is 0 for major and 1 for minor, the strength is the correlation with the
+/// matching key profile. The tonic is -1 for silence.
+pub fn estimate_key(chroma: &Chroma) -> (i32, i32, f32) {
+    let mut best = (-1, 0, 0.0);
+
+    for tonic in 0..NUM_PITCH_CLASSES {
+        for (scale, profile) in [MAJOR_PROFILE, MINOR_PROFILE].iter().enumerate() {
+            let strength = correlation(chroma, profile, tonic);
+            if strength > best.2 {
+                best = (tonic as i32, scale as i32, strength);
+            }
+        }
+    }
+
+    best
+}
+
+/// The strongest pitch class, -1 for silence.
+pub fn dominant_pitch_class(chroma: &Chroma) -> i32 {
+    chroma
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| **c > 0.0)
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map_or(-1, |(i, _)| i as i32)
+}
+
+/// A readable name for a key like "A minor", `None` if unknown.
+pub fn key_name(key: i32, scale: i32) -> Option<String> {
+    let tonic = PITCH_CLASSES.get(usize::try_from(key).ok()?)?;
+    let scale = if scale == 1 { "minor" } else { "major" };
+    Some(format!("{} {}", tonic, scale))
+}
+
+#[cfg(test)]
+#[test]
+fn test_chroma() {
+    assert_eq!(pitch_class(440.0), 9);
+    assert_eq!(pitch_class(261.63), 0);
+    assert_eq!(pitch_class(130.81), 0);
+
+    // a single bin at 440 Hz
+    let mut magnitudes = vec![0.0; 512];
+    magnitudes[10] = 1.0;
+    let chroma = chromagram(&magnitudes, 44000.0, 1000);
+    assert_eq!(dominant_pitch_class(&chroma), 9);
+    assert_eq!(chroma[9], 1.0);
+
+    // C major and A minor scales weighted towards their tonic triads
+    let c_major = [1.0, 0.0, 0.4, 0.0, 0.8, 0.4, 0.0, 0.9, 0.0, 0.4, 0.0, 0.3];
+    assert_eq!(estimate_key(&c_major).0, 0);
+    assert_eq!(estimate_key(&c_major).1, 0);
+    let a_minor = [0.8, 0.0, 0.3, 0.0, 0.9, 0.3, 0.0, 0.4, 0.0, 1.0, 0.0, 0.3];
+    assert_eq!(estimate_key(&a_minor).0, 9);
+    assert_eq!(estimate_key(&a_minor).1, 1);
+
+    assert_eq!(key_name(9, 1), Some(String::from("A minor")));
+    assert_eq!(estimate_key(&[0.0; 12]).0, -1);
+    assert_eq!(key_name(-1, 0), None);
+    assert_eq!(dominant_pitch_class(&[0.0; 12]), -1);
+
+    // a NaN from a silent or denormal spectrum is skipped
+    let mut chroma = [0.0; 12];
+    chroma[0] = f32::NAN;
+    chroma[4] = 0.5;
+    assert_eq!(dominant_pitch_class(&chroma), 4);
+}
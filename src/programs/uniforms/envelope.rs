@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+/// Time constant of the running peak used for auto-gain normalisation, in ms.
+const AUTO_GAIN_RELEASE: f32 = 5000.0;
+/// Lowest running peak auto-gain will normalise against, keeps silence from being amplified.
+const AUTO_GAIN_FLOOR: f32 = 0.001;
+
+/// Envelope follower settings, all times are in milliseconds.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnvelopeSettings {
+    /// Time to rise towards a louder input.
+    pub attack: f32,
+    /// Time to fall towards a quieter input.
+    pub release: f32,
+    /// How long a peak is held before releasing, 0 disables peak-hold.
+    pub peak_hold: f32,
+    /// Divide the output by a slowly decaying running peak so it stays roughly within 0-1.
+    pub auto_gain: bool,
+}
+
+impl Default for EnvelopeSettings {
+    fn default() -> Self {
+        Self {
+            attack: 10.0,
+            release: 100.0,
+            peak_hold: 0.0,
+            auto_gain: false,
+        }
+    }
+}
+
+impl EnvelopeSettings {
+    /// Settings that pass the input straight through.
+    pub fn instant() -> Self {
+        Self {
+            attack: 0.0,
+            release: 0.0,
+            peak_hold: 0.0,
+            auto_gain: false,
+        }
+    }
+}
+
+/// One-pole smoothing coefficient for a time constant in ms over a step of `dt` seconds.
+fn coefficient(time: f32, dt: f32) -> f32 {
+    if time <= 0.0 {
+        0.0
+    } else {
+        (-dt * 1000.0 / time).exp()
+    }
+}
+
+/// Follows a single signal with separate attack and release times.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct EnvelopeFollower {
+    level: f32,
+    held: f32,
+    hold_timer: f32,
+    gain_peak: f32,
+}
+
+impl EnvelopeFollower {
+    /// Advance the follower by `dt` seconds towards `input` and return the output value.
+    pub fn step(&mut self, input: f32, dt: f32, settings: &EnvelopeSettings) -> f32 {
+        let time = if input > self.level {
+            settings.attack
+        } else {
+            settings.release
+        };
+        self.level = input + (self.level - input) * coefficient(time, dt);
+
+        let mut output = self.level;
+
+        if settings.peak_hold > 0.0 {
+            if self.level >= self.held {
+                self.held = self.level;
+                self.hold_timer = settings.peak_hold / 1000.0;
+            } else if self.hold_timer > 0.0 {
+                self.hold_timer -= dt;
+            } else {
+                self.held =
+                    self.level + (self.held - self.level) * coefficient(settings.release, dt);
+            }
+            output = self.held;
+        } else {
+            self.held = self.level;
+        }
+
+        if settings.auto_gain {
+            self.gain_peak = output
+                .abs()
+                .max(self.gain_peak * coefficient(AUTO_GAIN_RELEASE, dt));
+            output /= self.gain_peak.max(AUTO_GAIN_FLOOR);
+        }
+
+        output
+    }
+}
+
+/// A set of envelope followers sharing settings, one per signal.
+/// Measures the time between updates itself so the response is independent of the frame rate.
+#[derive(Debug, Clone)]
+pub struct Envelope {
+    pub settings: EnvelopeSettings,
+
+    followers: Vec<EnvelopeFollower>,
+    last_update: Option<Instant>,
+}
+
+impl Envelope {
+    pub fn new(size: usize) -> Self {
+        Self {
+            settings: EnvelopeSettings::default(),
+            followers: vec![EnvelopeFollower::default(); size],
+            last_update: None,
+        }
+    }
+
+    /// Change the number of signals, resetting all followers.
+    pub fn resize(&mut self, size: usize) {
+        self.followers = vec![EnvelopeFollower::default(); size];
+        self.last_update = None;
+    }
+
+    /// Step every follower towards its target, writing the results to `output`.
+    pub fn process(&mut self, targets: &[f32], output: &mut [f32]) {
+        let now = Instant::now();
+        let dt = match self.last_update {
+            Some(last) => now.duration_since(last).as_secs_f32(),
+            None => 0.0,
+        };
+        self.last_update = Some(now);
+
+        for ((follower, target), out) in self
+            .followers
+            .iter_mut()
+            .zip(targets.iter())
+            .zip(output.iter_mut())
+        {
+            *out = follower.step(*target, dt, &self.settings);
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_envelope_follower() {
+    let settings = EnvelopeSettings {
+        attack: 0.0,
+        release: 100.0,
+        peak_hold: 50.0,
+        auto_gain: false,
+    };
+    let mut follower = EnvelopeFollower::default();
+
+    // instant attack
+    assert_eq!(follower.step(1.0, 0.01, &settings), 1.0);
+    // held for 50ms
+    assert_eq!(follower.step(0.0, 0.02, &settings), 1.0);
+    assert_eq!(follower.step(0.0, 0.02, &settings), 1.0);
+    assert_eq!(follower.step(0.0, 0.02, &settings), 1.0);
+    // then released
+    let released = follower.step(0.0, 0.02, &settings);
+    assert!(released < 1.0 && released > 0.0);
+
+    // the same release time gives the same decay regardless of step size
+    let settings = EnvelopeSettings {
+        attack: 0.0,
+        release: 100.0,
+        peak_hold: 0.0,
+        auto_gain: false,
+    };
+    let mut coarse = EnvelopeFollower::default();
+    let mut fine = EnvelopeFollower::default();
+    coarse.step(1.0, 0.0, &settings);
+    fine.step(1.0, 0.0, &settings);
+    let a = coarse.step(0.0, 0.1, &settings);
+    let mut b = 0.0;
+    for _ in 0..10 {
+        b = fine.step(0.0, 0.01, &settings);
+    }
+    assert!((a - b).abs() < 1e-5);
+}
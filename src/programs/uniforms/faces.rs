@@ -0,0 +1,361 @@
+use nannou::prelude::*;
+use opencv::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::app;
+use crate::programs::config;
+use crate::programs::uniforms::analysis::{AnalysisOutput, AnalysisSender, Latest};
+use crate::programs::uniforms::base::Bufferable;
+use crate::programs::uniforms::source_transform::SourceTransform;
+use crate::programs::uniforms::video_capture::{FrameAnalysis, VideoCapture};
+
+/// Number of faces the uniforms hold, the largest ones are kept.
+pub const MAX_FACES: usize = 8;
+/// Name of the analysis running on the webcam thread.
+pub const ANALYSIS: &str = "faces";
+
+/// Input size of the res10 SSD face detector.
+const NETWORK_SIZE: i32 = 300;
+/// Mean BGR color the res10 SSD face detector was trained with.
+const NETWORK_MEAN: [f64; 3] = [104.0, 177.0, 123.0];
+
+/// Which model detects faces and how.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FaceDetectionSettings {
+    /// File in the media folder, a Haar cascade `.xml` or a network OpenCV's dnn module reads,
+    /// like `res10_300x300_ssd_iter_140000.caffemodel`.
+    pub model: String,
+    /// Network configuration in the media folder, like `deploy.prototxt`.
+    pub config: Option<String>,
+    /// Frames are scaled by this before running a Haar cascade, smaller is faster.
+    pub scale: f64,
+    /// Networks only keep detections at least this confident.
+    pub confidence: f32,
+}
+
+impl Default for FaceDetectionSettings {
+    fn default() -> Self {
+        Self {
+            model: String::from("haarcascade_frontalface_default.xml"),
+            config: None,
+            scale: 0.5,
+            confidence: 0.5,
+        }
+    }
+}
+
+/// `[x, y, width, height]` of each face relative to the frame with the origin at the top
+/// left, the largest first.
+type Faces = Vec<[f32; 4]>;
+
+enum Model {
+    Cascade(opencv::objdetect::CascadeClassifier),
+    Network(opencv::dnn::Net),
+}
+
+/// Finds faces in the frames of a capture on its thread.
+struct FaceDetector {
+    model: Model,
+    settings: FaceDetectionSettings,
+    output: AnalysisSender<Faces>,
+    gray: opencv::core::Mat,
+    small: opencv::core::Mat,
+}
+
+fn media_file(media_path: &Path, name: &str) -> Result<String, String> {
+    let path = media_path.join(name);
+    if !path.is_file() {
+        return Err(format!(
+            "Face detection model {} not found in the media folder",
+            name
+        ));
+    }
+    Ok(path.to_string_lossy().into_owned())
+}
+
+impl FaceDetector {
+    fn new(
+        media_path: &Path,
+        settings: FaceDetectionSettings,
+        output: AnalysisSender<Faces>,
+    ) -> Result<Self, String> {
+        let model_path = media_file(media_path, &settings.model)?;
+
+        let model = if settings.model.to_lowercase().ends_with(".xml") {
+            let cascade = opencv::objdetect::CascadeClassifier::new(&model_path)
+                .map_err(|e| e.to_string())?;
+            if cascade.empty().map_err(|e| e.to_string())? {
+                return Err(format!("Unable to load the cascade {}", settings.model));
+            }
+            Model::Cascade(cascade)
+        } else {
+            let config_path = match &settings.config {
+                Some(config) => media_file(media_path, config)?,
+                None => String::new(),
+            };
+            let net =
+                opencv::dnn::read_net(&model_path, &config_path, "").map_err(|e| e.to_string())?;
+            Model::Network(net)
+        };
+
+        Ok(Self {
+            model,
+            settings,
+            output,
+            gray: opencv::core::Mat::default(),
+            small: opencv::core::Mat::default(),
+        })
+    }
+
+    fn detect(&mut self, frame: &opencv::core::Mat) -> opencv::Result<Vec<[f32; 4]>> {
+        let width = frame.cols() as f32;
+        let height = frame.rows() as f32;
+        let confidence = self.settings.confidence;
+
+        match &mut self.model {
+            Model::Cascade(cascade) => {
+                let scale = self.settings.scale.max(0.1).min(1.0);
+                opencv::imgproc::cvt_color(
+                    frame,
+                    &mut self.gray,
+                    opencv::imgproc::COLOR_BGRA2GRAY,
+                    0,
+                )?;
+                opencv::imgproc::resize(
+                    &self.gray,
+                    &mut self.small,
+                    opencv::core::Size::default(),
+                    scale,
+                    scale,
+                    opencv::imgproc::INTER_LINEAR,
+                )?;
+                opencv::imgproc::equalize_hist(&self.small, &mut self.gray)?;
+
+                let mut faces = opencv::core::Vector::<opencv::core::Rect>::new();
+                cascade.detect_multi_scale(
+                    &self.gray,
+                    &mut faces,
+                    1.1,
+                    4,
+                    0,
+                    opencv::core::Size::new(24, 24),
+                    opencv::core::Size::default(),
+                )?;
+
+                let scale = scale as f32;
+                Ok(faces
+                    .iter()
+                    .map(|r| {
+                        [
+                            r.x as f32 / scale / width,
+                            r.y as f32 / scale / height,
+                            r.width as f32 / scale / width,
+                            r.height as f32 / scale / height,
+                        ]
+                    })
+                    .collect())
+            }
+            Model::Network(net) => {
+                opencv::imgproc::cvt_color(
+                    frame,
+                    &mut self.small,
+                    opencv::imgproc::COLOR_BGRA2BGR,
+                    0,
+                )?;
+                let blob = opencv::dnn::blob_from_image(
+                    &self.small,
+                    1.0,
+                    opencv::core::Size::new(NETWORK_SIZE, NETWORK_SIZE),
+                    opencv::core::Scalar::new(
+                        NETWORK_MEAN[0],
+                        NETWORK_MEAN[1],
+                        NETWORK_MEAN[2],
+                        0.0,
+                    ),
+                    false,
+                    false,
+                    opencv::core::CV_32F,
+                )?;
+                net.set_input(&blob, "", 1.0, opencv::core::Scalar::default())?;
+                let output = net.forward_single("")?;
+
+                // rows of [image, class, confidence, left, top, right, bottom]
+                Ok(output
+                    .data_typed::<f32>()?
+                    .chunks_exact(7)
+                    .filter(|d| d[2] >= confidence)
+                    .map(|d| {
+                        let left = d[3].max(0.0).min(1.0);
+                        let top = d[4].max(0.0).min(1.0);
+                        let right = d[5].max(left).min(1.0);
+                        let bottom = d[6].max(top).min(1.0);
+                        [left, top, right - left, bottom - top]
+                    })
+                    .collect())
+            }
+        }
+    }
+}
+
+impl FrameAnalysis for FaceDetector {
+    fn name(&self) -> &'static str {
+        ANALYSIS
+    }
+
+    fn analyze(&mut self, frame: &opencv::core::Mat) {
+        let result = self.detect(frame).map(|mut rects| {
+            // largest first, so the first face is the closest one
+            rects.sort_by(|a, b| (b[2] * b[3]).total_cmp(&(a[2] * a[3])));
+            Some(rects)
+        });
+        self.output.send(result);
+    }
+}
+
+/// A face rectangle as it appears in the transformed source, in shader coordinates from
+/// 0 to 1 with the origin at the bottom left. `None` when it was cropped away.
+fn shader_rect(rect: [f32; 4], transform: &SourceTransform) -> Option<[f32; 4]> {
+    let [x, y, width, height] = rect;
+    let a = transform.output_coords([x, y]);
+    let b = transform.output_coords([x + width, y + height]);
+
+    let left = a[0].min(b[0]).max(0.0);
+    let right = a[0].max(b[0]).min(1.0);
+    let top = a[1].min(b[1]).max(0.0);
+    let bottom = a[1].max(b[1]).min(1.0);
+    if right <= left || bottom <= top {
+        return None;
+    }
+
+    Some([left, 1.0 - bottom, right - left, bottom - top])
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Data {
+    /// `x, y, width, height`, in shader coordinates with the origin at the bottom left.
+    pub face_rects: [[f32; 4]; MAX_FACES],
+    /// Padded to the std140 array stride, declared as `vec2 face_centers[MAX_FACES]`.
+    pub face_centers: [[f32; 4]; MAX_FACES],
+    pub face_count: i32,
+    _pad: [i32; 3],
+}
+
+/// Faces found in the first webcam.
+pub struct FaceUniforms {
+    pub error: Option<String>,
+    pub settings: FaceDetectionSettings,
+
+    data: Data,
+    output: AnalysisOutput<Faces>,
+    media_path: PathBuf,
+}
+
+impl Bufferable<Data> for FaceUniforms {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { wgpu::bytes::from(&self.data) }
+    }
+}
+
+impl FaceUniforms {
+    pub fn new() -> Self {
+        Self {
+            error: None,
+            settings: FaceDetectionSettings::default(),
+            data: Data {
+                face_rects: [[0.0; 4]; MAX_FACES],
+                face_centers: [[0.0; 4]; MAX_FACES],
+                face_count: 0,
+                _pad: [0; 3],
+            },
+            output: AnalysisOutput::new(ANALYSIS),
+            media_path: PathBuf::new(),
+        }
+    }
+
+    pub fn configure(&mut self, app: &App, settings: &Option<config::ProgramSettings>) {
+        let project_path = app.project_path().expect("failed to locate `project_path`");
+        let face_settings = settings
+            .as_ref()
+            .and_then(|s| s.face_detection.clone())
+            .unwrap_or_default();
+        self.set_settings(project_path.join(app::MEDIA_DIR), face_settings);
+    }
+
+    /// Models are loaded from `media_path`, the detector is replaced on the next update.
+    pub fn set_settings(&mut self, media_path: PathBuf, settings: FaceDetectionSettings) {
+        self.media_path = media_path;
+        self.settings = settings;
+        self.error = None;
+        self.output.detach();
+    }
+
+    /// Detect faces in the frames of `capture`, starting the detector if it doesn't run yet,
+    /// and copy the latest faces into the uniforms. A model that can't be loaded isn't tried
+    /// again until the settings change, errors while detecting clear the faces until the next
+    /// frame is analyzed.
+    pub fn update(&mut self, capture: &mut VideoCapture) {
+        let media_path = &self.media_path;
+        let settings = self.settings.clone();
+        let latest = self.output.update(capture, |output| {
+            FaceDetector::new(media_path, settings, output)
+                .map(|detector| Box::new(detector) as Box<dyn FrameAnalysis>)
+        });
+
+        let rects = match latest {
+            Latest {
+                error: Some(error), ..
+            } => {
+                println!("Face detection error: {}", error);
+                self.error = Some(error);
+                vec![]
+            }
+            Latest {
+                value: Some(rects), ..
+            } => {
+                self.error = None;
+                rects
+            }
+            _ => return,
+        };
+
+        let transform = capture.transform();
+        let rects = rects
+            .iter()
+            .filter_map(|r| shader_rect(*r, &transform))
+            .take(MAX_FACES)
+            .collect::<Vec<[f32; 4]>>();
+
+        self.data.face_count = rects.len() as i32;
+        for (index, [x, y, width, height]) in rects.into_iter().enumerate() {
+            self.data.face_rects[index] = [x, y, width, height];
+            self.data.face_centers[index] = [x + width / 2.0, y + height / 2.0, 0.0, 0.0];
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_shader_rect() {
+    let identity = SourceTransform::default();
+    assert_eq!(
+        shader_rect([0.25, 0.0, 0.5, 0.25], &identity),
+        Some([0.25, 0.75, 0.5, 0.25])
+    );
+
+    // webcams are mirrored by default
+    let mirrored = SourceTransform::mirrored();
+    assert_eq!(
+        shader_rect([0.0, 0.5, 0.25, 0.5], &mirrored),
+        Some([0.75, 0.0, 0.25, 0.5])
+    );
+
+    // faces outside the crop are dropped
+    let cropped = SourceTransform {
+        crop: [0.5, 0.0, 0.5, 1.0],
+        ..SourceTransform::default()
+    };
+    assert_eq!(shader_rect([0.0, 0.0, 0.25, 0.25], &cropped), None);
+}
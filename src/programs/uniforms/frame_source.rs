@@ -0,0 +1,333 @@
+use nannou::prelude::*;
+use opencv::prelude::*;
+use std::collections::HashMap;
+use std::io::Read;
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+use crate::programs::uniforms::image_sequence::{self, ImageSequence, ImageSequenceSettings};
+
+/// Prefixes of the ffmpeg pixel formats that carry an alpha channel.
+const ALPHA_FORMATS: [&str; 8] = [
+    "yuva", "gbrap", "ya", "rgba", "bgra", "argb", "abgr", "pal8",
+];
+
+/// Where the frames of a `VideoCapture` come from, read on the capture thread.
+pub trait FrameSource: Send {
+    /// Width and height of the frames.
+    fn size(&self) -> (f64, f64);
+    /// Frames per second, 0 when unknown.
+    fn frame_rate(&self) -> f64;
+    /// Number of frames, 0 for live sources.
+    fn frame_count(&self) -> f64;
+    /// Read the next frame, `Ok(false)` when there are no more.
+    fn read_frame(&mut self, frame: &mut opencv::core::Mat) -> opencv::Result<bool>;
+    /// Skip the next frame.
+    fn skip_frame(&mut self) -> opencv::Result<bool>;
+    /// Make `frame` the next frame read.
+    fn seek_frame(&mut self, frame: f64) -> opencv::Result<()>;
+    /// Position of the last frame read in seconds.
+    fn position(&self) -> f64;
+
+    /// Format of the texture the frames are uploaded to, BGRA frames are swizzled into it.
+    fn texture_format(&self) -> wgpu::TextureFormat {
+        wgpu::TextureFormat::Rgba8Unorm
+    }
+
+    /// Whether the frames are decoded into memory ahead of time, so they are worth keeping on
+    /// the GPU.
+    fn preloaded(&self) -> bool {
+        false
+    }
+}
+
+impl FrameSource for opencv::videoio::VideoCapture {
+    fn size(&self) -> (f64, f64) {
+        (
+            self.get(opencv::videoio::CAP_PROP_FRAME_WIDTH)
+                .unwrap_or(0.0),
+            self.get(opencv::videoio::CAP_PROP_FRAME_HEIGHT)
+                .unwrap_or(0.0),
+        )
+    }
+
+    fn frame_rate(&self) -> f64 {
+        self.get(opencv::videoio::CAP_PROP_FPS).unwrap_or(0.0)
+    }
+
+    fn frame_count(&self) -> f64 {
+        self.get(opencv::videoio::CAP_PROP_FRAME_COUNT)
+            .unwrap_or(0.0)
+    }
+
+    fn read_frame(&mut self, frame: &mut opencv::core::Mat) -> opencv::Result<bool> {
+        self.read(frame)
+    }
+
+    fn skip_frame(&mut self) -> opencv::Result<bool> {
+        self.grab()
+    }
+
+    fn seek_frame(&mut self, frame: f64) -> opencv::Result<()> {
+        self.set(opencv::videoio::CAP_PROP_POS_FRAMES, frame)
+            .map(|_| ())
+    }
+
+    fn position(&self) -> f64 {
+        self.get(opencv::videoio::CAP_PROP_POS_MSEC).unwrap_or(0.0) / 1000.0
+    }
+}
+
+/// What `ffprobe` reports about the first video stream of a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbeInfo {
+    pub width: u32,
+    pub height: u32,
+    pub frame_rate: f64,
+    pub frame_count: f64,
+    pub pixel_format: String,
+}
+
+impl ProbeInfo {
+    pub fn has_alpha(&self) -> bool {
+        ALPHA_FORMATS
+            .iter()
+            .any(|f| self.pixel_format.starts_with(f))
+    }
+}
+
+/// Parse `key=value` lines as printed by `ffprobe -of default=noprint_wrappers=1`.
+pub fn parse_probe(output: &str) -> Option<ProbeInfo> {
+    let values = output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, '=');
+            Some((parts.next()?.trim(), parts.next()?.trim()))
+        })
+        .collect::<HashMap<&str, &str>>();
+
+    let frame_rate = values.get("r_frame_rate").and_then(|r| {
+        let mut parts = r.splitn(2, '/');
+        let num = parts.next()?.parse::<f64>().ok()?;
+        let den = parts.next().map_or(Some(1.0), |d| d.parse::<f64>().ok())?;
+        if den > 0.0 {
+            Some(num / den)
+        } else {
+            None
+        }
+    })?;
+
+    let duration = values
+        .get("duration")
+        .and_then(|d| d.parse::<f64>().ok())
+        .unwrap_or(0.0);
+    let frame_count = values
+        .get("nb_frames")
+        .and_then(|n| n.parse::<f64>().ok())
+        .unwrap_or_else(|| (duration * frame_rate).floor());
+
+    Some(ProbeInfo {
+        width: values.get("width")?.parse().ok()?,
+        height: values.get("height")?.parse().ok()?,
+        frame_rate,
+        frame_count,
+        pixel_format: values.get("pix_fmt")?.to_string(),
+    })
+}
+
+/// Probe a file with `ffprobe`, `None` if it isn't available or the file has no video.
+pub fn probe(path: &str) -> Option<ProbeInfo> {
+    let output = Command::new("ffprobe")
+        .args(&["-v", "error", "-select_streams", "v:0"])
+        .args(&[
+            "-show_entries",
+            "stream=width,height,pix_fmt,r_frame_rate,nb_frames,duration",
+        ])
+        .args(&["-of", "default=noprint_wrappers=1", path])
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    parse_probe(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn ffmpeg_error(e: std::io::Error) -> opencv::Error {
+    opencv::Error::new(
+        opencv::core::StsError,
+        format!("Unable to run ffmpeg: {}", e),
+    )
+}
+
+/// Decodes a video file to BGRA with an `ffmpeg` process. Used for videos with an alpha
+/// channel, which OpenCV drops. Seeking restarts the process at the new position.
+pub struct FfmpegSource {
+    path: String,
+    info: ProbeInfo,
+    decoder: Option<(Child, ChildStdout)>,
+    /// Frame the next read returns.
+    next: f64,
+    /// Skipped frames are read into this.
+    skipped: Vec<u8>,
+}
+
+impl FfmpegSource {
+    pub fn new(path: &str, info: ProbeInfo) -> Self {
+        Self {
+            path: path.to_string(),
+            info,
+            decoder: None,
+            next: 0.0,
+            skipped: vec![],
+        }
+    }
+
+    fn spawn(&self) -> opencv::Result<(Child, ChildStdout)> {
+        let start = self.next / self.info.frame_rate;
+        let mut child = Command::new("ffmpeg")
+            .args(&["-hide_banner", "-loglevel", "error", "-nostdin"])
+            .args(&["-ss", &format!("{:.3}", start), "-i", &self.path])
+            .args(&["-an", "-f", "rawvideo", "-pix_fmt", "bgra", "pipe:1"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(ffmpeg_error)?;
+        let stdout = child.stdout.take().unwrap();
+        Ok((child, stdout))
+    }
+
+    fn stop(&mut self) {
+        if let Some((mut child, _)) = self.decoder.take() {
+            child.kill().ok();
+            child.wait().ok();
+        }
+    }
+
+    /// Read the bytes of the next frame, false at the end of the file.
+    fn read_bytes(&mut self, bytes: &mut [u8]) -> opencv::Result<bool> {
+        if self.decoder.is_none() {
+            self.decoder = Some(self.spawn()?);
+        }
+
+        let (_, stdout) = self.decoder.as_mut().unwrap();
+        if stdout.read_exact(bytes).is_err() {
+            self.stop();
+            return Ok(false);
+        }
+
+        self.next += 1.0;
+        Ok(true)
+    }
+
+    fn frame_bytes(&self) -> usize {
+        self.info.width as usize * self.info.height as usize * 4
+    }
+}
+
+impl FrameSource for FfmpegSource {
+    fn size(&self) -> (f64, f64) {
+        (self.info.width as f64, self.info.height as f64)
+    }
+
+    fn frame_rate(&self) -> f64 {
+        self.info.frame_rate
+    }
+
+    fn frame_count(&self) -> f64 {
+        self.info.frame_count
+    }
+
+    fn read_frame(&mut self, frame: &mut opencv::core::Mat) -> opencv::Result<bool> {
+        let rows = self.info.height as i32;
+        let cols = self.info.width as i32;
+        if frame.rows() != rows || frame.cols() != cols || frame.typ()? != opencv::core::CV_8UC4 {
+            *frame = opencv::core::Mat::new_rows_cols_with_default(
+                rows,
+                cols,
+                opencv::core::CV_8UC4,
+                opencv::core::Scalar::all(0.0),
+            )?;
+        }
+
+        self.read_bytes(frame.data_bytes_mut()?)
+    }
+
+    fn skip_frame(&mut self) -> opencv::Result<bool> {
+        let mut skipped = std::mem::take(&mut self.skipped);
+        skipped.resize(self.frame_bytes(), 0);
+        let result = self.read_bytes(&mut skipped);
+        self.skipped = skipped;
+        result
+    }
+
+    fn seek_frame(&mut self, frame: f64) -> opencv::Result<()> {
+        self.stop();
+        self.next = frame.max(0.0).floor();
+        Ok(())
+    }
+
+    fn position(&self) -> f64 {
+        (self.next - 1.0).max(0.0) / self.info.frame_rate
+    }
+}
+
+impl Drop for FfmpegSource {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Open a video file, with ffmpeg when it has an alpha channel and OpenCV otherwise.
+/// Folders and numbered images are played as image sequences with `sequence`, the returned
+/// flag is set for those since they have no soundtrack.
+pub fn open_file(
+    path: &str,
+    sequence: ImageSequenceSettings,
+) -> Result<(Box<dyn FrameSource>, bool), String> {
+    if let Some(frames) = image_sequence::find_frames(path) {
+        println!(
+            "playing {} frames of {} as an image sequence",
+            frames.len(),
+            path
+        );
+        return Ok((Box::new(ImageSequence::new(frames, sequence)?), true));
+    }
+
+    if let Some(info) = probe(path) {
+        if info.has_alpha() && info.frame_rate > 0.0 {
+            println!("decoding {} with ffmpeg to keep its alpha", path);
+            return Ok((Box::new(FfmpegSource::new(path, info)), false));
+        }
+    }
+
+    match opencv::videoio::VideoCapture::from_file(path, opencv::videoio::CAP_ANY) {
+        Ok(capture) => Ok((Box::new(capture), false)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_parse_probe() {
+    let output = "width=1920
+height=1080
+pix_fmt=yuva444p12le
+r_frame_rate=30000/1001
+duration=10.010000
+nb_frames=N/A
+";
+    let info = parse_probe(output).unwrap();
+    assert_eq!((info.width, info.height), (1920, 1080));
+    assert!((info.frame_rate - 29.97).abs() < 0.01);
+    assert_eq!(info.frame_count, 300.0);
+    assert!(info.has_alpha());
+
+    let output = "width=640\nheight=480\npix_fmt=yuv420p\nr_frame_rate=25/1\nnb_frames=50\n";
+    let info = parse_probe(output).unwrap();
+    assert_eq!(info.frame_count, 50.0);
+    assert!(!info.has_alpha());
+
+    assert_eq!(parse_probe(""), None);
+}
@@ -0,0 +1,326 @@
+use nannou::prelude::*;
+
+use crate::programs::uniforms::source_transform::SourceTransform;
+use crate::quad_2d;
+use crate::util;
+
+const VERTEX_SHADER: &str = "
+#version 450
+
+layout(location = 0) in vec2 position;
+layout(location = 0) out vec2 tex_coords;
+
+void main() {
+    gl_Position = vec4(position, 0.0, 1.0);
+    tex_coords = vec2(position.x * 0.5 + 0.5, 0.5 - position.y * 0.5);
+}
+";
+
+const FRAGMENT_SHADER: &str = "
+#version 450
+
+layout(location = 0) in vec2 tex_coords;
+layout(location = 0) out vec4 frag_color;
+
+layout(set = 0, binding = 0) uniform sampler frame_sampler;
+layout(set = 0, binding = 1) uniform texture2D frame;
+layout(set = 0, binding = 2) uniform Params {
+    vec2 origin;
+    vec2 step_u;
+    vec2 step_v;
+    int bgra;
+    float brightness;
+    float contrast;
+    float saturation;
+    float gamma;
+    int premultiplied;
+    int premultiply;
+};
+
+void main() {
+    vec2 uv = origin + tex_coords.x * step_u + tex_coords.y * step_v;
+    vec4 color = texture(sampler2D(frame, frame_sampler), uv);
+    if (bgra == 1) {
+        color = color.bgra;
+    }
+    if (premultiplied == 1 && color.a > 0.0) {
+        color.rgb /= color.a;
+    }
+
+    vec3 rgb = color.rgb + brightness;
+    rgb = (rgb - 0.5) * contrast + 0.5;
+    float luma = dot(rgb, vec3(0.2126, 0.7152, 0.0722));
+    rgb = mix(vec3(luma), rgb, saturation);
+    rgb = pow(clamp(rgb, 0.0, 1.0), vec3(1.0 / gamma));
+    if (premultiply == 1) {
+        rgb *= color.a;
+    }
+
+    frag_color = vec4(rgb, color.a);
+}
+";
+
+/// Layout of the `Params` block of the fragment shader.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct Params {
+    origin: [f32; 2],
+    step_u: [f32; 2],
+    step_v: [f32; 2],
+    bgra: i32,
+    brightness: f32,
+    contrast: f32,
+    saturation: f32,
+    gamma: f32,
+    premultiplied: i32,
+    premultiply: i32,
+    _pad: [f32; 3],
+}
+
+impl Params {
+    fn new(transform: &SourceTransform, bgra: bool) -> Self {
+        let [origin, step_u, step_v] = transform.coords_map();
+        Self {
+            origin,
+            step_u,
+            step_v,
+            bgra: bgra as i32,
+            brightness: transform.brightness,
+            contrast: transform.contrast,
+            saturation: transform.saturation,
+            gamma: transform.gamma.max(0.01),
+            premultiplied: transform.premultiplied as i32,
+            premultiply: transform.premultiply as i32,
+            _pad: [0.0; 3],
+        }
+    }
+}
+
+fn compile(
+    device: &wgpu::Device,
+    compiler: &mut shaderc::Compiler,
+    src: &str,
+    kind: shaderc::ShaderKind,
+    name: &str,
+) -> wgpu::ShaderModule {
+    let program = compiler
+        .compile_into_spirv(src, kind, name, "main", None)
+        .expect("failed to compile frame upload shader");
+    wgpu::shader_from_spirv_bytes(device, &program.as_binary_u8())
+}
+
+fn create_output_texture(
+    device: &wgpu::Device,
+    size: [u32; 2],
+    format: wgpu::TextureFormat,
+) -> wgpu::Texture {
+    wgpu::TextureBuilder::new()
+        .size(size)
+        .usage(wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED)
+        .format(format)
+        .build(device)
+}
+
+/// Renders a source texture into the texture shaders sample, applying a `SourceTransform`.
+/// Video frames come out of OpenCV or ffmpeg as 8-bit BGRA, they are uploaded as is and
+/// swizzled to RGBA by the same render pass. Alpha is passed through untouched.
+#[derive(Debug)]
+pub struct FrameUploader {
+    /// The transformed source.
+    pub texture: wgpu::Texture,
+    /// The transform currently applied.
+    pub transform: SourceTransform,
+    bgra: bool,
+    bind_group: wgpu::BindGroup,
+    bind_group_layout: wgpu::BindGroupLayout,
+    dst_view: wgpu::TextureView,
+    pipeline: wgpu::RenderPipeline,
+    raw_texture: wgpu::Texture,
+    raw_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    vertex_buffer: wgpu::Buffer,
+}
+
+impl FrameUploader {
+    /// An uploader for BGRA frames of the given size.
+    pub fn new(
+        device: &wgpu::Device,
+        size: [u32; 2],
+        format: wgpu::TextureFormat,
+        transform: SourceTransform,
+    ) -> Self {
+        let raw_texture = util::create_texture(device, size, format);
+        Self::build(device, raw_texture, true, transform)
+    }
+
+    /// Transform an RGBA texture that has already been uploaded, see `render`.
+    pub fn from_texture(
+        device: &wgpu::Device,
+        raw_texture: wgpu::Texture,
+        transform: SourceTransform,
+    ) -> Self {
+        Self::build(device, raw_texture, false, transform)
+    }
+
+    fn build(
+        device: &wgpu::Device,
+        raw_texture: wgpu::Texture,
+        bgra: bool,
+        transform: SourceTransform,
+    ) -> Self {
+        let raw_view = raw_texture.view().build();
+        let texture = create_output_texture(
+            device,
+            transform.output_size(raw_texture.size()),
+            raw_texture.format(),
+        );
+
+        let bind_group_layout = wgpu::BindGroupLayoutBuilder::new()
+            .sampler(wgpu::ShaderStage::FRAGMENT)
+            .sampled_texture(
+                wgpu::ShaderStage::FRAGMENT,
+                false,
+                wgpu::TextureViewDimension::D2,
+                raw_view.component_type(),
+            )
+            .uniform_buffer(wgpu::ShaderStage::FRAGMENT, false)
+            .build(device);
+        let sampler = wgpu::SamplerBuilder::new().build(device);
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            &sampler,
+            &raw_view,
+            &Params::new(&transform, bgra),
+        );
+
+        let mut compiler = shaderc::Compiler::new().unwrap();
+        let vs = compile(
+            device,
+            &mut compiler,
+            VERTEX_SHADER,
+            shaderc::ShaderKind::Vertex,
+            "frame_upload.vert",
+        );
+        let fs = compile(
+            device,
+            &mut compiler,
+            FRAGMENT_SHADER,
+            shaderc::ShaderKind::Fragment,
+            "frame_upload.frag",
+        );
+        let layout_desc = wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[&bind_group_layout],
+        };
+        let pipeline_layout = device.create_pipeline_layout(&layout_desc);
+        let pipeline =
+            util::create_render_pipeline(device, &pipeline_layout, &vs, &fs, texture.format(), 1);
+
+        Self {
+            dst_view: texture.view().build(),
+            texture,
+            transform,
+            bgra,
+            bind_group,
+            bind_group_layout,
+            pipeline,
+            raw_texture,
+            raw_view,
+            sampler,
+            vertex_buffer: quad_2d::create_vertex_buffer(device),
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        raw_view: &wgpu::TextureView,
+        params: &Params,
+    ) -> wgpu::BindGroup {
+        let bytes = unsafe { wgpu::bytes::from(params) };
+        let buffer = device.create_buffer_with_data(bytes, wgpu::BufferUsage::UNIFORM);
+        wgpu::BindGroupBuilder::new()
+            .sampler(sampler)
+            .texture_view(raw_view)
+            .buffer::<Params>(&buffer, 0..1)
+            .build(device, layout)
+    }
+
+    /// Size of the source before it is transformed.
+    pub fn source_size(&self) -> [u32; 2] {
+        self.raw_texture.size()
+    }
+
+    /// Size of a source frame in bytes.
+    pub fn frame_bytes(&self) -> usize {
+        self.raw_texture.size_bytes()
+    }
+
+    /// A texture a source frame can be uploaded to and kept in, see `show`.
+    pub fn create_frame(&self, device: &wgpu::Device) -> wgpu::Texture {
+        wgpu::TextureBuilder::new()
+            .size(self.raw_texture.size())
+            .usage(wgpu::TextureUsage::COPY_DST | wgpu::TextureUsage::COPY_SRC)
+            .format(self.raw_texture.format())
+            .build(device)
+    }
+
+    /// Render a frame kept in a texture made by `create_frame`, nothing is uploaded.
+    pub fn show(&self, encoder: &mut wgpu::CommandEncoder, frame: &wgpu::Texture) {
+        encoder.copy_texture_to_texture(
+            frame.default_copy_view(),
+            self.raw_texture.default_copy_view(),
+            frame.extent(),
+        );
+        self.render(encoder);
+    }
+
+    /// Change the transform, it applies from the next `upload` or `render`.
+    /// Returns true when the size changed and `texture` was replaced.
+    pub fn set_transform(&mut self, device: &wgpu::Device, transform: SourceTransform) -> bool {
+        if transform == self.transform {
+            return false;
+        }
+
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.sampler,
+            &self.raw_view,
+            &Params::new(&transform, self.bgra),
+        );
+
+        let size = transform.output_size(self.source_size());
+        self.transform = transform;
+        if size == self.texture.size() {
+            return false;
+        }
+
+        self.texture = create_output_texture(device, size, self.texture.format());
+        self.dst_view = self.texture.view().build();
+        true
+    }
+
+    /// Upload a frame of tightly packed BGRA rows and render it.
+    /// Frames that don't match the source size are ignored.
+    pub fn upload(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, bgra: &[u8]) {
+        if bgra.len() != self.frame_bytes() {
+            return;
+        }
+
+        self.raw_texture.upload_data(device, encoder, bgra);
+        self.render(encoder);
+    }
+
+    /// Render the source into `texture`.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder) {
+        let mut render_pass = wgpu::RenderPassBuilder::new()
+            .color_attachment(&self.dst_view, |color| color)
+            .begin(encoder);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, &self.vertex_buffer, 0, 0);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..quad_2d::VERTICES.len() as u32, 0..1);
+    }
+}
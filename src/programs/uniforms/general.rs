@@ -1,6 +1,7 @@
 use nannou::prelude::*;
 use std::time::SystemTime;
 
+use crate::programs::config;
 use crate::programs::uniforms::base::Bufferable;
 
 #[repr(C)]
@@ -17,6 +18,16 @@ pub struct GeneralUniforms {
     pub clock: SystemTime,
     pub data: Data,
 
+    /// Frames per second of the fixed timestep clock, see `set_fixed_fps`.
+    fixed_fps: Option<f64>,
+    /// Frames rendered since the clock started, not counting those while paused.
+    frames: u64,
+    /// Index of the current frame.
+    frame_index: u64,
+    /// Seconds between the current frame and the one before it.
+    time_delta: f32,
+    paused: bool,
+    // wall clock offsets, left alone by the fixed timestep clock
     paused_at: f32,
     paused_time: f32,
     reset_at: f32,
@@ -39,19 +50,65 @@ impl GeneralUniforms {
                 time: 0.0,
                 mouse_down: 0,
             },
+            fixed_fps: None,
+            frames: 0,
+            frame_index: 0,
+            time_delta: 0.0,
+            paused: false,
             paused_at: 0.0,
             paused_time: 0.0,
             reset_at: 0.0,
         }
     }
 
+    pub fn configure(&mut self, settings: &Option<config::ProgramSettings>) {
+        self.set_fixed_fps(settings.as_ref().and_then(|s| s.render_fps));
+    }
+
+    /// Advance time by exactly one frame of `fps` with every update instead of following the
+    /// wall clock, so renders are reproducible frame for frame. Time starts again at 0.
+    pub fn set_fixed_fps(&mut self, fps: Option<f64>) {
+        let fps = fps.filter(|fps| *fps > 0.0);
+        if fps.is_none() && self.fixed_fps.is_some() {
+            // the wall clock starts at 0 too instead of jumping ahead
+            self.reset_at = self.elapsed();
+            self.paused_time = 0.0;
+            self.paused_at = 0.0;
+            self.data.time = 0.0;
+        }
+        self.fixed_fps = fps;
+        self.frames = 0;
+    }
+
+    fn elapsed(&self) -> f32 {
+        self.clock.elapsed().unwrap().as_secs_f32()
+    }
+
     fn get_time(&self) -> f32 {
-        let elapsed = self.clock.elapsed().unwrap();
-        elapsed.as_secs_f32() - self.reset_at - self.paused_time
+        self.elapsed() - self.reset_at - self.paused_time
     }
 
     pub fn update(&mut self) {
-        self.data.time = self.get_time();
+        let time = match self.fixed_fps {
+            Some(fps) => (self.frames as f64 / fps) as f32,
+            None => self.get_time(),
+        };
+        self.time_delta = (time - self.data.time).max(0.0);
+        self.data.time = time;
+        self.frame_index = self.frames;
+        if !self.paused {
+            self.frames += 1;
+        }
+    }
+
+    /// Seconds since the previous update.
+    pub fn time_delta(&self) -> f32 {
+        self.time_delta
+    }
+
+    /// Index of the current frame, counted from when the clock started.
+    pub fn frame_index(&self) -> u64 {
+        self.frame_index
     }
 
     pub fn set_size(&mut self, size: Vector2) {
@@ -63,14 +120,53 @@ impl GeneralUniforms {
     }
 
     pub fn reset(&mut self) {
-        self.reset_at = self.data.time;
+        self.frames = 0;
+        if self.fixed_fps.is_none() {
+            self.reset_at += self.get_time();
+            // a pause resumes at the new start
+            self.paused_at = 0.0;
+        }
     }
 
     pub fn pause(&mut self) {
-        self.paused_at = self.data.time;
+        self.paused = true;
+        if self.fixed_fps.is_none() {
+            self.paused_at = self.data.time;
+        }
     }
 
     pub fn unpause(&mut self) {
-        self.paused_time += self.get_time() - self.paused_at;
+        self.paused = false;
+        if self.fixed_fps.is_none() {
+            self.paused_time += self.get_time() - self.paused_at;
+        }
     }
 }
+
+#[cfg(test)]
+#[test]
+fn test_fixed_clock() {
+    let mut general = GeneralUniforms::new(pt2(1.0, 1.0));
+    general.set_fixed_fps(Some(10.0));
+    general.update();
+    general.update();
+    assert_eq!(general.data.time, 0.1);
+    assert_eq!(general.frame_index(), 1);
+
+    // pausing holds the frame and leaves the wall clock alone
+    general.pause();
+    general.update();
+    general.update();
+    assert_eq!(general.data.time, 0.2);
+    general.unpause();
+    assert_eq!(general.paused_time, 0.0);
+    general.reset();
+    general.update();
+    assert_eq!(general.data.time, 0.0);
+    assert_eq!(general.reset_at, 0.0);
+
+    // the wall clock starts at 0 after the fixed one
+    general.set_fixed_fps(None);
+    general.update();
+    assert!(general.data.time >= 0.0 && general.data.time < 1.0);
+}
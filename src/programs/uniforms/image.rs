@@ -1,11 +1,15 @@
 use nannou::image;
 use nannou::image::GenericImageView;
 use nannou::prelude::*;
+use std::path::Path;
 use tinyfiledialogs::open_file_dialog;
 
 use crate::app;
 use crate::programs::config;
+use crate::programs::uniforms::animated_image::{Animation, AnimationSettings};
 use crate::programs::uniforms::base::Bufferable;
+use crate::programs::uniforms::frame_upload::FrameUploader;
+use crate::programs::uniforms::source_transform::SourceTransform;
 use crate::util;
 
 #[repr(C)]
@@ -15,14 +19,56 @@ pub struct Data {
     pub image2_size: Vector2,
 }
 
+/// Render a loaded image with its transform when it is new or the transform changed.
+/// Returns true when the transformed texture was replaced.
+fn transform_image(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+    transformed: &mut Option<FrameUploader>,
+    transform: SourceTransform,
+) -> bool {
+    match transformed {
+        Some(uploader) => {
+            if uploader.transform == transform {
+                return false;
+            }
+            let replaced = uploader.set_transform(device, transform);
+            uploader.render(encoder);
+            replaced
+        }
+        None => {
+            let uploader = FrameUploader::from_texture(device, texture.clone(), transform);
+            uploader.render(encoder);
+            *transformed = Some(uploader);
+            true
+        }
+    }
+}
+
+fn texture_size(texture: &wgpu::Texture) -> Vector2 {
+    let [width, height] = texture.size();
+    pt2(width as f32, height as f32)
+}
+
 pub struct ImageUniforms {
+    /// How animated images are played.
+    pub animation: AnimationSettings,
     pub data: Data,
     pub error: Option<String>,
     pub image1_name: Option<String>,
+    /// The image as loaded.
     pub image1_texture: wgpu::Texture,
+    pub image1_transform: SourceTransform,
     pub image2_name: Option<String>,
     pub image2_texture: wgpu::Texture,
+    pub image2_transform: SourceTransform,
     pub updated: bool,
+
+    image1_animation: Option<Animation>,
+    image1_transformed: Option<FrameUploader>,
+    image2_animation: Option<Animation>,
+    image2_transformed: Option<FrameUploader>,
 }
 
 impl Bufferable<Data> for ImageUniforms {
@@ -31,7 +77,14 @@ impl Bufferable<Data> for ImageUniforms {
     }
 
     fn textures(&self) -> Vec<&wgpu::Texture> {
-        vec![&self.image1_texture, &self.image2_texture]
+        vec![
+            self.image1_transformed
+                .as_ref()
+                .map_or(&self.image1_texture, |u| &u.texture),
+            self.image2_transformed
+                .as_ref()
+                .map_or(&self.image2_texture, |u| &u.texture),
+        ]
     }
 }
 
@@ -41,6 +94,7 @@ impl ImageUniforms {
         let image2_texture = util::create_texture(device, [1, 1], wgpu::TextureFormat::Rgba16Float);
 
         Self {
+            animation: AnimationSettings::default(),
             data: Data {
                 image1_size: pt2(0.0, 0.0),
                 image2_size: pt2(0.0, 0.0),
@@ -48,36 +102,54 @@ impl ImageUniforms {
             error: None,
             image1_name: None,
             image1_texture,
+            image1_animation: None,
+            image1_transform: SourceTransform::default(),
+            image1_transformed: None,
             image2_name: None,
             image2_texture,
+            image2_animation: None,
+            image2_transform: SourceTransform::default(),
+            image2_transformed: None,
             updated: false,
         }
     }
 
     pub fn load_image(&mut self, app: &App, image_id: i32, filepath: String) {
-        let img = match image::open(&filepath) {
-            Ok(img) => img,
-            Err(e) => {
-                self.error = Some(e.to_string());
-                return;
-            }
+        // animated images start out with their first frame
+        let animation = Animation::open(Path::new(&filepath));
+        let img = match &animation {
+            Some(animation) => animation.first_frame().clone(),
+            None => match image::open(&filepath) {
+                Ok(img) => img.to_rgba8(),
+                Err(e) => {
+                    self.error = Some(e.to_string());
+                    return;
+                }
+            },
         };
 
+        // always RGBA so grey and palette images keep their alpha channel too
+        let img = image::DynamicImage::ImageRgba8(img);
         let (width, height) = img.dimensions();
         let texture = wgpu::Texture::from_image(app, &img);
 
         let filename = filepath.split('/').last().unwrap().to_string();
         let size = pt2(width as f32, height as f32);
 
+        // transformed on the next update
         match image_id {
             1 => {
                 self.image1_name = Some(filename);
                 self.image1_texture = texture;
+                self.image1_animation = animation;
+                self.image1_transformed = None;
                 self.data.image1_size = size;
             }
             2 => {
                 self.image2_name = Some(filename);
                 self.image2_texture = texture;
+                self.image2_animation = animation;
+                self.image2_transformed = None;
                 self.data.image2_size = size;
             }
             _ => return,
@@ -86,7 +158,61 @@ impl ImageUniforms {
         self.updated = true;
     }
 
+    /// Apply the transforms to loaded images and play animated ones.
+    pub fn update(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        if self.image1_name.is_some()
+            && transform_image(
+                device,
+                encoder,
+                &self.image1_texture,
+                &mut self.image1_transformed,
+                self.image1_transform,
+            )
+        {
+            self.data.image1_size = texture_size(self.textures()[0]);
+            self.updated = true;
+        }
+
+        if self.image2_name.is_some()
+            && transform_image(
+                device,
+                encoder,
+                &self.image2_texture,
+                &mut self.image2_transformed,
+                self.image2_transform,
+            )
+        {
+            self.data.image2_size = texture_size(self.textures()[1]);
+            self.updated = true;
+        }
+
+        if let (Some(animation), Some(uploader)) =
+            (&mut self.image1_animation, &self.image1_transformed)
+        {
+            animation.play(device, encoder, uploader, self.animation);
+        }
+
+        if let (Some(animation), Some(uploader)) =
+            (&mut self.image2_animation, &self.image2_transformed)
+        {
+            animation.play(device, encoder, uploader, self.animation);
+        }
+    }
+
     pub fn configure(&mut self, app: &App, settings: &Option<config::ProgramSettings>) {
+        self.animation = settings
+            .as_ref()
+            .and_then(|s| s.image_animation)
+            .unwrap_or_default();
+        self.image1_transform = settings
+            .as_ref()
+            .and_then(|s| s.image1_transform)
+            .unwrap_or_default();
+        self.image2_transform = settings
+            .as_ref()
+            .and_then(|s| s.image2_transform)
+            .unwrap_or_default();
+
         if let Some(cnfg) = settings {
             let project_path = app.project_path().expect("failed to locate `project_path`");
 
@@ -119,7 +245,11 @@ impl ImageUniforms {
     }
 
     pub fn select_image(&mut self, app: &App, image_id: i32) {
-        let filepath = match open_file_dialog("Load Image", "~", Some((&["*.jpg", "*.png"], ""))) {
+        let filepath = match open_file_dialog(
+            "Load Image",
+            "~",
+            Some((&["*.jpg", "*.png", "*.gif", "*.apng"], "")),
+        ) {
             Some(filepath) => filepath,
             None => return,
         };
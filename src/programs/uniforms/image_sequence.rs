@@ -0,0 +1,391 @@
+use nannou::prelude::*;
+use opencv::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::programs::uniforms::frame_source::FrameSource;
+use crate::programs::uniforms::video_capture::PlayMode;
+
+/// Extensions of the images a sequence can be made of.
+const EXTENSIONS: [&str; 8] = ["png", "jpg", "jpeg", "tif", "tiff", "exr", "bmp", "webp"];
+/// Sequences are preloaded into memory up to this many bytes, longer ones are read from disk.
+const PRELOAD_BUDGET: usize = 2 * 1024 * 1024 * 1024;
+
+/// How a folder of numbered images is played as a video.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImageSequenceSettings {
+    /// Frames per second, sequences carry no timing of their own.
+    pub fps: f64,
+    #[serde(rename = "loop")]
+    pub play_mode: PlayMode,
+    /// Decode the frames into memory in the background and keep them on the GPU once shown,
+    /// if they fit.
+    pub preload: bool,
+}
+
+impl Default for ImageSequenceSettings {
+    fn default() -> Self {
+        Self {
+            fps: 24.0,
+            play_mode: PlayMode::Forward,
+            preload: false,
+        }
+    }
+}
+
+/// Splits a file name like `frame_0042.png` into its prefix, frame number and extension.
+fn parse_frame_name(name: &str) -> Option<(&str, u64, String)> {
+    let dot = name.rfind('.')?;
+    let stem = &name[..dot];
+    let extension = name[dot + 1..].to_lowercase();
+    if !EXTENSIONS.contains(&extension.as_str()) {
+        return None;
+    }
+
+    let prefix = stem.trim_end_matches(|c: char| c.is_ascii_digit());
+    let number = stem[prefix.len()..].parse().ok()?;
+    Some((prefix, number, extension))
+}
+
+/// The names of the frames of the sequence `name` belongs to in order,
+/// or of the longest sequence among `names` without one.
+fn sequence_frames(names: &[String], name: Option<&str>) -> Vec<String> {
+    let mut sequences = HashMap::<(&str, String), Vec<(u64, &String)>>::new();
+    for n in names {
+        if let Some((prefix, number, extension)) = parse_frame_name(n) {
+            sequences
+                .entry((prefix, extension))
+                .or_default()
+                .push((number, n));
+        }
+    }
+
+    let frames = match name {
+        Some(name) => parse_frame_name(name)
+            .and_then(|(prefix, _, extension)| sequences.remove(&(prefix, extension))),
+        None => sequences
+            .into_iter()
+            .max_by(|(a_key, a), (b_key, b)| a.len().cmp(&b.len()).then(b_key.cmp(a_key)))
+            .map(|(_, frames)| frames),
+    };
+
+    let mut frames = frames.unwrap_or_default();
+    frames.sort();
+    frames.into_iter().map(|(_, n)| n.clone()).collect()
+}
+
+/// The frames of the image sequence at `path`, either a folder or one of its frames.
+/// `None` when the path isn't a numbered image or a folder containing some.
+pub fn find_frames(path: &str) -> Option<Vec<PathBuf>> {
+    let path = Path::new(path);
+    let (dir, name) = if path.is_dir() {
+        (path, None)
+    } else {
+        let name = path.file_name()?.to_str()?;
+        parse_frame_name(name)?;
+        (path.parent()?, Some(name))
+    };
+
+    let names = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .collect::<Vec<String>>();
+
+    let frames = sequence_frames(&names, name);
+    if frames.is_empty() {
+        return None;
+    }
+
+    Some(frames.into_iter().map(|n| dir.join(n)).collect())
+}
+
+fn read_image(path: &Path) -> opencv::Result<opencv::core::Mat> {
+    let image =
+        opencv::imgcodecs::imread(&path.to_string_lossy(), opencv::imgcodecs::IMREAD_UNCHANGED)?;
+    if image.empty()? {
+        return Err(opencv::Error::new(
+            opencv::core::StsError,
+            format!("Unable to read {}", path.display()),
+        ));
+    }
+    Ok(image)
+}
+
+/// Decode an image to BGRA of `size`, 16-bit float in 0 to 1 when `float` is set and 8-bit
+/// otherwise.
+fn decode(
+    path: &Path,
+    float: bool,
+    size: opencv::core::Size,
+    frame: &mut opencv::core::Mat,
+) -> opencv::Result<()> {
+    let image = read_image(path)?;
+    let code = match image.channels()? {
+        1 => Some(opencv::imgproc::COLOR_GRAY2BGRA),
+        3 => Some(opencv::imgproc::COLOR_BGR2BGRA),
+        _ => None,
+    };
+    let mut bgra = match code {
+        Some(code) => {
+            let mut bgra = opencv::core::Mat::default();
+            opencv::imgproc::cvt_color(&image, &mut bgra, code, 0)?;
+            bgra
+        }
+        None => image,
+    };
+
+    // frames that differ from the first one are scaled to its size, before the conversion
+    // since there is no resizing 16-bit floats
+    if bgra.size()? != size {
+        let mut resized = opencv::core::Mat::default();
+        opencv::imgproc::resize(
+            &bgra,
+            &mut resized,
+            size,
+            0.0,
+            0.0,
+            opencv::imgproc::INTER_AREA,
+        )?;
+        bgra = resized;
+    }
+
+    let depth = bgra.depth()?;
+    let scale = match (float, depth) {
+        (true, opencv::core::CV_8U) => 1.0 / 255.0,
+        (true, opencv::core::CV_16U) => 1.0 / 65535.0,
+        (true, _) => 1.0,
+        (false, opencv::core::CV_8U) => 1.0,
+        (false, opencv::core::CV_16U) => 1.0 / 257.0,
+        (false, _) => 255.0,
+    };
+    let typ = if float {
+        opencv::core::CV_16FC4
+    } else {
+        opencv::core::CV_8UC4
+    };
+
+    if bgra.typ()? == typ {
+        bgra.copy_to(frame)
+    } else {
+        bgra.convert_to(frame, typ, scale, 0.0)
+    }
+}
+
+/// Frames decoded by the preload thread so far, in order.
+type Preloaded = Arc<Mutex<Vec<opencv::core::Mat>>>;
+
+/// Preload thread body, decodes frames until all are done, one fails or it is stopped.
+fn run_preload(
+    frames: Vec<PathBuf>,
+    float: bool,
+    size: opencv::core::Size,
+    preloaded: Preloaded,
+    stop: Arc<AtomicBool>,
+) {
+    for path in frames.iter() {
+        if stop.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let mut frame = opencv::core::Mat::default();
+        if let Err(e) = decode(path, float, size, &mut frame) {
+            // the remaining frames are read from disk, which reports the error
+            println!("Error preloading {}: {}", path.display(), e);
+            return;
+        }
+        preloaded.lock().unwrap().push(frame);
+    }
+}
+
+/// Whether `count` frames of `size` fit into the preload budget.
+fn fits_budget(size: opencv::core::Size, float: bool, count: usize) -> bool {
+    let bytes_per_pixel = if float { 8 } else { 4 };
+    (size.width.max(0) as usize)
+        .saturating_mul(size.height.max(0) as usize)
+        .saturating_mul(bytes_per_pixel)
+        .saturating_mul(count)
+        <= PRELOAD_BUDGET
+}
+
+/// Plays numbered images as the frames of a video. Sequences of 16-bit or float images
+/// (e.g. EXR) are decoded to 16-bit floats so they keep their precision.
+pub struct ImageSequence {
+    frames: Vec<PathBuf>,
+    settings: ImageSequenceSettings,
+    /// Size of the first frame, the others are scaled to it.
+    size: opencv::core::Size,
+    /// Whether the first frame has more than 8 bits per channel.
+    float: bool,
+    /// Frames decoded in the background when preloading, the ones that aren't ready yet
+    /// are read from disk.
+    preloaded: Option<Preloaded>,
+    preload_stop: Arc<AtomicBool>,
+    preload_thread: Option<thread::JoinHandle<()>>,
+    /// Frame the next read returns.
+    next: usize,
+}
+
+impl ImageSequence {
+    pub fn new(frames: Vec<PathBuf>, mut settings: ImageSequenceSettings) -> Result<Self, String> {
+        if settings.fps <= 0.0 {
+            settings.fps = ImageSequenceSettings::default().fps;
+        }
+
+        let first = read_image(&frames[0]).map_err(|e| e.to_string())?;
+        let depth = first.depth().map_err(|e| e.to_string())?;
+        let size = first.size().map_err(|e| e.to_string())?;
+        let float = depth != opencv::core::CV_8U;
+
+        let mut sequence = Self {
+            frames,
+            settings,
+            size,
+            float,
+            preloaded: None,
+            preload_stop: Arc::new(AtomicBool::new(false)),
+            preload_thread: None,
+            next: 0,
+        };
+
+        if settings.preload {
+            if fits_budget(size, float, sequence.frames.len()) {
+                sequence.start_preload();
+            } else {
+                println!("Too many frames to preload, reading them from disk as they are shown");
+            }
+        }
+
+        Ok(sequence)
+    }
+
+    fn start_preload(&mut self) {
+        let preloaded = Preloaded::default();
+        self.preloaded = Some(preloaded.clone());
+
+        let frames = self.frames.clone();
+        let (float, size) = (self.float, self.size);
+        let stop = self.preload_stop.clone();
+        self.preload_thread = Some(thread::spawn(move || {
+            run_preload(frames, float, size, preloaded, stop);
+        }));
+    }
+}
+
+impl Drop for ImageSequence {
+    fn drop(&mut self) {
+        self.preload_stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.preload_thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+impl FrameSource for ImageSequence {
+    fn size(&self) -> (f64, f64) {
+        (self.size.width as f64, self.size.height as f64)
+    }
+
+    fn frame_rate(&self) -> f64 {
+        self.settings.fps
+    }
+
+    fn frame_count(&self) -> f64 {
+        self.frames.len() as f64
+    }
+
+    fn read_frame(&mut self, frame: &mut opencv::core::Mat) -> opencv::Result<bool> {
+        if self.next >= self.frames.len() {
+            return Ok(false);
+        }
+
+        let preloaded = self
+            .preloaded
+            .as_ref()
+            .and_then(|p| p.lock().unwrap().get(self.next).map(|f| f.copy_to(frame)));
+        match preloaded {
+            Some(result) => result?,
+            None => decode(&self.frames[self.next], self.float, self.size, frame)?,
+        }
+
+        self.next += 1;
+        Ok(true)
+    }
+
+    fn skip_frame(&mut self) -> opencv::Result<bool> {
+        if self.next >= self.frames.len() {
+            return Ok(false);
+        }
+        self.next += 1;
+        Ok(true)
+    }
+
+    fn seek_frame(&mut self, frame: f64) -> opencv::Result<()> {
+        self.next = frame.max(0.0) as usize;
+        Ok(())
+    }
+
+    fn position(&self) -> f64 {
+        self.next.saturating_sub(1) as f64 / self.settings.fps
+    }
+
+    fn texture_format(&self) -> wgpu::TextureFormat {
+        if self.float {
+            wgpu::TextureFormat::Rgba16Float
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        }
+    }
+
+    fn preloaded(&self) -> bool {
+        self.preloaded.is_some()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_sequence_frames() {
+    let names = [
+        "frame_0010.png",
+        "frame_0002.png",
+        "frame_0001.png",
+        "notes.txt",
+        "matte_01.exr",
+        "matte_02.exr",
+        "frame_0003.PNG",
+        "cover.png",
+    ]
+    .iter()
+    .map(|n| n.to_string())
+    .collect::<Vec<String>>();
+
+    assert_eq!(
+        sequence_frames(&names, None),
+        vec![
+            "frame_0001.png",
+            "frame_0002.png",
+            "frame_0003.PNG",
+            "frame_0010.png"
+        ]
+    );
+    assert_eq!(
+        sequence_frames(&names, Some("matte_02.exr")),
+        vec!["matte_01.exr", "matte_02.exr"]
+    );
+    assert!(sequence_frames(&names, Some("cover.png")).is_empty());
+    assert_eq!(
+        parse_frame_name("shot12_v3_0005.tif"),
+        Some(("shot12_v3_", 5, "tif".to_string()))
+    );
+
+    // 4K frames take about 32 MiB each, twice that as half floats
+    let uhd = opencv::core::Size::new(3840, 2160);
+    assert!(fits_budget(uhd, false, 60));
+    assert!(!fits_budget(uhd, true, 60));
+    assert!(!fits_budget(uhd, false, usize::MAX));
+}
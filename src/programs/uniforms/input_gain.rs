@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+
+/// Input level below which the AGC holds its gain instead of amplifying noise, in dBFS.
+const AGC_GATE: f32 = -60.0;
+/// Time for the AGC to cut the gain when the input gets louder, in ms.
+const AGC_ATTACK: f32 = 100.0;
+/// Time for the AGC to raise the gain when the input gets quieter, in ms.
+const AGC_RELEASE: f32 = 3000.0;
+
+pub fn db_to_gain(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
+pub fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.max(1e-10).log10()
+}
+
+/// Input gain settings, levels are in dB.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GainSettings {
+    /// Manual gain applied before the AGC.
+    pub gain: f32,
+    /// Automatically adjust the gain so the RMS level settles at `target`.
+    pub agc: bool,
+    /// AGC target RMS level in dBFS.
+    pub target: f32,
+    /// Largest boost or cut the AGC may apply.
+    pub max_gain: f32,
+}
+
+impl Default for GainSettings {
+    fn default() -> Self {
+        Self {
+            gain: 0.0,
+            agc: false,
+            target: -18.0,
+            max_gain: 30.0,
+        }
+    }
+}
+
+/// Levels of a frame after the gain stage.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Levels {
+    /// Absolute peak, linear.
+    pub peak: f32,
+    /// RMS over all channels, linear.
+    pub rms: f32,
+    /// Whether any sample had to be clamped to [-1, 1].
+    pub clipped: bool,
+    /// Total gain applied, in dB.
+    pub gain: f32,
+}
+
+/// Applies the manual gain and AGC to frames before they are sent to subscribers.
+#[derive(Debug, Clone)]
+pub struct GainStage {
+    pub settings: GainSettings,
+
+    agc_gain: f32,
+    current_gain: f32,
+}
+
+impl GainStage {
+    pub fn new(settings: GainSettings) -> Self {
+        Self {
+            settings,
+            agc_gain: 0.0,
+            current_gain: db_to_gain(settings.gain),
+        }
+    }
+
+    /// Move the AGC gain towards the one that brings `input_db` to the target.
+    /// Silence below the gate holds the current gain.
+    fn update_agc(&mut self, input_db: f32, dt: f32) {
+        if !self.settings.agc {
+            self.agc_gain = 0.0;
+            return;
+        }
+
+        if input_db < AGC_GATE {
+            return;
+        }
+
+        let max_gain = self.settings.max_gain.abs();
+        let desired = (self.settings.target - input_db).clamp(-max_gain, max_gain);
+        let time = if desired < self.agc_gain {
+            AGC_ATTACK
+        } else {
+            AGC_RELEASE
+        };
+        let coefficient = (-dt * 1000.0 / time).exp();
+        self.agc_gain = desired + (self.agc_gain - desired) * coefficient;
+    }
+
+    /// Apply the gain to a frame of channels in place and return the resulting levels.
+    /// The gain is ramped across the frame to avoid zipper noise and the output is clamped.
+    pub fn process(&mut self, frame: &mut [Vec<f32>], sample_rate: f32) -> Levels {
+        let num_samples = frame.first().map_or(0, |c| c.len());
+        if num_samples == 0 {
+            return Levels::default();
+        }
+
+        let manual_gain = db_to_gain(self.settings.gain);
+        let num_values = (num_samples * frame.len()) as f32;
+        let input_rms = (frame.iter().flatten().map(|s| s * s).sum::<f32>() / num_values).sqrt();
+        let dt = num_samples as f32 / sample_rate.max(1.0);
+        self.update_agc(gain_to_db(input_rms * manual_gain), dt);
+
+        let start_gain = self.current_gain;
+        let end_gain = manual_gain * db_to_gain(self.agc_gain);
+        let mut levels = Levels {
+            gain: gain_to_db(end_gain),
+            ..Levels::default()
+        };
+        let mut sum = 0.0;
+
+        for channel in frame.iter_mut() {
+            for (i, sample) in channel.iter_mut().enumerate() {
+                let t = (i + 1) as f32 / num_samples as f32;
+                let value = *sample * (start_gain + (end_gain - start_gain) * t);
+                if value.abs() > 1.0 {
+                    levels.clipped = true;
+                }
+                *sample = value.clamp(-1.0, 1.0);
+                levels.peak = levels.peak.max(sample.abs());
+                sum += *sample * *sample;
+            }
+        }
+
+        levels.rms = (sum / num_values).sqrt();
+        self.current_gain = end_gain;
+        levels
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_gain_stage() {
+    assert!((gain_to_db(db_to_gain(-6.0)) + 6.0).abs() < 1e-4);
+
+    // manual gain ramps in over the first frame, then holds
+    let mut stage = GainStage::new(GainSettings::default());
+    stage.settings.gain = 20.0;
+    let mut frame = vec![vec![0.05; 100]];
+    let levels = stage.process(&mut frame, 1000.0);
+    assert!(frame[0][0] < 0.5);
+    assert!((frame[0][99] - 0.5).abs() < 1e-4);
+    assert!(!levels.clipped);
+
+    let mut frame = vec![vec![0.2; 100]];
+    let levels = stage.process(&mut frame, 1000.0);
+    assert!(levels.clipped);
+    assert_eq!(levels.peak, 1.0);
+
+    // the agc settles quiet and loud inputs at the same level
+    let settle = |amplitude: f32| {
+        let mut stage = GainStage::new(GainSettings {
+            agc: true,
+            ..GainSettings::default()
+        });
+        let mut levels = Levels::default();
+        for _ in 0..2000 {
+            let mut frame = vec![vec![amplitude, -amplitude]; 1];
+            levels = stage.process(&mut frame, 100.0);
+        }
+        gain_to_db(levels.rms)
+    };
+    assert!((settle(0.01) + 18.0).abs() < 0.5);
+    assert!((settle(0.9) + 18.0).abs() < 0.5);
+
+    // silence below the gate is not amplified
+    let mut stage = GainStage::new(GainSettings {
+        agc: true,
+        ..GainSettings::default()
+    });
+    let mut frame = vec![vec![0.0001; 100]];
+    stage.process(&mut frame, 1000.0);
+    assert_eq!(stage.agc_gain, 0.0);
+}
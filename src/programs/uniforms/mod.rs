@@ -3,21 +3,37 @@ use std::collections::HashMap;
 
 use crate::programs::config;
 
+pub mod analysis;
+pub mod animated_image;
 pub mod audio;
 pub mod audio_features;
 pub mod audio_fft;
+pub mod audio_generator;
 pub mod audio_source;
+pub mod audio_trigger;
+pub mod background;
 pub mod base;
 pub mod camera;
+pub mod chroma;
 pub mod color;
+pub mod envelope;
+pub mod faces;
+pub mod frame_source;
+pub mod frame_upload;
 pub mod general;
 pub mod geometry;
 pub mod image;
+pub mod image_sequence;
+pub mod input_gain;
 pub mod multipass;
 pub mod noise;
+pub mod optical_flow;
+pub mod source_transform;
 pub mod video;
+pub mod video_audio;
 pub mod video_capture;
 pub mod webcam;
+pub mod webcam_devices;
 
 use base::{UniformBuffers, UniformBuffersMethods};
 
@@ -30,13 +46,30 @@ pub struct UniformSubscriptions {
     pub audio_fft: bool,
     pub camera: bool,
     pub color: bool,
+    /// Faces found in the first webcam, which is captured for them.
+    pub faces: bool,
     pub general: bool,
     pub geometry: bool,
     pub image: bool,
     pub noise: bool,
     pub multipass: bool,
     pub video: bool,
-    pub webcam: bool,
+    /// Optical flow of the video, which is played for it.
+    pub video_flow: bool,
+    /// Foreground mask of the video, which is played for it.
+    pub video_mask: bool,
+    /// Subscriptions to each camera, `webcam` being the first.
+    pub webcam: [bool; webcam::MAX_WEBCAMS],
+    /// Optical flow of the first webcam, which is captured for it.
+    pub webcam_flow: bool,
+    /// Foreground mask of the first webcam, which is captured for it.
+    pub webcam_mask: bool,
+}
+
+impl UniformSubscriptions {
+    pub fn uses_webcam(&self) -> bool {
+        self.webcam.iter().any(|w| *w)
+    }
 }
 
 /// Build a subscriptions struct from a list of uniform names
@@ -47,13 +80,18 @@ pub fn get_subscriptions(names: &[String]) -> UniformSubscriptions {
         audio_fft: false,
         camera: false,
         color: false,
+        faces: false,
         geometry: false,
         general: false,
         image: false,
         noise: false,
         multipass: false,
         video: false,
-        webcam: false,
+        video_flow: false,
+        video_mask: false,
+        webcam: [false; webcam::MAX_WEBCAMS],
+        webcam_flow: false,
+        webcam_mask: false,
     };
 
     names.iter().for_each(|n| match n.as_str() {
@@ -62,14 +100,37 @@ pub fn get_subscriptions(names: &[String]) -> UniformSubscriptions {
         "audio_fft" => subscriptions.audio_fft = true,
         "camera" => subscriptions.camera = true,
         "color" => subscriptions.color = true,
+        "faces" => {
+            subscriptions.faces = true;
+            subscriptions.webcam[0] = true;
+        }
         "general" => subscriptions.general = true,
         "geometry" => subscriptions.geometry = true,
         "image" => subscriptions.image = true,
         "noise" => subscriptions.noise = true,
         "multipass" => subscriptions.multipass = true,
         "video" => subscriptions.video = true,
-        "webcam" => subscriptions.webcam = true,
-        _ => (),
+        "video_flow" => {
+            subscriptions.video = true;
+            subscriptions.video_flow = true;
+        }
+        "video_mask" => {
+            subscriptions.video = true;
+            subscriptions.video_mask = true;
+        }
+        "webcam_flow" => {
+            subscriptions.webcam[0] = true;
+            subscriptions.webcam_flow = true;
+        }
+        "webcam_mask" => {
+            subscriptions.webcam[0] = true;
+            subscriptions.webcam_mask = true;
+        }
+        n => {
+            if let Some(index) = webcam::parse_name(n) {
+                subscriptions.webcam[index] = true;
+            }
+        }
     });
 
     subscriptions
@@ -85,13 +146,18 @@ pub struct BufferStore {
     pub buffers: UniformBuffers,
     pub camera_uniforms: camera::CameraUniforms,
     pub color_uniforms: color::ColorUniforms,
+    pub face_uniforms: faces::FaceUniforms,
     pub general_uniforms: general::GeneralUniforms,
     pub geometry_uniforms: geometry::GeometryUniforms,
     pub image_uniforms: image::ImageUniforms,
     pub noise_uniforms: noise::NoiseUniforms,
     pub multipass_uniforms: multipass::MultipassUniforms,
+    pub video_analyses: analysis::SourceAnalyses,
     pub video_uniforms: video::VideoUniforms,
-    pub webcam_uniforms: webcam::WebcamUniforms,
+    /// Analyses of the first webcam.
+    pub webcam_analyses: analysis::SourceAnalyses,
+    /// One per camera.
+    pub webcam_uniforms: Vec<webcam::WebcamUniforms>,
 }
 
 impl BufferStore {
@@ -117,6 +183,9 @@ impl BufferStore {
         let color_uniforms = color::ColorUniforms::new();
         buffers.add(device, "color", &color_uniforms);
 
+        let face_uniforms = faces::FaceUniforms::new();
+        buffers.add(device, "faces", &face_uniforms);
+
         let general_uniforms = general::GeneralUniforms::new(size);
         buffers.add(device, "general", &general_uniforms);
 
@@ -135,8 +204,19 @@ impl BufferStore {
         let video_uniforms = video::VideoUniforms::new();
         buffers.add(device, "video", &video_uniforms);
 
-        let webcam_uniforms = webcam::WebcamUniforms::new();
-        buffers.add(device, "webcam", &webcam_uniforms);
+        let video_analyses = analysis::SourceAnalyses::new(device, &mut buffers, "video", "Video");
+
+        let webcam_analyses =
+            analysis::SourceAnalyses::new(device, &mut buffers, "webcam", "Webcam");
+
+        let webcam_uniforms = (0..webcam::MAX_WEBCAMS)
+            .map(|_| webcam::WebcamUniforms::new())
+            .collect::<Vec<webcam::WebcamUniforms>>();
+        for (index, uniforms) in webcam_uniforms.iter().enumerate() {
+            for name in webcam::buffer_names(index) {
+                buffers.add(device, &name, uniforms);
+            }
+        }
 
         Self {
             audio_uniforms,
@@ -146,24 +226,32 @@ impl BufferStore {
             buffers,
             camera_uniforms,
             color_uniforms,
+            face_uniforms,
             general_uniforms,
             geometry_uniforms,
             image_uniforms,
             multipass_uniforms,
             noise_uniforms,
+            video_analyses,
             video_uniforms,
+            webcam_analyses,
             webcam_uniforms,
         }
     }
 
+    fn add_webcam_buffers(&mut self, device: &wgpu::Device, index: usize) {
+        for name in webcam::buffer_names(index) {
+            self.buffers
+                .add(device, &name, &self.webcam_uniforms[index]);
+        }
+    }
+
     pub fn start_audio_session(&mut self, subscriptions: &UniformSubscriptions) {
         if !(subscriptions.audio || subscriptions.audio_fft || subscriptions.audio_features) {
             return;
         }
 
-        if !self.audio_source.start_session() {
-            return;
-        }
+        self.audio_source.start_session();
 
         if subscriptions.audio {
             self.audio_uniforms.start_session(&mut self.audio_source);
@@ -200,14 +288,26 @@ impl BufferStore {
         num_samples: u32,
     ) {
         self.end_audio_session();
+        self.audio_source
+            .set_generator(settings.as_ref().and_then(|s| s.audio_generator.clone()));
+        self.audio_uniforms.configure(device, settings);
+        if self.audio_uniforms.updated {
+            self.buffers.add(device, "audio", &self.audio_uniforms);
+        }
         self.audio_features_uniforms.configure(settings);
-        self.audio_fft_uniforms.configure(settings);
+        self.audio_fft_uniforms.configure(device, settings);
+        if self.audio_fft_uniforms.updated {
+            self.buffers
+                .add(device, "audio_fft", &self.audio_fft_uniforms);
+        }
         self.start_audio_session(subscriptions);
 
         self.camera_uniforms.configure(settings);
 
         self.color_uniforms.configure(settings);
 
+        self.general_uniforms.configure(settings);
+
         self.image_uniforms.configure(app, settings);
 
         self.multipass_uniforms
@@ -225,14 +325,42 @@ impl BufferStore {
             }
         }
 
-        if subscriptions.webcam {
-            self.webcam_uniforms.configure(device, size);
-            if self.webcam_uniforms.updated {
-                self.buffers.add(device, "webcam", &self.webcam_uniforms);
+        for (index, subscribed) in subscriptions.webcam.iter().enumerate() {
+            if *subscribed {
+                self.webcam_uniforms[index].configure(device, size);
+                if self.webcam_uniforms[index].updated {
+                    self.add_webcam_buffers(device, index);
+                }
+            } else {
+                self.webcam_uniforms[index].end_session();
             }
-        } else {
-            self.webcam_uniforms.end_session();
         }
+
+        if subscriptions.faces {
+            self.face_uniforms.configure(app, settings);
+        } else if let Some(capture) = &mut self.webcam_uniforms[0].video_capture {
+            capture.remove_analysis(faces::ANALYSIS);
+        }
+
+        self.video_analyses.configure(
+            settings,
+            subscriptions.video_flow,
+            subscriptions.video_mask,
+            self.video_uniforms.video_capture.as_mut(),
+        );
+
+        self.webcam_analyses.configure(
+            settings,
+            subscriptions.webcam_flow,
+            subscriptions.webcam_mask,
+            self.webcam_uniforms[0].video_capture.as_mut(),
+        );
+    }
+
+    /// Forget the learned backgrounds of the foreground masks.
+    pub fn relearn_background(&mut self) {
+        self.video_analyses.mask.relearn();
+        self.webcam_analyses.mask.relearn();
     }
 
     /// Update uniform data.
@@ -245,6 +373,12 @@ impl BufferStore {
         size: Point2,
         num_samples: u32,
     ) {
+        if subscriptions.video {
+            self.video_uniforms.sync_audio(&mut self.audio_source);
+        } else {
+            self.audio_source.set_feed(None);
+        }
+
         if subscriptions.audio || subscriptions.audio_features || subscriptions.audio_fft {
             self.audio_source.update();
         }
@@ -254,17 +388,22 @@ impl BufferStore {
         }
 
         if subscriptions.audio_features {
-            self.audio_features_uniforms.update(&mut self.audio_source);
+            self.audio_features_uniforms.update();
         }
 
         if subscriptions.audio_fft {
             self.audio_fft_uniforms.update();
         }
 
-        if subscriptions.general {
+        // synced videos follow the time uniform
+        if subscriptions.general || (subscriptions.video && self.video_uniforms.sync) {
             self.general_uniforms.update();
         }
 
+        if subscriptions.image {
+            self.image_uniforms.update(device, encoder);
+        }
+
         if subscriptions.image && self.image_uniforms.updated {
             self.buffers.add(device, "image", &self.image_uniforms);
         }
@@ -279,15 +418,43 @@ impl BufferStore {
         }
 
         if subscriptions.video {
-            self.video_uniforms.update();
+            self.video_uniforms
+                .update(device, self.general_uniforms.data.time);
             if self.video_uniforms.updated {
                 self.buffers.add(device, "video", &self.video_uniforms);
             }
         }
 
-        if subscriptions.webcam {
-            self.webcam_uniforms.update();
+        for (index, subscribed) in subscriptions.webcam.iter().enumerate() {
+            if *subscribed {
+                self.webcam_uniforms[index].update(device);
+                if self.webcam_uniforms[index].updated {
+                    self.add_webcam_buffers(device, index);
+                }
+            }
+        }
+
+        if subscriptions.faces {
+            if let Some(capture) = &mut self.webcam_uniforms[0].video_capture {
+                self.face_uniforms.update(capture);
+            }
         }
+
+        self.video_analyses.update(
+            device,
+            &mut self.buffers,
+            subscriptions.video_flow,
+            subscriptions.video_mask,
+            self.video_uniforms.video_capture.as_mut(),
+        );
+
+        self.webcam_analyses.update(
+            device,
+            &mut self.buffers,
+            subscriptions.webcam_flow,
+            subscriptions.webcam_mask,
+            self.webcam_uniforms[0].video_capture.as_mut(),
+        );
     }
 
     /// Update GPU uniform buffers with current data.
@@ -300,6 +467,8 @@ impl BufferStore {
     ) {
         if subscriptions.audio {
             self.audio_uniforms.update_texture(device, encoder);
+            self.buffers
+                .update(device, encoder, "audio", &self.audio_uniforms);
         }
 
         if subscriptions.audio_features {
@@ -314,6 +483,8 @@ impl BufferStore {
 
         if subscriptions.audio_fft {
             self.audio_fft_uniforms.update_texture(device, encoder);
+            self.buffers
+                .update(device, encoder, "audio_fft", &self.audio_fft_uniforms);
         }
 
         if subscriptions.camera {
@@ -326,6 +497,11 @@ impl BufferStore {
                 .update(device, encoder, "color", &self.color_uniforms);
         }
 
+        if subscriptions.faces {
+            self.buffers
+                .update(device, encoder, "faces", &self.face_uniforms);
+        }
+
         if subscriptions.general {
             self.buffers
                 .update(device, encoder, "general", &self.general_uniforms);
@@ -352,10 +528,30 @@ impl BufferStore {
                 .update(device, encoder, "video", &self.video_uniforms);
         }
 
-        if subscriptions.webcam {
-            self.webcam_uniforms.update_texture(device, encoder);
-            self.buffers
-                .update(device, encoder, "webcam", &self.webcam_uniforms);
+        self.video_analyses.update_buffers(
+            device,
+            encoder,
+            &self.buffers,
+            subscriptions.video_flow,
+            subscriptions.video_mask,
+        );
+
+        self.webcam_analyses.update_buffers(
+            device,
+            encoder,
+            &self.buffers,
+            subscriptions.webcam_flow,
+            subscriptions.webcam_mask,
+        );
+
+        for (index, subscribed) in subscriptions.webcam.iter().enumerate() {
+            if *subscribed {
+                let uniforms = &self.webcam_uniforms[index];
+                uniforms.update_texture(device, encoder);
+                for name in webcam::buffer_names(index) {
+                    self.buffers.update(device, encoder, &name, uniforms);
+                }
+            }
         }
     }
 
@@ -370,8 +566,10 @@ impl BufferStore {
             self.video_uniforms.pause();
         }
 
-        if subscriptions.webcam {
-            self.webcam_uniforms.pause();
+        for (index, subscribed) in subscriptions.webcam.iter().enumerate() {
+            if *subscribed {
+                self.webcam_uniforms[index].pause();
+            }
         }
     }
 
@@ -386,29 +584,43 @@ impl BufferStore {
             self.video_uniforms.unpause();
         }
 
-        if subscriptions.webcam {
-            self.webcam_uniforms.unpause();
+        for (index, subscribed) in subscriptions.webcam.iter().enumerate() {
+            if *subscribed {
+                self.webcam_uniforms[index].unpause();
+            }
         }
     }
 
     pub fn updated(&self) -> bool {
-        self.image_uniforms.updated
+        self.audio_uniforms.updated
+            || self.audio_fft_uniforms.updated
+            || self.image_uniforms.updated
             || self.multipass_uniforms.updated
+            || self.video_analyses.updated()
             || self.video_uniforms.updated
-            || self.webcam_uniforms.updated
+            || self.webcam_analyses.updated()
+            || self.webcam_uniforms.iter().any(|w| w.updated)
     }
 
     pub fn finish_update(&mut self) {
+        self.audio_uniforms.updated = false;
+        self.audio_fft_uniforms.updated = false;
         self.image_uniforms.updated = false;
         self.multipass_uniforms.updated = false;
+        self.video_analyses.finish_update();
         self.video_uniforms.updated = false;
-        self.webcam_uniforms.updated = false;
+        self.webcam_analyses.finish_update();
+        self.webcam_uniforms
+            .iter_mut()
+            .for_each(|w| w.updated = false);
     }
 
     pub fn end_session(&mut self) {
         self.end_audio_session();
         self.video_uniforms.end_session();
-        self.webcam_uniforms.end_session();
+        self.webcam_uniforms
+            .iter_mut()
+            .for_each(|w| w.end_session());
     }
 
     pub fn get_errors(&self) -> HashMap<String, Vec<String>> {
@@ -422,6 +634,10 @@ impl BufferStore {
             errors.insert(String::from("Audio Features"), vec![audio_error]);
         }
 
+        if let Some(face_error) = self.face_uniforms.error.clone() {
+            errors.insert(String::from("Faces"), vec![face_error]);
+        }
+
         if let Some(image_error) = self.image_uniforms.error.clone() {
             errors.insert(String::from("Image"), vec![image_error]);
         }
@@ -432,9 +648,19 @@ impl BufferStore {
             }
         }
 
-        if let Some(ref capture) = self.webcam_uniforms.video_capture {
-            if let Some(webcam_error) = capture.error.clone() {
-                errors.insert(String::from("Webcam"), vec![webcam_error]);
+        self.video_analyses.get_errors(&mut errors);
+
+        self.webcam_analyses.get_errors(&mut errors);
+
+        for (index, uniforms) in self.webcam_uniforms.iter().enumerate() {
+            let webcam_error = uniforms.error.clone().or_else(|| {
+                uniforms
+                    .video_capture
+                    .as_ref()
+                    .and_then(|c| c.error.clone())
+            });
+            if let Some(webcam_error) = webcam_error {
+                errors.insert(format!("Webcam {}", index), vec![webcam_error]);
             }
         }
 
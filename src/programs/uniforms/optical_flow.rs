@@ -0,0 +1,281 @@
+use nannou::prelude::*;
+use opencv::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::programs::config;
+use crate::programs::uniforms::analysis::{AnalysisOutput, AnalysisSender};
+use crate::programs::uniforms::base::Bufferable;
+use crate::programs::uniforms::source_transform::SourceTransform;
+use crate::programs::uniforms::video_capture::{FrameAnalysis, VideoCapture};
+
+/// Name of the analysis running on the capture thread.
+pub const ANALYSIS: &str = "optical_flow";
+
+/// How dense optical flow is computed between consecutive frames.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FlowSettings {
+    /// Frames are scaled by this before computing the flow, which is also the flow's resolution.
+    pub scale: f64,
+    /// Pyramid levels, more follow faster motion.
+    pub levels: i32,
+    /// Averaging window size, larger is smoother and less noisy.
+    pub window: i32,
+    /// Iterations at each pyramid level.
+    pub iterations: i32,
+}
+
+impl Default for FlowSettings {
+    fn default() -> Self {
+        Self {
+            scale: 0.25,
+            levels: 3,
+            window: 15,
+            iterations: 3,
+        }
+    }
+}
+
+/// Motion of each pixel since the previous frame in source texture coordinates, row by row
+/// from the top, and the size of the field.
+type Flow = (Vec<[f32; 2]>, [u32; 2]);
+
+/// Computes Farneback optical flow between consecutive frames of a capture on its thread.
+struct FlowAnalysis {
+    settings: FlowSettings,
+    output: AnalysisSender<Flow>,
+    gray: opencv::core::Mat,
+    previous: opencv::core::Mat,
+    next: opencv::core::Mat,
+    flow: opencv::core::Mat,
+}
+
+impl FlowAnalysis {
+    fn compute(&mut self, frame: &opencv::core::Mat) -> opencv::Result<Option<Flow>> {
+        let scale = self.settings.scale.max(0.05).min(1.0);
+        opencv::imgproc::cvt_color(frame, &mut self.gray, opencv::imgproc::COLOR_BGRA2GRAY, 0)?;
+        opencv::imgproc::resize(
+            &self.gray,
+            &mut self.next,
+            opencv::core::Size::default(),
+            scale,
+            scale,
+            opencv::imgproc::INTER_AREA,
+        )?;
+
+        let size = self.next.size()?;
+        let ready = self.previous.size()? == size;
+        if ready {
+            opencv::video::calc_optical_flow_farneback(
+                &self.previous,
+                &self.next,
+                &mut self.flow,
+                0.5,
+                self.settings.levels.max(1),
+                self.settings.window.max(3),
+                self.settings.iterations.max(1),
+                5,
+                1.1,
+                0,
+            )?;
+        }
+        std::mem::swap(&mut self.previous, &mut self.next);
+
+        if !ready {
+            return Ok(None);
+        }
+
+        let (width, height) = (size.width as f32, size.height as f32);
+        let flow = self
+            .flow
+            .data_typed::<opencv::core::Vec2f>()?
+            .iter()
+            .map(|v| [v[0] / width, v[1] / height])
+            .collect();
+        Ok(Some((flow, [size.width as u32, size.height as u32])))
+    }
+}
+
+impl FrameAnalysis for FlowAnalysis {
+    fn name(&self) -> &'static str {
+        ANALYSIS
+    }
+
+    fn analyze(&mut self, frame: &opencv::core::Mat) {
+        let result = self.compute(frame);
+        self.output.send(result);
+    }
+
+    fn reset(&mut self) {
+        // the flow to a frame that doesn't follow the previous one isn't motion
+        self.previous = opencv::core::Mat::default();
+    }
+}
+
+/// A motion in source texture coordinates as it appears in the transformed source,
+/// with y pointing up like shader coordinates.
+fn output_motion([x, y]: [f32; 2], transform: &SourceTransform) -> [f32; 2] {
+    // invert the linear part of the transform
+    let [_, u, v] = transform.coords_map();
+    let det = u[0] * v[1] - u[1] * v[0];
+    let du = (x * v[1] - y * v[0]) / det;
+    let dv = (u[0] * y - u[1] * x) / det;
+    [du, -dv]
+}
+
+/// A flow field of `size` lined up with the transformed source, with its vectors converted.
+fn transform_flow(
+    flow: &[[f32; 2]],
+    size: [u32; 2],
+    transform: &SourceTransform,
+) -> (Vec<[f32; 2]>, [u32; 2]) {
+    let (flow, size) = transform.resample(flow, size);
+    let flow = flow
+        .into_iter()
+        .map(|motion| output_motion(motion, transform))
+        .collect();
+    (flow, size)
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Data {
+    /// Average motion per frame in texture coordinates, y pointing up.
+    pub flow_motion: Vector2,
+    /// Average length of the motion vectors.
+    pub flow_magnitude: f32,
+}
+
+/// Optical flow of a video or webcam as an `Rg32Float` texture, the same orientation as the
+/// source's texture, holding the motion of each pixel since the previous frame.
+pub struct FlowUniforms {
+    pub error: Option<String>,
+    pub settings: FlowSettings,
+    pub updated: bool,
+
+    data: Data,
+    output: AnalysisOutput<Flow>,
+    flow: Vec<[f32; 2]>,
+    texture: wgpu::Texture,
+}
+
+impl Bufferable<Data> for FlowUniforms {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { wgpu::bytes::from(&self.data) }
+    }
+
+    fn textures(&self) -> Vec<&wgpu::Texture> {
+        vec![&self.texture]
+    }
+}
+
+fn create_texture(device: &wgpu::Device, size: [u32; 2]) -> wgpu::Texture {
+    wgpu::TextureBuilder::new()
+        .size(size)
+        .format(wgpu::TextureFormat::Rg32Float)
+        .usage(wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST)
+        .build(device)
+}
+
+impl FlowUniforms {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            error: None,
+            settings: FlowSettings::default(),
+            updated: false,
+            data: Data {
+                flow_motion: pt2(0.0, 0.0),
+                flow_magnitude: 0.0,
+            },
+            output: AnalysisOutput::new(ANALYSIS),
+            flow: vec![[0.0; 2]],
+            texture: create_texture(device, [1, 1]),
+        }
+    }
+
+    pub fn configure(&mut self, settings: &Option<config::ProgramSettings>) {
+        self.set_settings(
+            settings
+                .as_ref()
+                .and_then(|s| s.optical_flow)
+                .unwrap_or_default(),
+        );
+    }
+
+    /// The flow is computed with `settings` from the next update on.
+    pub fn set_settings(&mut self, settings: FlowSettings) {
+        self.settings = settings;
+        self.error = None;
+        self.output.detach();
+    }
+
+    /// Compute the flow of `capture`, starting the analysis if it doesn't run yet,
+    /// and take the latest flow.
+    pub fn update(&mut self, device: &wgpu::Device, capture: &mut VideoCapture) {
+        let settings = self.settings;
+        let latest = self.output.update(capture, |output| {
+            Ok(Box::new(FlowAnalysis {
+                settings,
+                output,
+                gray: opencv::core::Mat::default(),
+                previous: opencv::core::Mat::default(),
+                next: opencv::core::Mat::default(),
+                flow: opencv::core::Mat::default(),
+            }))
+        });
+
+        if let Some(error) = latest.error {
+            println!("Optical flow error: {}", error);
+            self.error = Some(error);
+        }
+        let (flow, size) = match latest.value {
+            Some((flow, size)) => transform_flow(&flow, size, &capture.transform()),
+            None => return,
+        };
+
+        if self.texture.size() != size {
+            self.texture = create_texture(device, size);
+            self.updated = true;
+        }
+
+        let count = flow.len().max(1) as f32;
+        let sum = flow
+            .iter()
+            .fold([0.0, 0.0], |sum, v| [sum[0] + v[0], sum[1] + v[1]]);
+        self.data.flow_motion = pt2(sum[0] / count, sum[1] / count);
+        self.data.flow_magnitude = flow
+            .iter()
+            .map(|v| (v[0] * v[0] + v[1] * v[1]).sqrt())
+            .sum::<f32>()
+            / count;
+        self.flow = flow;
+    }
+
+    pub fn update_texture(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        let bytes = unsafe { wgpu::bytes::from_slice(&self.flow) };
+        self.texture.upload_data(device, encoder, bytes);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_transform_flow() {
+    // a 2x1 field moving right and down
+    let flow = [[0.1, 0.2], [0.0, 0.0]];
+    let (out, size) = transform_flow(&flow, [2, 1], &SourceTransform::default());
+    assert_eq!(size, [2, 1]);
+    assert_eq!(out, vec![[0.1, -0.2], [0.0, 0.0]]);
+
+    // mirroring swaps the pixels and reverses horizontal motion
+    let (out, _) = transform_flow(&flow, [2, 1], &SourceTransform::mirrored());
+    assert_eq!(out, vec![[0.0, 0.0], [-0.1, -0.2]]);
+
+    // a quarter turn clockwise makes motion to the right go down
+    let rotated = SourceTransform {
+        rotation: 90,
+        ..SourceTransform::default()
+    };
+    let (out, size) = transform_flow(&flow, [2, 1], &rotated);
+    assert_eq!(size, [1, 2]);
+    assert_eq!(out, vec![[-0.2, -0.1], [0.0, 0.0]]);
+}
@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+
+/// Labels of the rotations the controls offer, in quarter turns.
+pub const ROTATIONS: [&str; 4] = ["0°", "90°", "180°", "270°"];
+
+/// Orientation, crop and color adjustments applied to a webcam, video or image source
+/// on the GPU before shaders sample it.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SourceTransform {
+    /// Flip horizontally.
+    pub mirror: bool,
+    /// Flip vertically.
+    pub flip: bool,
+    /// Clockwise rotation in degrees, rounded down to a multiple of 90.
+    pub rotation: u32,
+    /// Part of the source that is kept, `[x, y, width, height]` relative to its size
+    /// with the origin at the top left.
+    pub crop: [f32; 4],
+    /// Added to every channel.
+    pub brightness: f32,
+    /// Scales the distance from mid grey.
+    pub contrast: f32,
+    /// Scales the distance from the luma, 0 is greyscale.
+    pub saturation: f32,
+    pub gamma: f32,
+    /// The source color is already multiplied by its alpha, as in most keyed video exports.
+    /// It is divided out before the color adjustments.
+    pub premultiplied: bool,
+    /// Multiply the color by its alpha for shaders that composite premultiplied colors.
+    /// Otherwise shaders get straight alpha.
+    pub premultiply: bool,
+}
+
+impl Default for SourceTransform {
+    fn default() -> Self {
+        Self {
+            mirror: false,
+            flip: false,
+            rotation: 0,
+            crop: [0.0, 0.0, 1.0, 1.0],
+            brightness: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            gamma: 1.0,
+            premultiplied: false,
+            premultiply: false,
+        }
+    }
+}
+
+impl SourceTransform {
+    /// Webcams are mirrored by default so they behave like a mirror.
+    pub fn mirrored() -> Self {
+        Self {
+            mirror: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn quarter_turns(&self) -> u32 {
+        (self.rotation / 90) % 4
+    }
+
+    /// The crop rectangle limited to the source, never empty.
+    pub fn crop_rect(&self) -> [f32; 4] {
+        let x = self.crop[0].max(0.0).min(0.99);
+        let y = self.crop[1].max(0.0).min(0.99);
+        let width = self.crop[2].max(0.01).min(1.0 - x);
+        let height = self.crop[3].max(0.01).min(1.0 - y);
+        [x, y, width, height]
+    }
+
+    /// Size of the transformed source in pixels.
+    pub fn output_size(&self, size: [u32; 2]) -> [u32; 2] {
+        let [_, _, width, height] = self.crop_rect();
+        let width = ((size[0] as f32 * width).round() as u32).max(1);
+        let height = ((size[1] as f32 * height).round() as u32).max(1);
+        if self.quarter_turns() % 2 == 1 {
+            [height, width]
+        } else {
+            [width, height]
+        }
+    }
+
+    /// The source texture coordinate for a coordinate of the transformed source.
+    pub fn source_coords(&self, [u, v]: [f32; 2]) -> [f32; 2] {
+        let u = if self.mirror { 1.0 - u } else { u };
+        let v = if self.flip { 1.0 - v } else { v };
+
+        // undo the rotation
+        let [x, y] = match self.quarter_turns() {
+            1 => [v, 1.0 - u],
+            2 => [1.0 - u, 1.0 - v],
+            3 => [1.0 - v, u],
+            _ => [u, v],
+        };
+
+        let [cx, cy, cw, ch] = self.crop_rect();
+        [cx + x * cw, cy + y * ch]
+    }
+
+    /// `source_coords` as an affine map, the origin and the steps along u and v.
+    pub fn coords_map(&self) -> [[f32; 2]; 3] {
+        let origin = self.source_coords([0.0, 0.0]);
+        let u = self.source_coords([1.0, 0.0]);
+        let v = self.source_coords([0.0, 1.0]);
+        [
+            origin,
+            [u[0] - origin[0], u[1] - origin[1]],
+            [v[0] - origin[0], v[1] - origin[1]],
+        ]
+    }
+
+    /// Resample per pixel `data` of a source of `size` so it lines up with the transformed
+    /// source, nearest neighbour. Returns the new data and its size.
+    pub fn resample<T: Copy>(&self, data: &[T], size: [u32; 2]) -> (Vec<T>, [u32; 2]) {
+        let [width, height] = self.output_size(size);
+        let mut out = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let u = (x as f32 + 0.5) / width as f32;
+                let v = (y as f32 + 0.5) / height as f32;
+                let [sx, sy] = self.source_coords([u, v]);
+                let column = ((sx * size[0] as f32) as u32).min(size[0] - 1);
+                let row = ((sy * size[1] as f32) as u32).min(size[1] - 1);
+                out.push(data[(row * size[0] + column) as usize]);
+            }
+        }
+        (out, [width, height])
+    }
+
+    /// The coordinate of the transformed source showing a source texture coordinate,
+    /// the inverse of `source_coords`. Points cropped away end up outside 0 to 1.
+    pub fn output_coords(&self, [x, y]: [f32; 2]) -> [f32; 2] {
+        let [origin, u, v] = self.coords_map();
+        let (dx, dy) = (x - origin[0], y - origin[1]);
+        let det = u[0] * v[1] - u[1] * v[0];
+        [(dx * v[1] - dy * v[0]) / det, (u[0] * dy - u[1] * dx) / det]
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_source_coords() {
+    let identity = SourceTransform::default();
+    assert_eq!(identity.source_coords([0.25, 0.75]), [0.25, 0.75]);
+    assert_eq!(identity.output_size([640, 480]), [640, 480]);
+
+    let mirrored = SourceTransform::mirrored();
+    assert_eq!(mirrored.source_coords([0.0, 0.0]), [1.0, 0.0]);
+
+    // a quarter turn clockwise moves the top left corner to the top right
+    let rotated = SourceTransform {
+        rotation: 90,
+        ..SourceTransform::default()
+    };
+    assert_eq!(rotated.source_coords([1.0, 0.0]), [0.0, 0.0]);
+    assert_eq!(rotated.source_coords([0.0, 0.0]), [0.0, 1.0]);
+    assert_eq!(rotated.output_size([640, 480]), [480, 640]);
+
+    let cropped = SourceTransform {
+        crop: [0.5, 0.25, 0.5, 0.5],
+        ..SourceTransform::default()
+    };
+    assert_eq!(cropped.source_coords([1.0, 1.0]), [1.0, 0.75]);
+    assert_eq!(cropped.output_size([640, 480]), [320, 240]);
+    assert_eq!(cropped.coords_map(), [[0.5, 0.25], [0.5, 0.0], [0.0, 0.5]]);
+
+    // output coords undo source coords
+    assert_eq!(cropped.output_coords([1.0, 0.75]), [1.0, 1.0]);
+    assert_eq!(rotated.output_coords([0.0, 1.0]), [0.0, 0.0]);
+    assert_eq!(mirrored.output_coords([0.25, 0.5]), [0.75, 0.5]);
+
+    // resampling follows the transform
+    let data = [1, 2, 3, 4, 5, 6];
+    assert_eq!(identity.resample(&data, [3, 2]), (data.to_vec(), [3, 2]));
+    assert_eq!(
+        mirrored.resample(&data, [3, 2]),
+        (vec![3, 2, 1, 6, 5, 4], [3, 2])
+    );
+    assert_eq!(
+        rotated.resample(&data, [3, 2]),
+        (vec![4, 1, 5, 2, 6, 3], [2, 3])
+    );
+}
@@ -3,16 +3,32 @@ use tinyfiledialogs::open_file_dialog;
 
 use crate::app;
 use crate::programs::config;
+use crate::programs::uniforms::audio_source::{self, AudioSource};
 use crate::programs::uniforms::base::Bufferable;
+use crate::programs::uniforms::frame_source;
+use crate::programs::uniforms::image_sequence::ImageSequenceSettings;
+use crate::programs::uniforms::source_transform::SourceTransform;
+use crate::programs::uniforms::video_audio::VideoAudioSettings;
 use crate::programs::uniforms::video_capture::VideoCapture;
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct Data {
     pub video_size: Vector2,
+    /// Position of the current frame in seconds.
+    pub video_time: f32,
+    /// Position of the current frame relative to the duration.
+    pub video_progress: f32,
 }
 
 pub struct VideoUniforms {
+    pub audio_settings: VideoAudioSettings,
+    /// How folders and numbered images are played.
+    pub sequence: ImageSequenceSettings,
+    /// Whether frames follow the `time` uniform instead of playing freely.
+    pub sync: bool,
+    /// Applied to the current and future videos.
+    pub transform: SourceTransform,
     pub updated: bool,
     pub video_capture: Option<VideoCapture>,
     pub video_name: Option<String>,
@@ -27,7 +43,7 @@ impl Bufferable<Data> for VideoUniforms {
 
     fn textures(&self) -> Vec<&wgpu::Texture> {
         match &self.video_capture {
-            Some(capture) => vec![&capture.video_texture],
+            Some(capture) => vec![capture.texture()],
             None => vec![],
         }
     }
@@ -36,8 +52,14 @@ impl Bufferable<Data> for VideoUniforms {
 impl VideoUniforms {
     pub fn new() -> Self {
         Self {
+            audio_settings: VideoAudioSettings::default(),
+            sequence: ImageSequenceSettings::default(),
+            sync: false,
+            transform: SourceTransform::default(),
             data: Data {
                 video_size: pt2(0.0, 0.0),
+                video_time: 0.0,
+                video_progress: 0.0,
             },
             updated: false,
             video_capture: None,
@@ -46,12 +68,22 @@ impl VideoUniforms {
     }
 
     fn start_session(&mut self, device: &wgpu::Device, filepath: String, speed: f32) {
-        let capture =
-            opencv::videoio::VideoCapture::from_file(&filepath, opencv::videoio::CAP_ANY).unwrap();
+        let (capture, is_sequence) = match frame_source::open_file(&filepath, self.sequence) {
+            Ok(c) => c,
+            Err(e) => {
+                println!("Video error: {}", e);
+                return;
+            }
+        };
 
-        let video_capture = VideoCapture::new(device, capture, speed);
+        let mut video_capture = VideoCapture::new(device, capture, speed, self.transform);
+        if is_sequence {
+            video_capture.set_play_mode(self.sequence.play_mode);
+        } else {
+            video_capture.start_audio(&filepath, self.audio_settings);
+        }
 
-        self.data.video_size = video_capture.video_size;
+        self.data.video_size = video_capture.texture_size();
 
         self.video_capture = Some(video_capture);
 
@@ -66,6 +98,23 @@ impl VideoUniforms {
         device: &wgpu::Device,
         settings: &Option<config::ProgramSettings>,
     ) {
+        self.audio_settings = settings
+            .as_ref()
+            .and_then(|s| s.video_audio)
+            .unwrap_or_default();
+        self.sequence = settings
+            .as_ref()
+            .and_then(|s| s.video_sequence)
+            .unwrap_or_default();
+        self.sync = settings
+            .as_ref()
+            .and_then(|s| s.video_sync)
+            .unwrap_or(false);
+        self.transform = settings
+            .as_ref()
+            .and_then(|s| s.video_transform)
+            .unwrap_or_default();
+
         if let Some(cnfg) = settings {
             let project_path = app.project_path().expect("failed to locate `project_path`");
 
@@ -83,6 +132,11 @@ impl VideoUniforms {
                 }
 
                 self.start_session(device, video_path, speed);
+
+                if let (Some(capture), Some(region)) = (&mut self.video_capture, cnfg.video_loop) {
+                    capture.set_loop_region(Some(region));
+                    capture.seek(region.0);
+                }
             }
         }
     }
@@ -98,7 +152,13 @@ impl VideoUniforms {
         let filepath = match open_file_dialog(
             "Load Video",
             "~",
-            Some((&["*.mp4", "*.avi", "*.mov", "*.mpeg", "*.flv", "*.wmv"], "")),
+            Some((
+                &[
+                    "*.mp4", "*.avi", "*.mov", "*.mpeg", "*.flv", "*.wmv", "*.png", "*.exr",
+                    "*.tif", "*.tiff", "*.jpg",
+                ],
+                "",
+            )),
         ) {
             Some(filepath) => filepath,
             None => return,
@@ -115,9 +175,50 @@ impl VideoUniforms {
         println!("updated: {:?}", self.updated);
     }
 
-    pub fn update(&mut self) {
+    /// `time` is the render clock, used when the video is synced to it.
+    pub fn update(&mut self, device: &wgpu::Device, time: f32) {
+        if let Some(video_capture) = &mut self.video_capture {
+            if video_capture.set_transform(device, self.transform) {
+                self.data.video_size = video_capture.texture_size();
+                self.updated = true;
+            }
+            video_capture.update(device);
+            if self.sync {
+                video_capture.decode_at(time as f64);
+            }
+            self.data.video_time = video_capture.position() as f32;
+            self.data.video_progress = video_capture.progress() as f32;
+        }
+    }
+
+    pub fn set_sync(&mut self, sync: bool) {
+        self.sync = sync;
+        if sync {
+            return;
+        }
+
         if let Some(video_capture) = &mut self.video_capture {
-            video_capture.update();
+            video_capture.free_run();
+        }
+    }
+
+    /// Change how the soundtrack is used, applies to the current and future videos.
+    pub fn set_audio_settings(&mut self, settings: VideoAudioSettings) {
+        self.audio_settings = settings;
+        if let Some(video_capture) = &mut self.video_capture {
+            video_capture.set_audio_settings(settings);
+        }
+    }
+
+    /// Route the soundtrack to the audio source if it should be analyzed, and apply the
+    /// source's latency compensation to playback.
+    pub fn sync_audio(&mut self, audio_source: &mut AudioSource) {
+        match &mut self.video_capture {
+            Some(video_capture) => {
+                video_capture.set_audio_delay(audio_source::monitor_delay(audio_source.latency));
+                audio_source.set_feed(video_capture.audio_feed());
+            }
+            None => audio_source.set_feed(None),
         }
     }
 
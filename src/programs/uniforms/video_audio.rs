@@ -0,0 +1,458 @@
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::programs::uniforms::audio_source::AudioFeed;
+
+/// Decoded audio kept ahead of playback, in seconds.
+const BUFFER_TIME: f32 = 0.5;
+/// How far the soundtrack may drift from the video before decoding restarts at the video
+/// position, in seconds of the file.
+const SYNC_TOLERANCE: f64 = 0.15;
+/// Shortest time between two restarts, gives a restarted decoder time to catch up.
+const MIN_RESTART_INTERVAL: Duration = Duration::from_millis(500);
+/// Slowest tempo a single ffmpeg `atempo` filter accepts.
+const MIN_ATEMPO: f32 = 0.5;
+
+/// How the soundtrack of a video file is used.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoAudioSettings {
+    /// Play the soundtrack on the default output device.
+    pub play: bool,
+    /// Use the soundtrack as the audio source for all audio uniforms and inputs.
+    pub analyze: bool,
+    /// Playback volume, does not affect analysis.
+    pub volume: f32,
+}
+
+impl Default for VideoAudioSettings {
+    fn default() -> Self {
+        Self {
+            play: true,
+            analyze: false,
+            volume: 1.0,
+        }
+    }
+}
+
+impl VideoAudioSettings {
+    /// Whether the soundtrack needs to be decoded at all.
+    pub fn enabled(&self) -> bool {
+        self.play || self.analyze
+    }
+}
+
+/// ffmpeg filter that changes the tempo without changing the pitch.
+/// Speeds below what `atempo` accepts are reached by chaining filters.
+pub fn atempo_filter(speed: f32) -> String {
+    let mut speed = speed.max(0.01);
+    let mut filters = vec![];
+    while speed < MIN_ATEMPO {
+        filters.push(format!("atempo={}", MIN_ATEMPO));
+        speed /= MIN_ATEMPO;
+    }
+    filters.push(format!("atempo={}", speed));
+    filters.join(",")
+}
+
+/// State shared between the decoder thread, the output stream and the main thread.
+struct Playback {
+    /// Decoded interleaved samples waiting to be played.
+    buffer: VecDeque<f32>,
+    channels: usize,
+    /// Incremented on every restart, decoders of older generations stop.
+    generation: usize,
+    /// Position in the file where the current generation started, in seconds.
+    start: f64,
+    /// Frames played since the current generation started.
+    played: usize,
+    speed: f32,
+    paused: bool,
+    settings: VideoAudioSettings,
+    /// Monitor delay line for latency compensation.
+    delay: VecDeque<f32>,
+    delay_samples: usize,
+    feed: Option<Sender<Vec<f32>>>,
+    error: Option<String>,
+    /// Set when the file has no soundtrack or it can't be decoded, stops restarts.
+    stopped: bool,
+}
+
+impl Playback {
+    /// Output stream callback body. Plays decoded audio, sends it to the analysis feed and
+    /// delays the monitor output. Paused and starved streams produce silence.
+    fn fill(&mut self, data: &mut [f32]) {
+        let mut fresh = vec![0.0; data.len()];
+        if !self.paused {
+            let available = self.buffer.len().min(data.len()) / self.channels * self.channels;
+            for (f, s) in fresh.iter_mut().zip(self.buffer.drain(..available)) {
+                *f = s;
+            }
+            self.played += available / self.channels;
+        }
+
+        if let Some(feed) = &self.feed {
+            if feed.send(fresh.clone()).is_err() {
+                self.feed = None;
+            }
+        }
+
+        let gain = if self.settings.play {
+            self.settings.volume
+        } else {
+            0.0
+        };
+
+        // pad with silence when the delay grows, skip ahead when it shrinks
+        self.delay.extend(fresh);
+        let needed = self.delay_samples + data.len();
+        while self.delay.len() > needed {
+            self.delay.pop_front();
+        }
+        let silence = needed - self.delay.len();
+        for (i, out) in data.iter_mut().enumerate() {
+            *out = if i < silence {
+                0.0
+            } else {
+                self.delay.pop_front().unwrap_or(0.0) * gain
+            };
+        }
+    }
+}
+
+/// Decoder thread body, decodes the soundtrack from `start` with ffmpeg until the end of the
+/// file or until the playback is restarted.
+fn run_decoder(
+    path: String,
+    start: f64,
+    speed: f32,
+    sample_rate: u32,
+    generation: usize,
+    playback: Arc<Mutex<Playback>>,
+) {
+    let channels = playback.lock().unwrap().channels;
+    let max_buffered = (BUFFER_TIME * sample_rate as f32) as usize * channels;
+
+    let child = Command::new("ffmpeg")
+        .args(&["-hide_banner", "-loglevel", "error", "-nostdin"])
+        .args(&["-ss", &format!("{:.3}", start), "-i", &path, "-vn"])
+        .args(&["-af", &atempo_filter(speed)])
+        .args(&[
+            "-ac",
+            &channels.to_string(),
+            "-ar",
+            &sample_rate.to_string(),
+        ])
+        .args(&["-f", "f32le", "pipe:1"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+
+    let mut child = match child {
+        Ok(c) => c,
+        Err(e) => {
+            let mut p = playback.lock().unwrap();
+            p.error = Some(format!("Unable to run ffmpeg: {}", e));
+            p.stopped = true;
+            return;
+        }
+    };
+
+    let mut stdout = child.stdout.take().unwrap();
+    let mut bytes = vec![0_u8; 16384];
+    let mut pending: Vec<u8> = vec![];
+
+    'decode: loop {
+        let n = match stdout.read(&mut bytes) {
+            Ok(0) | Err(_) => break 'decode,
+            Ok(n) => n,
+        };
+
+        pending.extend_from_slice(&bytes[..n]);
+        let whole = pending.len() / 4 * 4;
+        let samples = pending[..whole]
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect::<Vec<f32>>();
+        pending.drain(..whole);
+
+        // wait for room in the buffer, the output stream sets the pace
+        loop {
+            let mut p = playback.lock().unwrap();
+            if p.generation != generation {
+                child.kill().ok();
+                child.wait().ok();
+                return;
+            }
+            if p.buffer.len() < max_buffered {
+                p.buffer.extend(samples);
+                break;
+            }
+            drop(p);
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    let success = child.wait().map_or(false, |s| s.success());
+    if success {
+        return;
+    }
+
+    let mut message = String::new();
+    if let Some(mut stderr) = child.stderr.take() {
+        stderr.read_to_string(&mut message).ok();
+    }
+
+    let mut p = playback.lock().unwrap();
+    if p.generation != generation {
+        return;
+    }
+
+    p.stopped = true;
+    // a video without a soundtrack just stays silent
+    if message.contains("does not contain any stream") {
+        println!("Video has no audio track: {}", path);
+    } else {
+        p.error = Some(format!("Error decoding video audio: {}", message.trim()));
+    }
+}
+
+/// Plays the soundtrack of a video file in sync with its `VideoCapture`, and optionally
+/// feeds it to an `AudioSource`.
+pub struct VideoAudio {
+    pub error: Option<String>,
+    pub settings: VideoAudioSettings,
+
+    feed: Option<AudioFeed>,
+    last_restart: Instant,
+    path: String,
+    playback: Arc<Mutex<Playback>>,
+    sample_rate: u32,
+    stream: Option<cpal::Stream>,
+}
+
+impl fmt::Debug for VideoAudio {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "VideoAudio({})", self.path)
+    }
+}
+
+impl VideoAudio {
+    pub fn new(path: &str, settings: VideoAudioSettings, speed: f32) -> Result<Self, String> {
+        let host = cpal::default_host();
+        let device = match host.default_output_device() {
+            Some(d) => d,
+            None => return Err(String::from("No audio output device available")),
+        };
+
+        let config = match device.default_output_config() {
+            Ok(c) => c,
+            Err(e) => return Err(format!("Error configuring audio output: {:?}", e)),
+        };
+        if config.sample_format() != cpal::SampleFormat::F32 {
+            return Err(String::from("Audio output does not support f32 samples"));
+        }
+
+        let cpal::SampleRate(sample_rate) = config.sample_rate();
+        let channels = config.channels().max(1) as usize;
+
+        let playback = Arc::new(Mutex::new(Playback {
+            buffer: VecDeque::new(),
+            channels,
+            generation: 0,
+            start: 0.0,
+            played: 0,
+            speed,
+            paused: false,
+            settings,
+            delay: VecDeque::new(),
+            delay_samples: 0,
+            feed: None,
+            error: None,
+            stopped: false,
+        }));
+
+        let callback_playback = playback.clone();
+        let stream = match device.build_output_stream(
+            &config.config(),
+            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                callback_playback.lock().unwrap().fill(data);
+            },
+            move |err| {
+                println!("Error playing video audio: {:?}", err);
+            },
+        ) {
+            Ok(s) => s,
+            Err(e) => return Err(format!("Error creating audio output: {:?}", e)),
+        };
+
+        if let Err(e) = stream.play() {
+            return Err(format!("Error starting audio output: {:?}", e));
+        }
+
+        let mut video_audio = Self {
+            error: None,
+            settings,
+            feed: None,
+            last_restart: Instant::now(),
+            path: String::from(path),
+            playback,
+            sample_rate,
+            stream: Some(stream),
+        };
+
+        video_audio.set_settings(settings);
+        video_audio.restart(0.0, speed);
+
+        Ok(video_audio)
+    }
+
+    /// Drop decoded audio and start decoding again from a position in seconds.
+    fn restart(&mut self, start: f64, speed: f32) {
+        let generation = {
+            let mut p = self.playback.lock().unwrap();
+            p.generation += 1;
+            p.buffer.clear();
+            p.start = start;
+            p.played = 0;
+            p.speed = speed;
+            p.generation
+        };
+
+        let path = self.path.clone();
+        let sample_rate = self.sample_rate;
+        let playback = self.playback.clone();
+        thread::spawn(move || run_decoder(path, start, speed, sample_rate, generation, playback));
+
+        self.last_restart = Instant::now();
+    }
+
+    /// Current playback position in the file, in seconds.
+    pub fn position(&self) -> f64 {
+        let p = self.playback.lock().unwrap();
+        p.start + p.played as f64 / self.sample_rate as f64 * p.speed as f64
+    }
+
+    /// Follow the video clock. Restarts decoding at the video position after a speed change,
+    /// a seek or a loop, or when the soundtrack has drifted too far.
+    pub fn sync(&mut self, video_position: f64, speed: f32) {
+        let speed_changed = {
+            let mut p = self.playback.lock().unwrap();
+            if let Some(error) = p.error.take() {
+                self.error = Some(error);
+            }
+            if p.stopped {
+                return;
+            }
+            (p.speed - speed).abs() > f32::EPSILON
+        };
+        let drift = (self.position() - video_position).abs();
+        if (speed_changed || drift > SYNC_TOLERANCE)
+            && self.last_restart.elapsed() >= MIN_RESTART_INTERVAL
+        {
+            self.restart(video_position, speed);
+        }
+    }
+
+    pub fn set_settings(&mut self, settings: VideoAudioSettings) {
+        self.settings = settings;
+
+        let mut p = self.playback.lock().unwrap();
+        p.settings = settings;
+
+        if settings.analyze && self.feed.is_none() {
+            let (feed, feed_tx) = AudioFeed::new(p.channels, self.sample_rate as f32);
+            self.feed = Some(feed);
+            p.feed = Some(feed_tx);
+        } else if !settings.analyze {
+            self.feed = None;
+            p.feed = None;
+        }
+    }
+
+    /// Delay the played sound, used for latency compensation.
+    pub fn set_delay(&mut self, delay: Duration) {
+        let mut p = self.playback.lock().unwrap();
+        let frames = (delay.as_secs_f64() * self.sample_rate as f64) as usize;
+        p.delay_samples = frames * p.channels;
+    }
+
+    /// The soundtrack as an audio source feed, if it should be analyzed.
+    pub fn feed(&self) -> Option<AudioFeed> {
+        self.feed.clone()
+    }
+
+    pub fn pause(&mut self) {
+        self.playback.lock().unwrap().paused = true;
+    }
+
+    pub fn unpause(&mut self) {
+        self.playback.lock().unwrap().paused = false;
+    }
+
+    pub fn end_session(&mut self) {
+        {
+            let mut p = self.playback.lock().unwrap();
+            p.generation += 1;
+            p.feed = None;
+        }
+
+        if let Some(stream) = self.stream.take() {
+            stream.pause().ok();
+        }
+
+        self.feed = None;
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_video_audio_playback() {
+    assert_eq!(atempo_filter(1.5), "atempo=1.5");
+    assert_eq!(atempo_filter(0.25), "atempo=0.5,atempo=0.5");
+
+    let mut playback = Playback {
+        buffer: (0..8).map(|i| i as f32).collect(),
+        channels: 2,
+        generation: 0,
+        start: 0.0,
+        played: 0,
+        speed: 1.0,
+        paused: false,
+        settings: VideoAudioSettings::default(),
+        delay: VecDeque::new(),
+        delay_samples: 2,
+        feed: None,
+        error: None,
+        stopped: false,
+    };
+
+    // the monitor output lags by one frame, the played position does not
+    let mut data = [0.0; 4];
+    playback.fill(&mut data);
+    assert_eq!(data, [0.0, 0.0, 0.0, 1.0]);
+    assert_eq!(playback.played, 2);
+
+    // paused and starved streams play silence without advancing
+    playback.paused = true;
+    playback.fill(&mut data);
+    assert_eq!(data, [2.0, 3.0, 0.0, 0.0]);
+    assert_eq!(playback.played, 2);
+
+    playback.paused = false;
+    playback.delay_samples = 0;
+    playback.fill(&mut data);
+    assert_eq!(data, [4.0, 5.0, 6.0, 7.0]);
+    playback.fill(&mut data);
+    assert_eq!(data, [0.0; 4]);
+    assert_eq!(playback.played, 4);
+}
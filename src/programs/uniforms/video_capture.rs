@@ -1,139 +1,617 @@
-use nannou::image;
 use nannou::prelude::*;
 use opencv::prelude::*;
-use ringbuf::{Consumer, RingBuffer};
+use ringbuf::{Consumer, Producer, RingBuffer};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::mpsc::{channel, Receiver, Sender};
-use std::time::SystemTime;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
 use std::{thread, time};
 
-use crate::util;
+use crate::programs::uniforms::audio_source::AudioFeed;
+use crate::programs::uniforms::frame_source::FrameSource;
+use crate::programs::uniforms::frame_upload::FrameUploader;
+use crate::programs::uniforms::source_transform::SourceTransform;
+use crate::programs::uniforms::video_audio::{VideoAudio, VideoAudioSettings};
 
 const FRAME_RATE: f64 = 30.0;
+/// Frames decoded ahead of the last requested one along the play order in synchronous mode.
+const DECODE_AHEAD: i64 = 8;
+/// Frames kept decoded behind the last requested one in synchronous mode, for small jumps back.
+const DECODE_BEHIND: i64 = 2;
+/// Gaps up to this many frames are read through instead of seeking.
+const MAX_READ_THROUGH: i64 = 30;
+/// How long to wait for a frame in synchronous mode before giving up.
+const DECODE_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+/// Frames of preloaded sources are kept on the GPU up to this many bytes.
+const GPU_FRAMES_BUDGET: usize = 2 * 1024 * 1024 * 1024;
+
+/// A decoded frame, 8-bit BGRA, or 16-bit float for sources with more precision.
+type FrameData = opencv::core::Mat;
+
+/// Work done with every frame a capture reads or decodes for `decode_at`, on the capture
+/// thread, like computer vision that doesn't fit into a shader.
+pub trait FrameAnalysis: Send {
+    /// Identifies the analysis, a capture runs each one once.
+    fn name(&self) -> &'static str;
+    /// `frame` is the 8-bit BGRA frame as captured, before the source transform.
+    fn analyze(&mut self, frame: &opencv::core::Mat);
+    /// Called before analyzing a frame that doesn't follow the one analyzed last, after a
+    /// seek or a jump in synchronous playback.
+    fn reset(&mut self) {}
+}
 
 enum Message {
+    /// Run an analysis on every frame read from now on, replacing one with the same name.
+    AddAnalysis(Box<dyn FrameAnalysis>),
     Close(()),
+    /// Decode the frame shown a number of steps into playback and send it back, in
+    /// synchronous mode.
+    Decode(i64),
     Pause(()),
+    /// Stop running the analysis with a name.
+    RemoveAnalysis(&'static str),
+    /// Seek to a time in seconds.
+    Seek(f64),
+    /// Seek to a frame index.
+    SeekFrame(i64),
+    /// Loop between an in and out point in seconds, or over the whole video.
+    SetLoop(Option<(f64, f64)>),
+    SetPlayMode(PlayMode),
     SetSpeed(f32),
+    /// Switch between free running and synchronous decoding.
+    SetSync(bool),
+    /// Move a number of frames from the current one, shown even while paused.
+    Step(i64),
     Unpause(()),
 }
 
-pub struct VideoConsumer {
-    consumer: Consumer<Vec<Vec<opencv::core::Vec3b>>>,
+/// Order in which the frames of a video file are played.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayMode {
+    Forward,
+    Reverse,
+    PingPong,
 }
 
-impl fmt::Debug for VideoConsumer {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "VideoConsumer")
+impl PlayMode {
+    pub const ALL: [PlayMode; 3] = [PlayMode::Forward, PlayMode::Reverse, PlayMode::PingPong];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PlayMode::Forward => "forward",
+            PlayMode::Reverse => "reverse",
+            PlayMode::PingPong => "ping-pong",
+        }
+    }
+}
+
+/// First and last frame of a loop region given in frames, or of the whole video.
+fn loop_range(frame_count: f64, region: Option<(f64, f64)>) -> (f64, f64) {
+    let last = (frame_count - 1.0).max(0.0);
+    match region {
+        Some((start, end)) => {
+            let start = start.round().max(0.0).min(last);
+            (start, end.round().max(start).min(last))
+        }
+        None => (0.0, last),
+    }
+}
+
+/// The frame shown `step` frames into playback of the range `start..=end`.
+fn play_order(step: i64, start: i64, end: i64, mode: PlayMode) -> i64 {
+    let len = end - start + 1;
+    if len <= 1 {
+        return start;
+    }
+
+    match mode {
+        PlayMode::Forward => start + step.rem_euclid(len),
+        PlayMode::Reverse => end - step.rem_euclid(len),
+        PlayMode::PingPong => {
+            let offset = step.rem_euclid(2 * (len - 1));
+            if offset < len {
+                start + offset
+            } else {
+                end - (offset - (len - 1))
+            }
+        }
+    }
+}
+
+/// Convert a frame as read from the capture to BGRA, reusing the memory of `bgra`.
+fn to_bgra(frame: &opencv::core::Mat, bgra: &mut FrameData) -> opencv::Result<()> {
+    let code = match frame.channels()? {
+        1 => opencv::imgproc::COLOR_GRAY2BGRA,
+        4 => return frame.copy_to(bgra),
+        _ => opencv::imgproc::COLOR_BGR2BGRA,
+    };
+    opencv::imgproc::cvt_color(frame, bgra, code, 0)
+}
+
+/// Whether `frame` is next to the frame analyzed last, in either direction.
+fn follows(frame: i64, last: Option<i64>) -> bool {
+    last.map_or(false, |last| (frame - last).abs() == 1)
+}
+
+/// Run the analyses on a frame, on an 8-bit copy of it when it has more precision.
+/// They are reset first when the frame isn't `continuous` with the one analyzed before it.
+fn analyze_frame(
+    analyses: &mut [Box<dyn FrameAnalysis>],
+    frame: &FrameData,
+    continuous: bool,
+    eight_bit: &mut opencv::core::Mat,
+) -> opencv::Result<()> {
+    if analyses.is_empty() {
+        return Ok(());
+    }
+    if !continuous {
+        for analysis in analyses.iter_mut() {
+            analysis.reset();
+        }
+    }
+
+    let frame = if frame.depth()? == opencv::core::CV_8U {
+        frame
+    } else {
+        // float frames are in 0 to 1
+        frame.convert_to(eight_bit, opencv::core::CV_8UC4, 255.0, 0.0)?;
+        &*eight_bit
+    };
+    for analysis in analyses.iter_mut() {
+        analysis.analyze(frame);
+    }
+    Ok(())
+}
+
+/// Decode a single frame in synchronous mode. `cursor` is the frame the capture reads next,
+/// `None` when unknown.
+fn decode_frame(
+    capture: &mut dyn FrameSource,
+    cursor: &mut Option<i64>,
+    frame: i64,
+    scratch: &mut opencv::core::Mat,
+) -> opencv::Result<FrameData> {
+    // short gaps are read through, seeking is slower and not exact with every codec
+    match *cursor {
+        Some(next) if next <= frame && frame - next <= MAX_READ_THROUGH => {
+            for _ in next..frame {
+                capture.skip_frame()?;
+            }
+        }
+        _ => {
+            capture.seek_frame(frame as f64)?;
+        }
+    }
+
+    let success = capture.read_frame(scratch)?;
+    *cursor = Some(frame + 1);
+
+    let mut bgra = opencv::core::Mat::default();
+    if success {
+        to_bgra(scratch, &mut bgra)?;
+    }
+
+    Ok(bgra)
+}
+
+/// Tracks which frame the capture thread reads next.
+/// Live sources have no frame count and are always read sequentially.
+#[derive(Debug)]
+struct Transport {
+    frame_count: f64,
+    /// Index of the last frame read.
+    current: f64,
+    /// Index of the next frame to read.
+    next: f64,
+    /// Loop region in frames.
+    region: Option<(f64, f64)>,
+    mode: PlayMode,
+    /// Whether playback currently moves backwards, in reverse or on the way back in ping-pong.
+    backwards: bool,
+}
+
+impl Transport {
+    fn new(frame_count: f64) -> Self {
+        Self {
+            frame_count,
+            current: 0.0,
+            next: 0.0,
+            region: None,
+            mode: PlayMode::Forward,
+            backwards: false,
+        }
+    }
+
+    fn seekable(&self) -> bool {
+        self.frame_count > 0.0
+    }
+
+    /// First and last frame of the loop region.
+    fn range(&self) -> (f64, f64) {
+        loop_range(self.frame_count, self.region)
+    }
+
+    /// The frame shown `step` frames into synchronous playback of the loop region.
+    fn frame_at(&self, step: i64) -> i64 {
+        let (start, end) = self.range();
+        play_order(step, start as i64, end as i64, self.mode)
+    }
+
+    /// Frames to keep decoded around `step` in synchronous mode.
+    fn decode_window(&self, step: i64) -> Vec<i64> {
+        (step - DECODE_BEHIND..=step + DECODE_AHEAD)
+            .map(|s| self.frame_at(s))
+            .collect()
+    }
+
+    /// The next frame to decode ahead of `step` along the play order in synchronous mode,
+    /// from the start before the first request. `None` when all of them are decoded.
+    fn decode_next(&self, step: Option<i64>, decoded: impl Fn(i64) -> bool) -> Option<i64> {
+        let first = step.map_or(0, |s| s + 1);
+        (first..first + DECODE_AHEAD)
+            .map(|s| self.frame_at(s))
+            .find(|frame| !decoded(*frame))
+    }
+
+    fn seek(&mut self, frame: f64) {
+        if self.seekable() {
+            let last = (self.frame_count - 1.0).max(0.0);
+            self.next = frame.round().max(0.0).min(last);
+        }
+    }
+
+    fn set_mode(&mut self, mode: PlayMode) {
+        self.mode = mode;
+        self.backwards = mode == PlayMode::Reverse;
+    }
+
+    /// Go back to the start of the loop region, or its end when playing backwards.
+    fn restart(&mut self) {
+        let (start, end) = self.range();
+        self.next = if self.backwards { end } else { start };
+    }
+
+    /// Pick the frame to read after the current one.
+    /// Returns whether the capture has to seek to it.
+    fn advance(&mut self) -> bool {
+        if !self.seekable() {
+            return false;
+        }
+
+        let (start, end) = self.range();
+        let step = if self.backwards { -1.0 } else { 1.0 };
+        let mut next = self.current + step;
+
+        if next < start || next > end {
+            next = match self.mode {
+                PlayMode::Forward => start,
+                PlayMode::Reverse => end,
+                PlayMode::PingPong => {
+                    self.backwards = !self.backwards;
+                    (self.current - step).max(start).min(end)
+                }
+            };
+        }
+
+        self.next = next;
+        // reading moves the capture one frame forward
+        next != self.current + 1.0
     }
 }
 
-fn float_as_bytes(data: &f32) -> [u8; 2] {
-    half::f16::from_f32(*data).to_ne_bytes()
+pub struct VideoConsumer {
+    consumer: Consumer<(i64, FrameData)>,
+    /// The frame currently shown.
+    frame: FrameData,
+    /// Index of the frame currently shown, -1 before the first one.
+    index: i64,
+    /// Frames that have been shown, handed back to the capture thread to decode into.
+    recycle: Producer<FrameData>,
 }
 
-fn floats_as_byte_vec(data: &[f32]) -> Vec<u8> {
-    let mut bytes = vec![];
-    data.iter()
-        .for_each(|f| bytes.extend(float_as_bytes(f).iter()));
-    bytes
+impl fmt::Debug for VideoConsumer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "VideoConsumer")
+    }
 }
 
 #[derive(Debug)]
 pub struct VideoCapture {
+    /// Length in seconds, 0 for live sources.
+    pub duration: f64,
     pub error: Option<String>,
+    pub frame_rate: f64,
+    /// Loop region in seconds, the whole video when `None`.
+    pub loop_region: Option<(f64, f64)>,
+    pub play_mode: PlayMode,
     pub running: bool,
     pub speed: f32,
+    /// Size of the frames as captured, before they are transformed.
     pub video_size: Vector2,
-    pub video_texture: wgpu::Texture,
 
+    /// Names of the analyses running on the capture thread.
+    analyses: Vec<&'static str>,
+    audio: Option<VideoAudio>,
+    audio_path: Option<String>,
     capture_thread: Option<std::thread::JoinHandle<()>>,
     message_channel_tx: Sender<Message>,
     error_channel_rx: Receiver<String>,
-    frame_data: Vec<Vec<opencv::core::Vec3b>>,
+    frame_channel_rx: Receiver<(i64, FrameData)>,
+    frame_count: f64,
+    /// Frames already uploaded, by index, when `keep_frames` is set.
+    gpu_frames: HashMap<i64, wgpu::Texture>,
+    keep_frames: bool,
+    paused: bool,
+    /// Position of the last frame read, seconds as `f64` bits.
+    position: Arc<AtomicU64>,
+    /// Whether frames are decoded on request instead of by the free running capture thread.
+    synced: bool,
+    /// Frame currently shown in synchronous mode.
+    synced_frame: Option<i64>,
+    /// Whether a frame wasn't decoded in time in synchronous mode.
+    decode_timed_out: bool,
+    /// Whether the frame shown was added to `gpu_frames` and still has to be uploaded.
+    upload_pending: bool,
+    uploader: FrameUploader,
     video_consumer: VideoConsumer,
 }
 
 impl VideoCapture {
     pub fn new(
         device: &wgpu::Device,
-        mut capture: opencv::videoio::VideoCapture,
+        mut capture: Box<dyn FrameSource>,
         speed: f32,
+        transform: SourceTransform,
     ) -> Self {
         // save size
-        let width = capture.get(opencv::videoio::CAP_PROP_FRAME_WIDTH).unwrap();
-        let height = capture.get(opencv::videoio::CAP_PROP_FRAME_HEIGHT).unwrap();
+        let (width, height) = capture.size();
         let video_size = pt2(width as f32, height as f32);
-        let mut frame_rate = FRAME_RATE;
-        if let Ok(fr) = capture.get(opencv::videoio::CAP_PROP_FPS) {
-            frame_rate = fr;
+        let mut frame_rate = capture.frame_rate();
+        if frame_rate <= 0.0 {
+            frame_rate = FRAME_RATE;
+        }
+        let frame_count = capture.frame_count();
+        let mut duration = 0.0;
+        if frame_count > 0.0 && frame_rate > 0.0 {
+            duration = frame_count / frame_rate;
         }
 
-        // create video texture
-        let video_texture = util::create_texture(
+        // frames are transformed into the video texture by the uploader
+        let uploader = FrameUploader::new(
             device,
             [width as u32, height as u32],
-            wgpu::TextureFormat::Rgba16Float,
+            capture.texture_format(),
+            transform,
         );
 
-        // setup ring buffer
-        let video_ring_buffer = RingBuffer::<Vec<Vec<opencv::core::Vec3b>>>::new(2);
+        // frames of preloaded sources stay on the GPU once shown, if they fit
+        let keep_frames = capture.preloaded()
+            && frame_count * uploader.frame_bytes() as f64 <= GPU_FRAMES_BUDGET as f64;
+        if capture.preloaded() && !keep_frames {
+            println!("Too many frames to keep on the GPU, uploading them as they are shown");
+        }
+
+        // setup ring buffers, shown frames go back to the capture thread to be reused
+        let video_ring_buffer = RingBuffer::<(i64, FrameData)>::new(2);
         let (mut video_producer, video_consumer) = video_ring_buffer.split();
+        let recycle_ring_buffer = RingBuffer::<FrameData>::new(4);
+        let (recycle_producer, mut recycle_consumer) = recycle_ring_buffer.split();
 
         // setup communication channels
         let (error_channel_tx, error_channel_rx) = channel();
         let (message_channel_tx, message_channel_rx) = channel();
+        let (frame_channel_tx, frame_channel_rx) = channel();
+
+        let position = Arc::new(AtomicU64::new(0.0_f64.to_bits()));
+        let capture_position = position.clone();
 
         // thread for reading from the capture
         let capture_thread = thread::spawn(move || {
             let clock = SystemTime::now();
             let mut video_speed = speed as f64;
+            let mut transport = Transport::new(frame_count);
+            let mut paused = false;
+            // whether the next frame can't be read sequentially
+            let mut seek = false;
+            // whether to read a frame even though playback is paused
+            let mut refresh = false;
+            // synchronous mode state
+            let mut sync = false;
+            let mut requested: Option<i64> = None;
+            let mut last_step: Option<i64> = None;
+            let mut last_requested = -1;
+            let mut cursor: Option<i64> = None;
+            let mut cache = BTreeMap::<i64, FrameData>::new();
+            // frames are read into the same memory every time
+            let mut scratch = opencv::core::Mat::default();
+            let mut analyses = Vec::<Box<dyn FrameAnalysis>>::new();
+            let mut eight_bit = opencv::core::Mat::default();
+            // index of the frame analyzed last
+            let mut last_analyzed: Option<i64> = None;
 
             'capture: loop {
-                // read from camera
-                let start_time = clock.elapsed().unwrap().as_secs_f64();
-                let mut frame = opencv::core::Mat::default();
-                match capture.read(&mut frame) {
-                    Ok(success) => {
-                        if !success {
-                            println!("No video frame available");
-                            capture
-                                .set(opencv::videoio::CAP_PROP_POS_FRAMES, 0.0)
-                                .unwrap();
-                        }
-                    }
-                    Err(e) => {
-                        println!("Error capturing video frame: {:?}", e);
-                        error_channel_tx.send(e.to_string()).unwrap();
-                        break 'capture;
-                    }
-                }
+                // block until there is something to do
+                let idle = if sync {
+                    requested.is_none()
+                        && transport
+                            .decode_next(last_step, |f| cache.contains_key(&f))
+                            .is_none()
+                } else {
+                    paused && !refresh
+                };
 
-                // get usable data
-                let data: Vec<Vec<opencv::core::Vec3b>> = frame.to_vec_2d().unwrap();
-                video_producer.push(data).ok();
+                let messages = if idle {
+                    match message_channel_rx.recv() {
+                        Ok(message) => vec![message],
+                        Err(_) => break 'capture,
+                    }
+                } else {
+                    message_channel_rx.try_iter().collect::<Vec<Message>>()
+                };
 
-                if let Ok(msg) = message_channel_rx.try_recv() {
-                    match msg {
+                for message in messages {
+                    match message {
+                        Message::AddAnalysis(analysis) => {
+                            analyses.retain(|a| a.name() != analysis.name());
+                            analyses.push(analysis);
+                        }
                         Message::Close(()) => {
                             // break from the outer loop
                             println!("Closing capture thread");
                             break 'capture;
                         }
-                        Message::Pause(()) => {
-                            // the stream has been paused, block it is unpaused
-                            'pause: for message in message_channel_rx.iter() {
-                                match message {
-                                    Message::Close(()) => break 'capture,
-                                    Message::SetSpeed(s) => video_speed = s as f64,
-                                    Message::Unpause(()) => break 'pause,
-                                    _ => (),
+                        Message::Decode(step) => requested = Some(step),
+                        Message::Pause(()) => paused = true,
+                        Message::RemoveAnalysis(name) => analyses.retain(|a| a.name() != name),
+                        Message::Seek(time) => {
+                            transport.seek(time * frame_rate);
+                            seek = true;
+                            refresh = true;
+                        }
+                        Message::SeekFrame(frame) => {
+                            transport.seek(frame as f64);
+                            seek = true;
+                            refresh = true;
+                        }
+                        Message::SetLoop(region) => {
+                            transport.region =
+                                region.map(|(start, end)| (start * frame_rate, end * frame_rate));
+                        }
+                        Message::SetPlayMode(mode) => transport.set_mode(mode),
+                        Message::SetSpeed(s) => video_speed = s as f64,
+                        Message::SetSync(s) => {
+                            sync = s;
+                            cursor = None;
+                            cache.clear();
+                            last_step = None;
+                            if !sync {
+                                // continue free running from the last decoded frame
+                                transport.seek(last_requested as f64);
+                                seek = true;
+                            }
+                        }
+                        Message::Step(frames) => {
+                            transport.seek(transport.current + frames as f64);
+                            seek = true;
+                            refresh = true;
+                        }
+                        Message::Unpause(()) => paused = false,
+                    }
+                }
+
+                if sync {
+                    let result = match requested.take() {
+                        Some(step) => {
+                            let frame = transport.frame_at(step);
+                            // only frames around the requested one along the play order are kept
+                            let window = transport.decode_window(step);
+                            cache.retain(|f, _| window.contains(f));
+                            last_step = Some(step);
+                            last_requested = frame;
+
+                            let decoded = if cache.contains_key(&frame) {
+                                Ok(())
+                            } else {
+                                decode_frame(capture.as_mut(), &mut cursor, frame, &mut scratch)
+                                    .map(|data| {
+                                        cache.insert(frame, data);
+                                    })
+                            };
+                            // the cache keeps its frame for when playback comes back to it
+                            let mut data = opencv::core::Mat::default();
+                            let continuous = follows(frame, last_analyzed);
+                            decoded
+                                .and_then(|_| cache[&frame].copy_to(&mut data))
+                                .and_then(|_| {
+                                    analyze_frame(&mut analyses, &data, continuous, &mut eight_bit)
+                                })
+                                .map(|_| {
+                                    last_analyzed = Some(frame);
+                                    let time = frame as f64 / frame_rate;
+                                    capture_position.store(time.to_bits(), Ordering::Relaxed);
+                                    frame_channel_tx.send((frame, data)).ok();
+                                })
+                        }
+                        None => {
+                            // decode ahead along the play order while there are no requests
+                            match transport.decode_next(last_step, |f| cache.contains_key(&f)) {
+                                Some(ahead) => {
+                                    decode_frame(capture.as_mut(), &mut cursor, ahead, &mut scratch)
+                                        .map(|data| {
+                                            cache.insert(ahead, data);
+                                        })
                                 }
+                                None => Ok(()),
                             }
                         }
-                        Message::SetSpeed(s) => video_speed = s as f64,
-                        Message::Unpause(()) => (),
+                    };
+
+                    if let Err(e) = result {
+                        println!("Error decoding video frame: {:?}", e);
+                        error_channel_tx.send(e.to_string()).unwrap();
+                        break 'capture;
                     }
+
+                    continue;
+                }
+
+                if paused && !refresh {
+                    continue;
+                }
+                refresh = false;
+
+                // read from camera
+                let start_time = clock.elapsed().unwrap().as_secs_f64();
+                if seek && transport.seekable() {
+                    capture.seek_frame(transport.next).ok();
+                }
+
+                // live sources are always read in order
+                let index = transport.next as i64;
+                let continuous = !transport.seekable() || follows(index, last_analyzed);
+                let result = capture.read_frame(&mut scratch).and_then(|success| {
+                    if !success {
+                        return Ok(None);
+                    }
+                    // convert into the memory of a frame that has already been shown
+                    let mut data = recycle_consumer
+                        .pop()
+                        .unwrap_or_else(opencv::core::Mat::default);
+                    to_bgra(&scratch, &mut data)?;
+                    analyze_frame(&mut analyses, &data, continuous, &mut eight_bit)?;
+                    Ok(Some(data))
+                });
+
+                match result {
+                    Ok(Some(data)) => {
+                        last_analyzed = Some(index);
+                        transport.current = transport.next;
+                        seek = transport.advance();
+
+                        let time = capture.position();
+                        capture_position.store(time.to_bits(), Ordering::Relaxed);
+
+                        video_producer.push((transport.current as i64, data)).ok();
+                    }
+                    Ok(None) => {
+                        println!("No video frame available");
+                        transport.restart();
+                        seek = true;
+                    }
+                    Err(e) => {
+                        println!("Error capturing video frame: {:?}", e);
+                        error_channel_tx.send(e.to_string()).unwrap();
+                        break 'capture;
+                    }
+                }
+
+                if paused {
+                    continue;
                 }
 
                 let frame_dur = 1.0_f64 / (frame_rate * video_speed);
@@ -148,22 +626,126 @@ impl VideoCapture {
         });
 
         Self {
+            analyses: vec![],
+            audio: None,
+            audio_path: None,
             capture_thread: Some(capture_thread),
             message_channel_tx,
+            duration,
             error: None,
             error_channel_rx,
-            frame_data: vec![],
+            frame_channel_rx,
+            frame_count,
+            frame_rate,
+            gpu_frames: HashMap::new(),
+            keep_frames,
+            loop_region: None,
+            paused: false,
+            play_mode: PlayMode::Forward,
+            position,
             running: true,
             speed,
+            synced: false,
+            synced_frame: None,
+            decode_timed_out: false,
+            upload_pending: false,
+            uploader,
             video_consumer: VideoConsumer {
                 consumer: video_consumer,
+                frame: opencv::core::Mat::default(),
+                index: -1,
+                recycle: recycle_producer,
             },
             video_size,
-            video_texture,
         }
     }
 
+    /// Run `analysis` on every frame from now on, replacing a running one with the same name.
+    pub fn add_analysis(&mut self, analysis: Box<dyn FrameAnalysis>) {
+        if !self.has_analysis(analysis.name()) {
+            self.analyses.push(analysis.name());
+        }
+        self.message_channel_tx
+            .send(Message::AddAnalysis(analysis))
+            .ok();
+    }
+
+    pub fn remove_analysis(&mut self, name: &'static str) {
+        self.analyses.retain(|a| *a != name);
+        self.message_channel_tx
+            .send(Message::RemoveAnalysis(name))
+            .ok();
+    }
+
+    pub fn has_analysis(&self, name: &str) -> bool {
+        self.analyses.iter().any(|a| *a == name)
+    }
+
+    /// Play the soundtrack of the video file at `path` along with the video.
+    pub fn start_audio(&mut self, path: &str, settings: VideoAudioSettings) {
+        self.audio_path = Some(String::from(path));
+        self.set_audio_settings(settings);
+    }
+
+    /// Change how the soundtrack is used, starting or stopping playback as needed.
+    pub fn set_audio_settings(&mut self, settings: VideoAudioSettings) {
+        let path = match &self.audio_path {
+            Some(p) => p.clone(),
+            None => return,
+        };
+
+        if !settings.enabled() {
+            if let Some(mut audio) = self.audio.take() {
+                audio.end_session();
+            }
+            return;
+        }
+
+        if let Some(audio) = &mut self.audio {
+            audio.set_settings(settings);
+            return;
+        }
+
+        match VideoAudio::new(&path, settings, self.speed) {
+            Ok(mut audio) => {
+                if self.paused {
+                    audio.pause();
+                }
+                self.audio = Some(audio);
+            }
+            Err(e) => {
+                println!("Video audio error: {}", e);
+                self.error = Some(e);
+            }
+        }
+    }
+
+    pub fn audio_settings(&self) -> Option<VideoAudioSettings> {
+        self.audio.as_ref().map(|a| a.settings)
+    }
+
+    /// The soundtrack as an audio source feed, if it should be analyzed.
+    pub fn audio_feed(&self) -> Option<AudioFeed> {
+        self.audio.as_ref().and_then(|a| a.feed())
+    }
+
+    /// Delay the played soundtrack, used for latency compensation.
+    pub fn set_audio_delay(&mut self, delay: time::Duration) {
+        if let Some(audio) = &mut self.audio {
+            audio.set_delay(delay);
+        }
+    }
+
+    /// Position of the most recent frame in seconds.
+    pub fn position(&self) -> f64 {
+        f64::from_bits(self.position.load(Ordering::Relaxed))
+    }
+
     pub fn end_session(&mut self) {
+        if let Some(mut audio) = self.audio.take() {
+            audio.end_session();
+        }
+
         if !self.running {
             return;
         }
@@ -176,7 +758,7 @@ impl VideoCapture {
         self.running = false;
     }
 
-    pub fn update(&mut self) {
+    pub fn update(&mut self, device: &wgpu::Device) {
         if !self.running {
             return;
         }
@@ -189,9 +771,33 @@ impl VideoCapture {
             return;
         }
 
-        let popped = self.video_consumer.consumer.pop();
-        if let Some(d) = popped {
-            self.frame_data = d;
+        if !self.synced {
+            let popped = self.video_consumer.consumer.pop();
+            if let Some((index, d)) = popped {
+                let shown = mem::replace(&mut self.video_consumer.frame, d);
+                self.video_consumer.index = index;
+                self.video_consumer.recycle.push(shown).ok();
+            }
+        }
+
+        // a frame seen for the first time gets a texture to keep it in
+        let index = self.video_consumer.index;
+        self.upload_pending =
+            self.keep_frames && index >= 0 && !self.gpu_frames.contains_key(&index);
+        if self.upload_pending {
+            self.gpu_frames
+                .insert(index, self.uploader.create_frame(device));
+        }
+
+        if !self.paused && self.play_mode == PlayMode::Forward {
+            let position = self.position();
+            if let Some(audio) = &mut self.audio {
+                audio.sync(position, self.speed);
+                if let Some(error) = audio.error.take() {
+                    println!("Video audio error: {}", error);
+                    self.error = Some(error);
+                }
+            }
         }
     }
 
@@ -200,40 +806,240 @@ impl VideoCapture {
             return;
         }
 
-        if self.frame_data.is_empty() || self.frame_data[0].is_empty() {
-            return;
+        // frames that don't match the source size are ignored
+        let bytes = match self.video_consumer.frame.data_bytes() {
+            Ok(bytes) if bytes.len() == self.uploader.frame_bytes() => bytes,
+            _ => return,
+        };
+
+        match self.gpu_frames.get(&self.video_consumer.index) {
+            Some(frame) => {
+                if self.upload_pending {
+                    frame.upload_data(device, encoder, bytes);
+                }
+                self.uploader.show(encoder, frame);
+            }
+            None => self.uploader.upload(device, encoder, bytes),
         }
+    }
 
-        let width = self.video_size.x as u32;
-        let height = self.video_size.y as u32;
+    /// The transformed frames.
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.uploader.texture
+    }
 
-        let image = image::ImageBuffer::from_fn(width, height, |x, y| {
-            let pixel = self.frame_data[y as usize][(width - x - 1) as usize];
-            // convert from BGR to RGB
-            image::Rgba([
-                pixel[2] as f32 / 255.0,
-                pixel[1] as f32 / 255.0,
-                pixel[0] as f32 / 255.0,
-                1.0,
-            ])
-        });
+    /// Size of the transformed frames.
+    pub fn texture_size(&self) -> Vector2 {
+        let [width, height] = self.uploader.texture.size();
+        pt2(width as f32, height as f32)
+    }
+
+    /// How frames are transformed.
+    pub fn transform(&self) -> SourceTransform {
+        self.uploader.transform
+    }
 
-        let flat_samples = image.as_flat_samples();
-        let byte_vec = floats_as_byte_vec(flat_samples.as_slice());
-        self.video_texture
-            .upload_data(device, encoder, &byte_vec[..]);
+    /// Change how frames are transformed, returns true when the texture was replaced.
+    pub fn set_transform(&mut self, device: &wgpu::Device, transform: SourceTransform) -> bool {
+        self.uploader.set_transform(device, transform)
     }
 
     pub fn pause(&mut self) {
+        self.paused = true;
         self.message_channel_tx.send(Message::Pause(())).ok();
+        if let Some(audio) = &mut self.audio {
+            audio.pause();
+        }
     }
 
     pub fn unpause(&mut self) {
+        self.paused = false;
         self.message_channel_tx.send(Message::Unpause(())).ok();
+        if self.play_mode == PlayMode::Forward {
+            if let Some(audio) = &mut self.audio {
+                audio.unpause();
+            }
+        }
     }
 
     pub fn set_speed(&mut self, speed: f32) {
         self.speed = speed;
         self.message_channel_tx.send(Message::SetSpeed(speed)).ok();
     }
+
+    /// Position of the most recent frame relative to the duration, 0 for live sources.
+    pub fn progress(&self) -> f64 {
+        if self.duration > 0.0 {
+            (self.position() / self.duration).min(1.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// Show exactly the frame at `time` seconds of the render clock, blocking until it is decoded.
+    /// Switches the capture thread from free running to synchronous decoding, so that renders
+    /// are reproducible frame for frame. Live sources can't be decoded on request. After a frame
+    /// timed out the one shown stays until `free_run`, instead of waiting for every following one.
+    pub fn decode_at(&mut self, time: f64) {
+        if !self.running || self.frame_count <= 0.0 || self.decode_timed_out {
+            return;
+        }
+
+        if !self.synced {
+            self.synced = true;
+            self.synced_frame = None;
+            self.message_channel_tx.send(Message::SetSync(true)).ok();
+        }
+
+        let region = self
+            .loop_region
+            .map(|(start, end)| (start * self.frame_rate, end * self.frame_rate));
+        let (start, end) = loop_range(self.frame_count, region);
+        // times that are whole frames apart shouldn't round down to the frame before
+        let step = (time.max(0.0) * self.speed as f64 * self.frame_rate + 1e-6).floor() as i64;
+        let frame = play_order(step, start as i64, end as i64, self.play_mode);
+
+        if self.synced_frame == Some(frame) {
+            return;
+        }
+
+        self.message_channel_tx.send(Message::Decode(step)).ok();
+
+        // skip frames that were requested before but arrive late
+        let deadline = Instant::now() + DECODE_TIMEOUT;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.frame_channel_rx.recv_timeout(timeout) {
+                Ok((decoded, data)) if decoded == frame => {
+                    self.video_consumer.frame = data;
+                    self.video_consumer.index = frame;
+                    self.synced_frame = Some(frame);
+                    return;
+                }
+                Ok(_) => (),
+                Err(_) => {
+                    self.error = Some(format!("Timed out decoding video frame {}", frame));
+                    self.decode_timed_out = true;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Go back to free running playback after `decode_at`.
+    pub fn free_run(&mut self) {
+        self.decode_timed_out = false;
+        if self.synced {
+            self.synced = false;
+
+            // frames read before synchronous decoding started would be shown out of order,
+            // and so would late answers to requests
+            while let Some((_, data)) = self.video_consumer.consumer.pop() {
+                self.video_consumer.recycle.push(data).ok();
+            }
+            self.frame_channel_rx.try_iter().for_each(drop);
+
+            self.message_channel_tx.send(Message::SetSync(false)).ok();
+        }
+    }
+
+    /// Jump to a time in seconds.
+    pub fn seek(&mut self, time: f64) {
+        self.message_channel_tx.send(Message::Seek(time)).ok();
+    }
+
+    /// Jump to a frame.
+    pub fn seek_frame(&mut self, frame: i64) {
+        self.message_channel_tx.send(Message::SeekFrame(frame)).ok();
+    }
+
+    /// Move a number of frames forward or backward, mostly useful while paused.
+    pub fn step(&mut self, frames: i64) {
+        self.message_channel_tx.send(Message::Step(frames)).ok();
+    }
+
+    /// Loop between an in and out point in seconds, or over the whole video with `None`.
+    pub fn set_loop_region(&mut self, region: Option<(f64, f64)>) {
+        self.loop_region = region;
+        self.message_channel_tx.send(Message::SetLoop(region)).ok();
+    }
+
+    /// The soundtrack only plays along forwards.
+    pub fn set_play_mode(&mut self, mode: PlayMode) {
+        self.play_mode = mode;
+        self.message_channel_tx
+            .send(Message::SetPlayMode(mode))
+            .ok();
+
+        if let Some(audio) = &mut self.audio {
+            if mode == PlayMode::Forward && !self.paused {
+                audio.unpause();
+            } else {
+                audio.pause();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_transport() {
+    let mut transport = Transport::new(10.0);
+    assert!(!transport.advance());
+    assert_eq!(transport.next, 1.0);
+
+    // forward playback wraps to the loop in point
+    transport.region = Some((2.0, 4.0));
+    transport.current = 4.0;
+    assert!(transport.advance());
+    assert_eq!(transport.next, 2.0);
+
+    // reverse playback always seeks
+    transport.set_mode(PlayMode::Reverse);
+    transport.current = 3.0;
+    assert!(transport.advance());
+    assert_eq!(transport.next, 2.0);
+    transport.current = 2.0;
+    transport.advance();
+    assert_eq!(transport.next, 4.0);
+
+    // ping-pong bounces off both ends
+    transport.set_mode(PlayMode::PingPong);
+    transport.current = 4.0;
+    transport.advance();
+    assert_eq!(transport.next, 3.0);
+    assert!(transport.backwards);
+    transport.current = 2.0;
+    assert!(!transport.advance());
+    assert_eq!(transport.next, 3.0);
+
+    // seeking is clamped to the video
+    transport.seek(25.0);
+    assert_eq!(transport.next, 9.0);
+
+    // synchronous decoding maps render time to frames the same way
+    assert_eq!(play_order(12, 2, 4, PlayMode::Forward), 2);
+    assert_eq!(play_order(1, 2, 4, PlayMode::Reverse), 3);
+    assert_eq!(play_order(3, 2, 4, PlayMode::PingPong), 3);
+    assert_eq!(play_order(4, 2, 4, PlayMode::PingPong), 2);
+    assert_eq!(play_order(-1, 0, 9, PlayMode::Forward), 9);
+    assert_eq!(loop_range(10.0, Some((8.0, 20.0))), (8.0, 9.0));
+
+    // decoding ahead follows the play order and keeps frames behind the requested one
+    let mut order = Transport::new(20.0);
+    order.set_mode(PlayMode::Reverse);
+    assert_eq!(order.decode_next(None, |_| false), Some(19));
+    assert_eq!(order.decode_next(Some(0), |f| f == 18), Some(17));
+    assert_eq!(order.decode_next(Some(0), |f| f >= 11), None);
+    assert_eq!(order.decode_window(3)[..3], [18, 17, 16]);
+
+    // analyses keep their state between frames played in order in either direction
+    assert!(follows(4, Some(5)) && follows(6, Some(5)));
+    assert!(!follows(0, Some(19)) && !follows(0, None));
+
+    // live sources are read sequentially
+    let mut live = Transport::new(-1.0);
+    live.seek(5.0);
+    assert!(!live.advance());
+    assert_eq!(live.next, 0.0);
 }
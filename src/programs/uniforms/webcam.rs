@@ -1,8 +1,34 @@
 use nannou::prelude::*;
-use opencv::prelude::*;
 
 use crate::programs::uniforms::base::Bufferable;
 use crate::programs::uniforms::video_capture::VideoCapture;
+use crate::programs::uniforms::webcam_devices::{self, WebcamSettings};
+
+/// Number of cameras a program can use at once.
+pub const MAX_WEBCAMS: usize = 4;
+
+/// The camera a uniform name refers to, `webcam` is the same as `webcam0`.
+pub fn parse_name(name: &str) -> Option<usize> {
+    let index = match name.strip_prefix("webcam")? {
+        "" => 0,
+        n => n.parse().ok()?,
+    };
+
+    if index < MAX_WEBCAMS {
+        Some(index)
+    } else {
+        None
+    }
+}
+
+/// Names the uniforms of a camera can be subscribed with.
+pub fn buffer_names(index: usize) -> Vec<String> {
+    let mut names = vec![format!("webcam{}", index)];
+    if index == 0 {
+        names.push(String::from("webcam"));
+    }
+    names
+}
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
@@ -11,6 +37,9 @@ pub struct Data {
 }
 
 pub struct WebcamUniforms {
+    /// Set when the webcam couldn't be opened.
+    pub error: Option<String>,
+    pub settings: WebcamSettings,
     pub updated: bool,
     pub video_capture: Option<VideoCapture>,
 
@@ -24,7 +53,7 @@ impl Bufferable<Data> for WebcamUniforms {
 
     fn textures(&self) -> Vec<&wgpu::Texture> {
         match &self.video_capture {
-            Some(capture) => vec![&capture.video_texture],
+            Some(capture) => vec![capture.texture()],
             None => vec![],
         }
     }
@@ -36,6 +65,8 @@ impl WebcamUniforms {
             data: Data {
                 video_size: pt2(0.0, 0.0),
             },
+            error: None,
+            settings: WebcamSettings::default(),
             updated: false,
             video_capture: None,
         }
@@ -50,15 +81,22 @@ impl WebcamUniforms {
             }
         }
 
-        let mut capture = opencv::videoio::VideoCapture::new(0, opencv::videoio::CAP_ANY).unwrap();
-        capture
-            .set(opencv::videoio::CAP_PROP_FRAME_WIDTH, size[0] as f64)
-            .ok();
-        capture
-            .set(opencv::videoio::CAP_PROP_FRAME_HEIGHT, size[1] as f64)
-            .ok();
+        let capture = match webcam_devices::open_webcam(&self.settings, size) {
+            Ok(c) => c,
+            Err(e) => {
+                println!("Webcam error: {}", e);
+                self.error = Some(e);
+                return;
+            }
+        };
 
-        self.video_capture = Some(VideoCapture::new(device, capture, 1.0));
+        self.error = None;
+        self.video_capture = Some(VideoCapture::new(
+            device,
+            Box::new(capture),
+            1.0,
+            self.settings.transform,
+        ));
 
         self.updated = true;
     }
@@ -67,6 +105,20 @@ impl WebcamUniforms {
         self.start_session(device, size);
     }
 
+    /// Switch to another device or mode, reopening the webcam if it changed.
+    pub fn set_settings(&mut self, device: &wgpu::Device, settings: &WebcamSettings, size: Point2) {
+        if *settings == self.settings {
+            return;
+        }
+
+        let reopen = !settings.same_capture(&self.settings);
+        self.settings = settings.clone();
+        if reopen {
+            self.end_session();
+            self.start_session(device, size);
+        }
+    }
+
     pub fn end_session(&mut self) {
         if let Some(video_capture) = &mut self.video_capture {
             video_capture.end_session();
@@ -74,10 +126,13 @@ impl WebcamUniforms {
         }
     }
 
-    pub fn update(&mut self) {
+    pub fn update(&mut self, device: &wgpu::Device) {
         if let Some(video_capture) = &mut self.video_capture {
-            video_capture.update();
-            self.data.video_size = video_capture.video_size;
+            if video_capture.set_transform(device, self.settings.transform) {
+                self.updated = true;
+            }
+            video_capture.update(device);
+            self.data.video_size = video_capture.texture_size();
         }
     }
 
@@ -99,3 +154,15 @@ impl WebcamUniforms {
         }
     }
 }
+
+#[cfg(test)]
+#[test]
+fn test_parse_name() {
+    assert_eq!(parse_name("webcam"), Some(0));
+    assert_eq!(parse_name("webcam0"), Some(0));
+    assert_eq!(parse_name("webcam3"), Some(3));
+    assert_eq!(parse_name("webcam4"), None);
+    assert_eq!(parse_name("webcams"), None);
+    assert_eq!(parse_name("video"), None);
+    assert_eq!(buffer_names(0), vec!["webcam0", "webcam"]);
+}
@@ -0,0 +1,286 @@
+use nannou::prelude::*;
+use opencv::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::process::Command;
+
+use crate::programs::uniforms::source_transform::SourceTransform;
+
+/// A capture mode supported by a webcam.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptureMode {
+    /// FourCC of the pixel format, e.g. `MJPG` or `YUYV`.
+    pub pixel_format: String,
+    pub width: u32,
+    pub height: u32,
+    pub fps: f32,
+}
+
+impl CaptureMode {
+    pub fn label(&self) -> String {
+        format!(
+            "{}x{} {} {}fps",
+            self.width, self.height, self.pixel_format, self.fps
+        )
+    }
+}
+
+/// A video capture device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebcamDevice {
+    /// Index as in `/dev/video<index>`, also the OpenCV device index.
+    pub index: i32,
+    pub name: String,
+    /// Supported modes, empty when they couldn't be queried.
+    pub modes: Vec<CaptureMode>,
+}
+
+impl WebcamDevice {
+    pub fn label(&self) -> String {
+        format!("{}: {}", self.index, self.name)
+    }
+}
+
+/// Which webcam to open and how. Unset values are left to the driver,
+/// except for the size which defaults to the window size.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebcamSettings {
+    /// Device index, as in `/dev/video<index>`.
+    pub device: i32,
+    /// Part of the device name, takes precedence over `device` when a device matches.
+    pub name: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub fps: Option<f32>,
+    /// FourCC of the pixel format, e.g. `MJPG` or `YUYV`.
+    pub pixel_format: Option<String>,
+    /// Applied to the frames, changing it doesn't reopen the webcam.
+    pub transform: SourceTransform,
+}
+
+impl Default for WebcamSettings {
+    fn default() -> Self {
+        Self {
+            device: 0,
+            name: None,
+            width: None,
+            height: None,
+            fps: None,
+            pixel_format: None,
+            transform: SourceTransform::mirrored(),
+        }
+    }
+}
+
+impl WebcamSettings {
+    /// Whether both settings open the same device in the same mode.
+    pub fn same_capture(&self, other: &Self) -> bool {
+        Self {
+            transform: other.transform,
+            ..self.clone()
+        } == *other
+    }
+
+    /// Whether the settings ask for exactly this mode.
+    pub fn uses_mode(&self, mode: &CaptureMode) -> bool {
+        self.width == Some(mode.width)
+            && self.height == Some(mode.height)
+            && self.fps == Some(mode.fps)
+            && self.pixel_format.as_deref() == Some(mode.pixel_format.as_str())
+    }
+
+    pub fn set_mode(&mut self, mode: Option<&CaptureMode>) {
+        self.width = mode.map(|m| m.width);
+        self.height = mode.map(|m| m.height);
+        self.fps = mode.map(|m| m.fps);
+        self.pixel_format = mode.map(|m| m.pixel_format.clone());
+    }
+}
+
+/// Parse the output of `v4l2-ctl --list-formats-ext` into capture modes.
+/// Only discrete sizes and intervals are listed.
+pub fn parse_formats(output: &str) -> Vec<CaptureMode> {
+    let format_re = Regex::new(r"\[\d+\]: '(\w+)'").unwrap();
+    let size_re = Regex::new(r"Size: Discrete (\d+)x(\d+)").unwrap();
+    let fps_re = Regex::new(r"Interval: Discrete [\d.]+s \(([\d.]+) fps\)").unwrap();
+
+    let mut modes = vec![];
+    let mut pixel_format = None;
+    let mut size = None;
+
+    for line in output.lines() {
+        if let Some(captures) = format_re.captures(line) {
+            pixel_format = Some(captures[1].to_string());
+            size = None;
+        } else if let Some(captures) = size_re.captures(line) {
+            size = Some((
+                captures[1].parse().unwrap_or(0),
+                captures[2].parse().unwrap_or(0),
+            ));
+        } else if let Some(captures) = fps_re.captures(line) {
+            if let (Some(format), Some((width, height))) = (&pixel_format, size) {
+                modes.push(CaptureMode {
+                    pixel_format: format.clone(),
+                    width,
+                    height,
+                    fps: captures[1].parse().unwrap_or(0.0),
+                });
+            }
+        }
+    }
+
+    modes
+}
+
+/// Query the modes of a device with `v4l2-ctl`, `None` if it isn't available.
+fn query_modes(index: i32) -> Option<Vec<CaptureMode>> {
+    let output = Command::new("v4l2-ctl")
+        .args(&["--list-formats-ext", "-d", &format!("/dev/video{}", index)])
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(parse_formats(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// List the `/dev/video*` devices that can capture video, sorted by index.
+/// Devices that report no capture formats, like the metadata nodes many webcams add, are skipped.
+pub fn list_devices() -> Vec<WebcamDevice> {
+    let entries = match fs::read_dir("/dev") {
+        Ok(e) => e,
+        Err(_) => return vec![],
+    };
+
+    let mut indices = entries
+        .filter_map(Result::ok)
+        .filter_map(|e| {
+            e.file_name()
+                .to_str()
+                .and_then(|n| n.strip_prefix("video"))
+                .and_then(|n| n.parse::<i32>().ok())
+        })
+        .collect::<Vec<i32>>();
+    indices.sort_unstable();
+
+    indices
+        .into_iter()
+        .filter_map(|index| {
+            let name = fs::read_to_string(format!("/sys/class/video4linux/video{}/name", index))
+                .map(|n| n.trim().to_string())
+                .unwrap_or_else(|_| format!("video{}", index));
+
+            let modes = match query_modes(index) {
+                Some(modes) if modes.is_empty() => return None,
+                Some(modes) => modes,
+                None => vec![],
+            };
+
+            Some(WebcamDevice { index, name, modes })
+        })
+        .collect()
+}
+
+/// The device index to open, looking the device up by name if one is given.
+fn resolve_device(settings: &WebcamSettings) -> i32 {
+    if let Some(name) = &settings.name {
+        let name = name.to_lowercase();
+        if let Some(device) = list_devices()
+            .iter()
+            .find(|d| d.name.to_lowercase().contains(&name))
+        {
+            return device.index;
+        }
+        println!(
+            "No webcam matching '{}', using device {}",
+            name, settings.device
+        );
+    }
+
+    settings.device
+}
+
+/// Pack a four character code the way OpenCV expects it.
+fn fourcc(code: &str) -> Option<i32> {
+    let bytes = code.as_bytes();
+    if bytes.len() != 4 {
+        return None;
+    }
+
+    Some(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Open a webcam and request the configured mode. The driver may pick a different one,
+/// the negotiated size and frame rate can be read back from the capture.
+pub fn open_webcam(
+    settings: &WebcamSettings,
+    size: Point2,
+) -> Result<opencv::videoio::VideoCapture, String> {
+    let index = resolve_device(settings);
+    let mut capture = match opencv::videoio::VideoCapture::new(index, opencv::videoio::CAP_ANY) {
+        Ok(c) => c,
+        Err(e) => return Err(format!("Error opening webcam {}: {}", index, e)),
+    };
+
+    if !capture.is_opened().unwrap_or(false) {
+        return Err(format!("Unable to open webcam {}", index));
+    }
+
+    // most drivers only offer larger sizes once the pixel format is set
+    if let Some(code) = settings.pixel_format.as_deref().and_then(fourcc) {
+        capture
+            .set(opencv::videoio::CAP_PROP_FOURCC, code as f64)
+            .ok();
+    }
+
+    let width = settings.width.map_or(size[0] as f64, |w| w as f64);
+    let height = settings.height.map_or(size[1] as f64, |h| h as f64);
+    capture
+        .set(opencv::videoio::CAP_PROP_FRAME_WIDTH, width)
+        .ok();
+    capture
+        .set(opencv::videoio::CAP_PROP_FRAME_HEIGHT, height)
+        .ok();
+
+    if let Some(fps) = settings.fps {
+        capture.set(opencv::videoio::CAP_PROP_FPS, fps as f64).ok();
+    }
+
+    Ok(capture)
+}
+
+#[cfg(test)]
+#[test]
+fn test_parse_formats() {
+    let output = "ioctl: VIDIOC_ENUM_FMT
+\tType: Video Capture
+
+\t[0]: 'MJPG' (Motion-JPEG, compressed)
+\t\tSize: Discrete 1920x1080
+\t\t\tInterval: Discrete 0.033s (30.000 fps)
+\t\t\tInterval: Discrete 0.017s (60.000 fps)
+\t\tSize: Discrete 1280x720
+\t\t\tInterval: Discrete 0.033s (30.000 fps)
+\t[1]: 'YUYV' (YUYV 4:2:2)
+\t\tSize: Discrete 640x480
+\t\t\tInterval: Discrete 0.033s (30.000 fps)
+";
+
+    let modes = parse_formats(output);
+    assert_eq!(modes.len(), 4);
+    assert_eq!(modes[1].label(), "1920x1080 MJPG 60fps");
+    assert_eq!(modes[3].pixel_format, "YUYV");
+    assert_eq!((modes[3].width, modes[3].height), (640, 480));
+
+    let mut settings = WebcamSettings::default();
+    settings.set_mode(Some(&modes[2]));
+    assert!(settings.uses_mode(&modes[2]));
+    assert!(!settings.uses_mode(&modes[0]));
+
+    assert_eq!(fourcc("MJPG"), Some(0x47504a4d));
+    assert_eq!(fourcc("MJPEG"), None);
+}
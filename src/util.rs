@@ -11,6 +11,8 @@ pub struct Vertex {
     pub position: [f32; 2],
 }
 
+/// Fragment outputs replace the target as is, so alpha stays straight instead of being
+/// multiplied into the color by blending.
 pub fn create_render_pipeline(
     device: &wgpu::Device,
     layout: &wgpu::PipelineLayout,
@@ -25,6 +27,8 @@ pub fn create_render_pipeline(
         .add_vertex_buffer::<Vertex>(&wgpu::vertex_attr_array![0 => Float2])
         .sample_count(sample_count)
         .primitive_topology(wgpu::PrimitiveTopology::TriangleStrip)
+        .color_blend(wgpu::BlendDescriptor::REPLACE)
+        .alpha_blend(wgpu::BlendDescriptor::REPLACE)
         .build(device)
 }
 